@@ -0,0 +1,61 @@
+//! Benchmarks of the hot paths of the instrumentation, with telemetry disabled.
+//!
+//! Performance budget, per operation on a recent x86-64 core:
+//!
+//! | Operation                                          | Budget |
+//! |----------------------------------------------------|--------|
+//! | span start and end through `tracing`               | 50 ns  |
+//! | counter add with 3 attributes                      | 50 ns  |
+//! | helper checking `is_enabled` first                 | 5 ns   |
+//!
+//! The budget of the enabled telemetry is in `benches/hot_paths.rs`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use myotel::middleware::{emit_access_log, AccessLogRequest, AccessLogResponse};
+use myotel::*;
+use std::hint::black_box;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+fn disabled(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    runtime.block_on(init_otel(InitConfig::disabled())).unwrap();
+
+    c.bench_function("disabled span start and end", |b| {
+        b.iter(|| info_span!("bench.span", user.id = black_box(42)).in_scope(|| {}))
+    });
+
+    let counter = meter("bench").u64_counter("bench.requests").init();
+    c.bench_function("disabled counter add with 3 attributes", |b| {
+        b.iter(|| {
+            counter.add(
+                black_box(1),
+                attrs![
+                    "http.request.method" => "GET",
+                    "http.route" => "/users/{id}",
+                    "http.response.status_code" => 200,
+                ],
+            )
+        })
+    });
+
+    c.bench_function("disabled feature flag", |b| {
+        b.iter(|| record_feature_flag("checkout-flow", black_box("classic"), "bench"))
+    });
+
+    let req = AccessLogRequest {
+        method: "GET".to_owned(),
+        path: "/users/42".to_owned(),
+        ..Default::default()
+    };
+    let resp = AccessLogResponse {
+        status: 200,
+        body_size: None,
+    };
+    c.bench_function("disabled access log", |b| {
+        b.iter(|| emit_access_log(&req, &resp, black_box(Duration::from_millis(3))))
+    });
+}
+
+criterion_group!(benches, disabled);
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+//! Benchmarks of the hot paths of the instrumentation, with telemetry enabled.
+//!
+//! The spans and log records are batched towards an unreachable OTLP endpoint, so that the
+//! benchmarks measure the instrumentation up to the batch queues rather than the exports,
+//! which fail and are dropped in the background.
+//!
+//! Performance budget, per operation on a recent x86-64 core:
+//!
+//! | Operation                                          | Budget |
+//! |----------------------------------------------------|--------|
+//! | span start and end through `tracing`               | 2 µs   |
+//! | log record emitted through the bridge              | 2 µs   |
+//! | counter add with 3 attributes                      | 300 ns |
+//! | context captured for another thread                | 200 ns |
+//!
+//! The budget of the disabled telemetry is in `benches/disabled.rs`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use myotel::thread::in_current_context;
+use myotel::*;
+use std::hint::black_box;
+use tokio::runtime::Runtime;
+
+const UNREACHABLE_ENDPOINT: &str = "http://127.0.0.1:9";
+
+fn init() -> Runtime {
+    let runtime = Runtime::new().unwrap();
+    runtime.block_on(async {
+        init_otel(
+            InitConfig::new()
+                .with_stdout_exporter(false)
+                .with_traces_endpoint(Some(UNREACHABLE_ENDPOINT.to_owned()))
+                .with_logs_endpoint(Some(UNREACHABLE_ENDPOINT.to_owned()))
+                .with_metrics_endpoint(Some(UNREACHABLE_ENDPOINT.to_owned()))
+                .with_batch_trace_config(Some(BatchTraceConfig::default()))
+                .with_batch_log_config(Some(BatchLogConfig::default()))
+                // Drops the spans beyond the queue without reporting each of them.
+                .with_span_queue_config(Some(SpanQueueConfig::default())),
+        )
+        .await
+        .unwrap();
+    });
+    runtime
+}
+
+fn hot_paths(c: &mut Criterion) {
+    let runtime = init();
+    let _guard = runtime.enter();
+
+    c.bench_function("span start and end", |b| {
+        b.iter(|| info_span!("bench.span", user.id = black_box(42)).in_scope(|| {}))
+    });
+
+    c.bench_function("log record through the bridge", |b| {
+        b.iter(|| info!(user.id = black_box(42), "bench log record"))
+    });
+
+    let counter = meter("bench").u64_counter("bench.requests").init();
+    c.bench_function("counter add with 3 attributes", |b| {
+        b.iter(|| {
+            counter.add(
+                black_box(1),
+                attrs![
+                    "http.request.method" => "GET",
+                    "http.route" => "/users/{id}",
+                    "http.response.status_code" => 200,
+                ],
+            )
+        })
+    });
+
+    let span = info_span!("bench.parent");
+    let _entered = span.enter();
+    c.bench_function("context captured for another thread", |b| {
+        b.iter(|| in_current_context(|| black_box(42))())
+    });
+}
+
+criterion_group!(benches, hot_paths);
+criterion_main!(benches);
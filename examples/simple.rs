@@ -27,13 +27,7 @@ async fn emit_span() {
         vec![KeyValue::new("event_attribute1", "event_value1")]
     );
     otel_span.add_link(
-        SpanContext::new(
-            TraceId::from_hex("58406520a006649127e371903a2de979").expect("invalid"),
-            SpanId::from_hex("b6d7d7f6d7d6d7f6").expect("invalid"),
-            TraceFlags::default(),
-            false,
-            TraceState::NONE
-        ),
+        traceparent_to_span_context("00-58406520a006649127e371903a2de979-b6d7d7f6d7d6d7f6-00").expect("invalid"),
         vec![
             KeyValue::new("link_attribute1", "link_value1"),
             KeyValue::new("link_attribute2", "link_value2")
@@ -41,13 +35,7 @@ async fn emit_span() {
     );
 
     otel_span.add_link(
-        SpanContext::new(
-            TraceId::from_hex("23401120a001249127e371903f2de971").expect("invalid"),
-            SpanId::from_hex("cd37d765d743d7f6").expect("invalid"),
-            TraceFlags::default(),
-            false,
-            TraceState::NONE
-        ),
+        traceparent_to_span_context("00-23401120a001249127e371903f2de971-cd37d765d743d7f6-00").expect("invalid"),
         vec![
             KeyValue::new("link_attribute1", "link_value1"),
             KeyValue::new("link_attribute2", "link_value2")
@@ -75,16 +63,16 @@ async fn emit_metrics() {
     let meter = meter_provider().meter("stdout-example");
     // let meter = meter("stdout-example");
     let c = meter.u64_counter("example_counter").init();
-    c.add(1, &[KeyValue::new("name", "apple"), KeyValue::new("color", "green")]);
-    c.add(1, &[KeyValue::new("name", "apple"), KeyValue::new("color", "green")]);
-    c.add(2, &[KeyValue::new("name", "apple"), KeyValue::new("color", "red")]);
-    c.add(1, &[KeyValue::new("name", "banana"), KeyValue::new("color", "yellow")]);
-    c.add(11, &[KeyValue::new("name", "banana"), KeyValue::new("color", "yellow")]);
+    c.add(1, attrs!["name" => "apple", "color" => "green"]);
+    c.add(1, attrs!["name" => "apple", "color" => "green"]);
+    c.add(2, attrs!["name" => "apple", "color" => "red"]);
+    c.add(1, attrs!["name" => "banana", "color" => "yellow"]);
+    c.add(11, attrs!["name" => "banana", "color" => "yellow"]);
 
     let h = meter.f64_histogram("example_histogram").init();
-    h.record(1.0, &[KeyValue::new("name", "apple"), KeyValue::new("color", "green")]);
-    h.record(1.0, &[KeyValue::new("name", "apple"), KeyValue::new("color", "green")]);
-    h.record(2.0, &[KeyValue::new("name", "apple"), KeyValue::new("color", "red")]);
-    h.record(1.0, &[KeyValue::new("name", "banana"), KeyValue::new("color", "yellow")]);
-    h.record(11.0, &[KeyValue::new("name", "banana"), KeyValue::new("color", "yellow")]);
+    h.record(1.0, attrs!["name" => "apple", "color" => "green"]);
+    h.record(1.0, attrs!["name" => "apple", "color" => "green"]);
+    h.record(2.0, attrs!["name" => "apple", "color" => "red"]);
+    h.record(1.0, attrs!["name" => "banana", "color" => "yellow"]);
+    h.record(11.0, attrs!["name" => "banana", "color" => "yellow"]);
 }
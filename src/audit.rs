@@ -0,0 +1,91 @@
+//! Audit log channel, separate from application logs.
+//!
+//! Audit records are emitted with the [`audit!`](crate::audit!) macro through a dedicated
+//! logger provider with its own exporter and processor, configured by [`AuditConfig`].
+//! They do not go through `tracing`, so the level filters do not apply to them.
+
+use crate::reconfigure::ReloadableLogExporter;
+use crate::{logs, BatchLogConfig};
+
+use opentelemetry::logs::{LogRecord as _, Logger as _, Severity};
+use opentelemetry_sdk::logs::LoggerProvider;
+use opentelemetry_sdk::Resource;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// The audit logger provider.
+pub(crate) static AUDIT_LOGGER_PROVIDER: OnceLock<LoggerProvider> = OnceLock::new();
+
+/// Emit an audit record of `actor` performing `action` on `resource`, with its `outcome`.
+///
+/// ```ignore
+/// audit!(user_id, "delete", format!("document/{doc_id}"), "success");
+/// ```
+#[macro_export]
+macro_rules! audit {
+    ($actor:expr, $action:expr, $resource:expr, $outcome:expr) => {
+        $crate::audit::record($actor, $action, $resource, $outcome)
+    };
+}
+
+/// Configuration of the audit log channel.
+#[derive(Debug, Default, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct AuditConfig {
+    /// The OTLP endpoint of audit records,
+    /// falling back to the `OTEL_EXPORTER_OTLP_*` environment variables.
+    otlp_endpoint: Option<String>,
+    /// If the batch log configuration is configured, batch reporting will be enabled.
+    batch_log_config: Option<BatchLogConfig>,
+}
+
+pub(crate) fn init_audit(
+    use_stdout_exporter: bool,
+    config: AuditConfig,
+    resource: Resource,
+) -> anyhow::Result<()> {
+    let exporter = ReloadableLogExporter::new(logs::build_log_exporter(
+        use_stdout_exporter,
+        config.otlp_endpoint.as_deref(),
+    )?);
+    let logger_provider =
+        logs::with_log_exporter(LoggerProvider::builder(), exporter, config.batch_log_config);
+    let _ = AUDIT_LOGGER_PROVIDER.set(logger_provider.with_resource(resource).build());
+    Ok(())
+}
+
+/// Emit an audit record, see [`audit!`](crate::audit!).
+/// Does nothing if OpenTelemetry is not initialized or disabled.
+pub fn record(
+    actor: impl Display,
+    action: impl Display,
+    resource: impl Display,
+    outcome: impl Display,
+) {
+    if !crate::is_enabled() {
+        return;
+    }
+    let Some(logger_provider) = AUDIT_LOGGER_PROVIDER.get() else {
+        return;
+    };
+    let logger = logs::scoped_logger(logger_provider, "audit");
+    let mut record = logger.create_log_record();
+    let (actor, action, resource, outcome) = (
+        actor.to_string(),
+        action.to_string(),
+        resource.to_string(),
+        outcome.to_string(),
+    );
+    record.set_event_name("audit");
+    record.set_target("audit");
+    record.set_severity_number(Severity::Info);
+    record.set_severity_text("INFO");
+    record.set_body(format!("{} {} {}: {}", actor, action, resource, outcome).into());
+    record.add_attributes([
+        ("audit.actor", actor),
+        ("audit.action", action),
+        ("audit.resource", resource),
+        ("audit.outcome", outcome),
+    ]);
+    logger.emit(record);
+}
@@ -0,0 +1,384 @@
+pub use opentelemetry::baggage::{Baggage, BaggageExt};
+
+use opentelemetry::baggage::KeyValueMetadata;
+use opentelemetry::propagation::{
+    text_map_propagator::FieldIter, Extractor, Injector, TextMapPropagator,
+};
+use opentelemetry::trace::{Span as _, TraceResult};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::propagation::BaggagePropagator;
+use opentelemetry_sdk::trace::{Span as SdkSpan, SpanProcessor};
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+/// A typed baggage entry key.
+///
+/// Values are stored as strings in the baggage of a [`Context`], and are propagated to
+/// downstream services by the `Baggage` propagator, subject to the baggage allowlist
+/// of [`InitConfig`](crate::InitConfig).
+///
+/// ```
+/// use myotel::BaggageKey;
+/// use opentelemetry::Context;
+///
+/// const USER_ID: BaggageKey<u64> = BaggageKey::new("user.id");
+///
+/// let cx = USER_ID.set_in(&Context::new(), 42);
+/// assert_eq!(USER_ID.get_from(&cx), Some(42));
+/// ```
+pub struct BaggageKey<T> {
+    name: &'static str,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> BaggageKey<T> {
+    /// Create a new BaggageKey named `name`.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The name of the baggage entry.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Return a clone of `cx` with the entry set to `value`.
+    pub fn set_in(&self, cx: &Context, value: T) -> Context
+    where
+        T: Display,
+    {
+        cx.with_baggage([KeyValue::new(self.name, value.to_string())])
+    }
+
+    /// Return a clone of the current context with the entry set to `value`.
+    pub fn set_in_current(&self, value: T) -> Context
+    where
+        T: Display,
+    {
+        self.set_in(&Context::current(), value)
+    }
+
+    /// Get the entry from `cx`, if present and parseable as `T`.
+    pub fn get_from(&self, cx: &Context) -> Option<T>
+    where
+        T: FromStr,
+    {
+        cx.baggage().get(self.name)?.as_str().parse().ok()
+    }
+
+    /// Get the entry from the current context, if present and parseable as `T`.
+    pub fn get_from_current(&self) -> Option<T>
+    where
+        T: FromStr,
+    {
+        self.get_from(&Context::current())
+    }
+}
+
+impl<T> Clone for BaggageKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for BaggageKey<T> {}
+
+impl<T> fmt::Debug for BaggageKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BaggageKey").field(&self.name).finish()
+    }
+}
+
+/// A baggage propagator that only injects and extracts the allowlisted entries,
+/// so that process-local entries do not cross process boundaries.
+#[derive(Debug)]
+pub struct AllowlistBaggagePropagator {
+    inner: BaggagePropagator,
+    allowlist: Vec<String>,
+}
+
+impl AllowlistBaggagePropagator {
+    /// Create a new AllowlistBaggagePropagator propagating the entries named in `allowlist`.
+    pub fn new(allowlist: Vec<String>) -> Self {
+        Self {
+            inner: BaggagePropagator::new(),
+            allowlist,
+        }
+    }
+
+    fn filtered(&self, baggage: &Baggage) -> Vec<KeyValueMetadata> {
+        entries(baggage, Some(&self.allowlist))
+    }
+}
+
+/// The entries of `baggage`, only the allowlisted ones if `allowlist` is set, sorted by key
+/// since the baggage does not keep their insertion order.
+fn entries(baggage: &Baggage, allowlist: Option<&[String]>) -> Vec<KeyValueMetadata> {
+    let mut entries: Vec<_> = baggage
+        .iter()
+        .filter(|(key, _)| {
+            allowlist.map_or(true, |allowlist| {
+                allowlist.iter().any(|allowed| allowed == key.as_str())
+            })
+        })
+        .map(|(key, (value, metadata))| {
+            KeyValueMetadata::new(key.clone(), value.clone(), metadata.clone())
+        })
+        .collect();
+    entries.sort_unstable_by(|a, b| a.key.as_str().cmp(b.key.as_str()));
+    entries
+}
+
+impl TextMapPropagator for AllowlistBaggagePropagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let cx = Context::new().with_baggage(self.filtered(cx.baggage()));
+        self.inner.inject_context(&cx, injector)
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        let extracted = self.inner.extract_with_context(&Context::new(), extractor);
+        let baggage = self.filtered(extracted.baggage());
+        if baggage.is_empty() {
+            cx.clone()
+        } else {
+            cx.with_baggage(baggage)
+        }
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        self.inner.fields()
+    }
+}
+
+/// Limits of the baggage injected by the `Baggage` propagator, following the W3C baggage
+/// specification, so that an oversized baggage cannot break downstream proxies,
+/// see [`InitConfig::with_baggage_limits`](crate::InitConfig::with_baggage_limits).
+///
+/// The lengths are counted before percent-encoding.
+#[derive(Debug, Clone, Copy, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct BaggageLimits {
+    /// The maximum number of entries. Defaults to 180.
+    max_entries: usize,
+    /// The maximum length of an entry, as `key=value;metadata`. Defaults to 4096.
+    max_pair_length: usize,
+    /// The maximum length of all entries, separators included. Defaults to 8192.
+    max_total_length: usize,
+    /// What to do with a baggage exceeding the limits. Defaults to truncating it.
+    policy: BaggageLimitPolicy,
+}
+
+impl Default for BaggageLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 180,
+            max_pair_length: 4096,
+            max_total_length: 8192,
+            policy: BaggageLimitPolicy::Truncate,
+        }
+    }
+}
+
+/// What to do with a baggage exceeding its [`BaggageLimits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BaggageLimitPolicy {
+    /// Drop the entries that do not fit within the limits, and keep the others, which are
+    /// considered in key order so that the same baggage is always truncated the same way.
+    #[default]
+    Truncate,
+    /// Drop the whole baggage.
+    Drop,
+    /// Drop the whole baggage, and fail; the propagator reports the error to the global
+    /// OpenTelemetry error handler.
+    Error,
+}
+
+impl BaggageLimits {
+    /// Create new BaggageLimits, with the defaults of the W3C baggage specification.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The entries of `baggage` to propagate according to the limits and their policy.
+    pub fn enforce(&self, baggage: &Baggage) -> anyhow::Result<Vec<KeyValueMetadata>> {
+        self.enforce_entries(entries(baggage, None))
+    }
+
+    fn enforce_entries(
+        &self,
+        entries: Vec<KeyValueMetadata>,
+    ) -> anyhow::Result<Vec<KeyValueMetadata>> {
+        let mut kept = Vec::new();
+        let mut total_length = 0;
+        let mut exceeded = false;
+        for entry in entries {
+            let metadata = entry.metadata.as_str();
+            let mut pair_length = entry.key.as_str().len() + 1 + entry.value.as_str().len();
+            if !metadata.is_empty() {
+                pair_length += 1 + metadata.len();
+            }
+            let separator_length = usize::from(!kept.is_empty());
+            if kept.len() >= self.max_entries
+                || pair_length > self.max_pair_length
+                || total_length + separator_length + pair_length > self.max_total_length
+            {
+                exceeded = true;
+                continue;
+            }
+            total_length += separator_length + pair_length;
+            kept.push(entry);
+        }
+        if !exceeded {
+            return Ok(kept);
+        }
+        match self.policy {
+            BaggageLimitPolicy::Truncate => Ok(kept),
+            BaggageLimitPolicy::Drop => Ok(Vec::new()),
+            BaggageLimitPolicy::Error => anyhow::bail!(
+                "the baggage exceeds its limits of {} entries, {} bytes per entry \
+                 and {} bytes in total",
+                self.max_entries,
+                self.max_pair_length,
+                self.max_total_length
+            ),
+        }
+    }
+}
+
+/// A baggage propagator enforcing [`BaggageLimits`] on the injected baggage,
+/// and propagating only the allowlisted entries if an allowlist is set.
+#[derive(Debug)]
+pub(crate) struct LimitedBaggagePropagator {
+    inner: BaggagePropagator,
+    allowlist: Option<Vec<String>>,
+    limits: BaggageLimits,
+}
+
+impl LimitedBaggagePropagator {
+    pub(crate) fn new(allowlist: Option<Vec<String>>, limits: BaggageLimits) -> Self {
+        Self {
+            inner: BaggagePropagator::new(),
+            allowlist,
+            limits,
+        }
+    }
+}
+
+impl TextMapPropagator for LimitedBaggagePropagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let entries = entries(cx.baggage(), self.allowlist.as_deref());
+        let entries = self.limits.enforce_entries(entries).unwrap_or_else(|err| {
+            global::handle_error(global::Error::Other(err.to_string()));
+            Vec::new()
+        });
+        self.inner
+            .inject_context(&Context::new().with_baggage(entries), injector)
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        let extracted = self.inner.extract_with_context(&Context::new(), extractor);
+        let baggage = entries(extracted.baggage(), self.allowlist.as_deref());
+        if baggage.is_empty() {
+            cx.clone()
+        } else {
+            cx.with_baggage(baggage)
+        }
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        self.inner.fields()
+    }
+}
+
+/// A span processor copying the allowlisted baggage entries of the parent context
+/// onto every started span, as attributes of the same name.
+#[derive(Debug)]
+pub struct BaggageSpanProcessor {
+    allowlist: Vec<String>,
+}
+
+impl BaggageSpanProcessor {
+    /// Create a new BaggageSpanProcessor copying the entries named in `allowlist`.
+    pub fn new(allowlist: Vec<String>) -> Self {
+        Self { allowlist }
+    }
+}
+
+impl SpanProcessor for BaggageSpanProcessor {
+    fn on_start(&self, span: &mut SdkSpan, cx: &Context) {
+        let baggage = cx.baggage();
+        for key in &self.allowlist {
+            if let Some(value) = baggage.get(key) {
+                span.set_attribute(KeyValue::new(key.clone(), value.clone()));
+            }
+        }
+    }
+
+    fn on_end(&self, _span: SpanData) {}
+
+    fn force_flush(&self) -> TraceResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeSet, HashMap};
+
+    fn baggage() -> Baggage {
+        ["e", "c", "a", "d", "b"]
+            .into_iter()
+            .map(|key| KeyValue::new(key, "1"))
+            .collect()
+    }
+
+    fn keys(entries: &[KeyValueMetadata]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.key.as_str()).collect()
+    }
+
+    #[test]
+    fn truncation_keeps_the_first_entries_by_key() {
+        let limits = BaggageLimits::new().with_max_entries(3);
+        for _ in 0..10 {
+            assert_eq!(keys(&limits.enforce(&baggage()).unwrap()), ["a", "b", "c"]);
+        }
+
+        // `a=1,b=1` is 7 bytes long, with the separator.
+        let limits = BaggageLimits::new().with_max_total_length(8);
+        assert_eq!(keys(&limits.enforce(&baggage()).unwrap()), ["a", "b"]);
+    }
+
+    #[test]
+    fn exceeded_limits_follow_the_policy() {
+        let limits = BaggageLimits::new().with_max_entries(3);
+        let dropped = limits.with_policy(BaggageLimitPolicy::Drop);
+        assert!(dropped.enforce(&baggage()).unwrap().is_empty());
+        let failed = limits.with_policy(BaggageLimitPolicy::Error);
+        assert!(failed.enforce(&baggage()).is_err());
+        // Within the limits, whatever the policy.
+        let within = BaggageLimits::new().with_policy(BaggageLimitPolicy::Error);
+        assert_eq!(within.enforce(&baggage()).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn propagator_injects_the_allowlisted_entries_within_the_limits() {
+        let allowlist = ["a", "c", "e"].map(str::to_owned).to_vec();
+        let limits = BaggageLimits::new().with_max_entries(2);
+        let propagator = LimitedBaggagePropagator::new(Some(allowlist), limits);
+        let cx = Context::new().with_baggage(baggage());
+        let mut headers: HashMap<String, String> = HashMap::new();
+        propagator.inject_context(&cx, &mut headers);
+        let injected: BTreeSet<_> = headers["baggage"].split(',').collect();
+        assert_eq!(injected, BTreeSet::from(["a=1", "c=1"]));
+    }
+}
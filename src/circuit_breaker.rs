@@ -0,0 +1,129 @@
+use opentelemetry::metrics::Gauge;
+use opentelemetry::trace::TraceContextExt as _;
+use opentelemetry::{Context, KeyValue};
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+
+const CIRCUIT_BREAKER_STATE_CHANGE: &str = "circuit_breaker.state_change";
+const CIRCUIT_BREAKER_NAME: &str = "circuit_breaker.name";
+const CIRCUIT_BREAKER_STATE: &str = "circuit_breaker.state";
+const CIRCUIT_BREAKER_PREVIOUS_STATE: &str = "circuit_breaker.previous_state";
+
+static STATE_GAUGE: OnceLock<Gauge<i64>> = OnceLock::new();
+
+/// The state of a circuit breaker, see [`CircuitBreakerObserver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CircuitState {
+    /// The calls go through. Recorded as 0 by the state gauge.
+    #[default]
+    Closed,
+    /// Some trial calls go through, to probe whether the callee recovered.
+    /// Recorded as 1 by the state gauge.
+    HalfOpen,
+    /// The calls are rejected. Recorded as 2 by the state gauge.
+    Open,
+}
+
+impl CircuitState {
+    /// The name of the state, e.g. `half_open`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::HalfOpen => "half_open",
+            CircuitState::Open => "open",
+        }
+    }
+
+    fn gauge_value(&self) -> i64 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::HalfOpen => 1,
+            CircuitState::Open => 2,
+        }
+    }
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Observes a circuit breaker, whichever crate implements it, so that all of them report
+/// the same telemetry: call [`CircuitBreakerObserver::on_state_change`] from the state change
+/// callback of the breaker.
+///
+/// Each state change is recorded as a `circuit_breaker.state_change` event of the current
+/// span, and into the `circuit_breaker.state` gauge (0 closed, 1 half-open, 2 open),
+/// both with the `circuit_breaker.name` attribute.
+///
+/// ```ignore
+/// let observer = CircuitBreakerObserver::new("payments-api");
+/// let breaker = Breaker::builder()
+///     .on_state_change(move |state| observer.on_state_change(match state {
+///         BreakerState::Closed => CircuitState::Closed,
+///         BreakerState::HalfOpen => CircuitState::HalfOpen,
+///         BreakerState::Open => CircuitState::Open,
+///     }))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerObserver {
+    name: Cow<'static, str>,
+    state: Arc<Mutex<CircuitState>>,
+}
+
+impl CircuitBreakerObserver {
+    /// Create an observer of the circuit breaker named `name`, which starts closed.
+    pub fn new(name: impl Into<Cow<'static, str>>) -> Self {
+        let observer = Self {
+            name: name.into(),
+            state: Arc::new(Mutex::new(CircuitState::Closed)),
+        };
+        observer.record_state(CircuitState::Closed);
+        observer
+    }
+
+    /// The name of the observed circuit breaker.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The last state of the circuit breaker.
+    pub fn state(&self) -> CircuitState {
+        *self.state.lock().unwrap()
+    }
+
+    /// Record that the circuit breaker changed to `state`.
+    /// Does nothing if it already was in `state`.
+    pub fn on_state_change(&self, state: CircuitState) {
+        let previous = std::mem::replace(&mut *self.state.lock().unwrap(), state);
+        if previous == state || !crate::is_enabled() {
+            return;
+        }
+        self.record_state(state);
+        Context::current().span().add_event(
+            CIRCUIT_BREAKER_STATE_CHANGE,
+            vec![
+                KeyValue::new(CIRCUIT_BREAKER_NAME, self.name.clone()),
+                KeyValue::new(CIRCUIT_BREAKER_PREVIOUS_STATE, previous.as_str()),
+                KeyValue::new(CIRCUIT_BREAKER_STATE, state.as_str()),
+            ],
+        );
+    }
+
+    fn record_state(&self, state: CircuitState) {
+        STATE_GAUGE
+            .get_or_init(|| {
+                crate::metrics::crate_meter()
+                    .i64_gauge(CIRCUIT_BREAKER_STATE)
+                    .with_description("State of the circuit breaker: 0 closed, 1 half-open, 2 open")
+                    .init()
+            })
+            .record(
+                state.gauge_value(),
+                &[KeyValue::new(CIRCUIT_BREAKER_NAME, self.name.clone())],
+            );
+    }
+}
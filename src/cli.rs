@@ -0,0 +1,161 @@
+//! Root traces for command-line programs.
+
+use crate::{init_otel, shutdown_all_providers, tracer_span, InitConfig};
+
+use futures_util::FutureExt as _;
+use opentelemetry::trace::{FutureExt as _, SpanBuilder, SpanKind, Status, TraceContextExt};
+use opentelemetry::{Array, Context, KeyValue, StringValue, Value};
+use opentelemetry_semantic_conventions::attribute::{
+    PROCESS_COMMAND_ARGS, PROCESS_EXECUTABLE_NAME, PROCESS_EXIT_CODE,
+};
+use std::any::Any;
+use std::future::Future;
+use std::panic::{resume_unwind, AssertUnwindSafe};
+use std::path::Path;
+
+/// The argument names whose values are redacted from the span of [`run_cli`].
+const SENSITIVE_ARGS: [&str; 6] = ["password", "passwd", "secret", "token", "key", "credential"];
+
+/// Initialize OpenTelemetry with simple exporters, and run `main` within the root span
+/// of a new trace named after the binary and its subcommand, with `process.*` attributes
+/// holding the arguments, the values of sensitive ones such as `--token` being redacted.
+///
+/// An error or a panic of `main` is recorded as the status of the span, along with
+/// the exit code it leads to, and the providers are shut down before returning or
+/// resuming the panic, so that nothing is lost when the process exits.
+/// Exiting with `std::process::exit` from `main` skips all of this.
+///
+/// The telemetry is not exported if OpenTelemetry fails to initialize, which is reported
+/// to the standard error.
+pub async fn run_cli<T, F, Fut>(init_config: InitConfig, main: F) -> anyhow::Result<T>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let init_config = init_config
+        .with_batch_log_config(None)
+        .with_batch_trace_config(None);
+    if let Err(err) = init_otel(init_config).await {
+        eprintln!("failed to initialize OpenTelemetry: {err:#}");
+    }
+
+    let args = sanitize_args(std::env::args());
+    let executable = args
+        .first()
+        .and_then(|program| Path::new(program).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match args.iter().skip(1).find(|arg| !arg.starts_with('-')) {
+        Some(subcommand) => format!("{executable} {subcommand}"),
+        None => executable.clone(),
+    };
+    let args: Vec<StringValue> = args.into_iter().map(Into::into).collect();
+    let builder = SpanBuilder::from_name(name)
+        .with_kind(SpanKind::Internal)
+        .with_attributes([
+            KeyValue::new(PROCESS_EXECUTABLE_NAME, executable),
+            KeyValue::new(PROCESS_COMMAND_ARGS, Value::Array(Array::String(args))),
+        ]);
+    let span = tracer_span(builder, Some(&Context::new()));
+    let cx = Context::current_with_span(span);
+
+    let result = AssertUnwindSafe(main().with_context(cx.clone()))
+        .catch_unwind()
+        .await;
+
+    let span = cx.span();
+    let exit_code: i64 = match &result {
+        Ok(Ok(_)) => 0,
+        Ok(Err(err)) => {
+            span.set_status(Status::error(format!("{err:#}")));
+            1
+        }
+        Err(panic) => {
+            span.set_status(Status::error(format!(
+                "panicked: {}",
+                panic_message(&**panic)
+            )));
+            101
+        }
+    };
+    span.set_attribute(KeyValue::new(PROCESS_EXIT_CODE, exit_code));
+    span.end();
+    shutdown_all_providers();
+
+    match result {
+        Ok(result) => result,
+        Err(panic) => resume_unwind(panic),
+    }
+}
+
+/// Run the body of an async `main` function with [`run_cli`], and the configuration
+/// of [`default_config`](crate::default_config).
+///
+/// ```ignore
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     myotel::instrument_main!(async {
+///         sync().await?;
+///         Ok(())
+///     })
+///     .await
+/// }
+/// ```
+#[macro_export]
+macro_rules! instrument_main {
+    ($body:expr) => {
+        $crate::cli::run_cli(
+            $crate::InitConfig::new()
+                .with_service_name(env!("CARGO_PKG_NAME").to_owned())
+                .with_service_version(env!("CARGO_PKG_VERSION").to_owned()),
+            || $body,
+        )
+    };
+}
+
+/// Replace the values of the sensitive arguments with `***`,
+/// whether given as `--token=value` or `--token value`.
+fn sanitize_args(args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut sanitized = Vec::new();
+    let mut redact_next = false;
+    for arg in args {
+        if std::mem::take(&mut redact_next) && !arg.starts_with('-') {
+            sanitized.push("***".to_owned());
+            continue;
+        }
+        let Some(flag) = arg.strip_prefix('-') else {
+            sanitized.push(arg);
+            continue;
+        };
+        let (flag, value) = match flag.split_once('=') {
+            Some((flag, value)) => (flag, Some(value)),
+            None => (flag, None),
+        };
+        let flag_lowercase = flag.to_ascii_lowercase();
+        if !SENSITIVE_ARGS
+            .iter()
+            .any(|name| flag_lowercase.contains(name))
+        {
+            sanitized.push(arg);
+            continue;
+        }
+        match value {
+            Some(_) => sanitized.push(format!("-{flag}=***")),
+            None => {
+                redact_next = true;
+                sanitized.push(arg);
+            }
+        }
+    }
+    sanitized
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
@@ -0,0 +1,443 @@
+//! Client spans and metrics for database queries and connection pools, following the OTel DB
+//! semantic conventions.
+
+use crate::{
+    register_observable_up_down_counter, tracer_span, Instant, ObservableHandle, TraceSpan,
+};
+
+use opentelemetry::metrics::Histogram;
+use opentelemetry::trace::{FutureExt as _, SpanBuilder, SpanKind, Status, TraceContextExt};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_semantic_conventions::attribute::{DB_OPERATION_NAME, DB_SYSTEM};
+use opentelemetry_semantic_conventions::metric::DB_CLIENT_OPERATION_DURATION;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+const DB_STATEMENT: &str = "db.statement";
+const DB_ROWS_AFFECTED: &str = "db.rows_affected";
+const DB_CLIENT_CONNECTIONS_USAGE: &str = "db.client.connections.usage";
+const DB_CLIENT_CONNECTIONS_MAX: &str = "db.client.connections.max";
+const POOL_NAME: &str = "pool.name";
+const STATE: &str = "state";
+
+static SANITIZE_STATEMENTS: AtomicBool = AtomicBool::new(true);
+
+static DURATION_HISTOGRAM: OnceLock<Histogram<f64>> = OnceLock::new();
+
+pub(crate) fn set_sanitize_statements(sanitize: bool) {
+    SANITIZE_STATEMENTS.store(sanitize, Ordering::Relaxed);
+}
+
+/// Run `query` within a client span with `db.system` and `db.statement` attributes,
+/// and record its latency into the `db.client.operation.duration` histogram.
+///
+/// ```ignore
+/// let user = traced_query("postgresql", sql, sqlx::query_as(sql).fetch_one(&pool)).await?;
+/// ```
+pub async fn traced_query<T, E, F>(system: &'static str, statement: &str, query: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    run(system, statement, query, |_| None).await
+}
+
+/// Like [`traced_query`], for statements resolving to the number of affected rows,
+/// which is recorded as the `db.rows_affected` attribute.
+///
+/// ```ignore
+/// traced_execute("postgresql", sql, async {
+///     sqlx::query(sql).execute(&pool).await.map(|r| r.rows_affected())
+/// })
+/// .await?;
+/// ```
+pub async fn traced_execute<E, F>(system: &'static str, statement: &str, query: F) -> Result<u64, E>
+where
+    F: Future<Output = Result<u64, E>>,
+    E: Display,
+{
+    run(system, statement, query, |rows| Some(*rows)).await
+}
+
+async fn run<T, E, F>(
+    system: &'static str,
+    statement: &str,
+    query: F,
+    rows_affected: impl FnOnce(&T) -> Option<u64>,
+) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    if !crate::is_enabled() {
+        return query.await;
+    }
+    let operation = operation_name(statement);
+    let span = start_db_span(system, statement, &operation);
+    let cx = Context::current_with_span(span);
+
+    let start = Instant::now();
+    let result = query.with_context(cx.clone()).await;
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let span = cx.span();
+    match &result {
+        Ok(value) => {
+            if let Some(rows) = rows_affected(value) {
+                span.set_attribute(KeyValue::new(DB_ROWS_AFFECTED, rows as i64));
+            }
+        }
+        Err(err) => span.set_status(Status::error(err.to_string())),
+    }
+    span.end();
+
+    DURATION_HISTOGRAM
+        .get_or_init(|| {
+            crate::metrics::crate_meter()
+                .f64_histogram(DB_CLIENT_OPERATION_DURATION)
+                .with_unit("s")
+                .with_description("Duration of database client operations")
+                .init()
+        })
+        .record(
+            elapsed,
+            &[
+                KeyValue::new(DB_SYSTEM, system),
+                KeyValue::new(DB_OPERATION_NAME, operation),
+            ],
+        );
+    result
+}
+
+/// Run a sqlx query on an executor within a client span with the `db.system`, `db.statement`
+/// and `db.operation.name` attributes, and record its latency into the
+/// `db.client.operation.duration` histogram, see [`traced_query()`].
+///
+/// Without method, the query is executed and the number of affected rows is returned and
+/// recorded as the `db.rows_affected` attribute. Otherwise the query is run with the given
+/// method, e.g. `fetch_one`, and its result returned. To be used within an async context.
+///
+/// ```ignore
+/// let rows = traced_query!(&pool, sqlx::query("DELETE FROM users WHERE id = $1").bind(id))?;
+/// let query = sqlx::query_as("SELECT * FROM users WHERE id = $1").bind(id);
+/// let user: User = traced_query!(&pool, query, fetch_one)?;
+/// ```
+#[cfg(feature = "sqlx")]
+#[macro_export]
+macro_rules! traced_query {
+    ($executor:expr, $query:expr $(,)?) => {{
+        let (executor, query) = ($executor, $query);
+        let system = $crate::db::DbSpanExt::db_system(&query);
+        let statement = $crate::db::DbSpanExt::db_statement(&query);
+        $crate::db::traced_execute(system, statement, async move {
+            query
+                .execute(executor)
+                .await
+                .map(|result| result.rows_affected())
+        })
+        .await
+    }};
+    ($executor:expr, $query:expr, $method:ident $(,)?) => {{
+        let query = $query;
+        let system = $crate::db::DbSpanExt::db_system(&query);
+        let statement = $crate::db::DbSpanExt::db_statement(&query);
+        $crate::db::traced_query(system, statement, query.$method($executor)).await
+    }};
+}
+
+/// The database attributes of a sqlx query, for [`traced_query!`](crate::traced_query).
+#[cfg(feature = "sqlx")]
+pub trait DbSpanExt<'q, DB: sqlx::Database>: sqlx::Execute<'q, DB> {
+    /// The `db.system` of the database of the query, e.g. `postgresql`.
+    fn db_system(&self) -> &'static str {
+        match DB::NAME {
+            "PostgreSQL" => "postgresql",
+            "MySQL" => "mysql",
+            "SQLite" => "sqlite",
+            "MSSQL" => "mssql",
+            _ => "other_sql",
+        }
+    }
+
+    /// The statement of the query, sanitized when recorded unless disabled with
+    /// [`InitConfig::with_sanitize_db_statements`](crate::InitConfig::with_sanitize_db_statements).
+    fn db_statement(&self) -> &'q str {
+        self.sql()
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl<'q, DB: sqlx::Database, Q: sqlx::Execute<'q, DB>> DbSpanExt<'q, DB> for Q {}
+
+fn start_db_span(system: &'static str, statement: &str, operation: &str) -> TraceSpan {
+    let statement = if SANITIZE_STATEMENTS.load(Ordering::Relaxed) {
+        sanitize_statement(statement)
+    } else {
+        statement.to_owned()
+    };
+    let name = if operation.is_empty() {
+        system.to_owned()
+    } else {
+        operation.to_owned()
+    };
+    let builder = SpanBuilder::from_name(name)
+        .with_kind(SpanKind::Client)
+        .with_attributes([
+            KeyValue::new(DB_SYSTEM, system),
+            KeyValue::new(DB_STATEMENT, statement),
+            KeyValue::new(DB_OPERATION_NAME, operation.to_owned()),
+        ]);
+    tracer_span(builder, Some(&Context::current()))
+}
+
+/// Observable `db.client.connections.*` metrics of a connection pool, read from callbacks
+/// at each collection, with a `pool.name` attribute:
+/// - `db.client.connections.usage`: the used and idle connections, by `state`;
+/// - `db.client.connections.max`: the size of the pool.
+///
+/// The metrics stop being observed when it is dropped.
+///
+/// ```ignore
+/// let (size_pool, used_pool, idle_pool) = (pool.clone(), pool.clone(), pool.clone());
+/// let _pool_metrics = PoolMetrics::register(
+///     "main",
+///     move || size_pool.options().get_max_connections() as usize,
+///     move || (used_pool.size() as usize).saturating_sub(used_pool.num_idle()),
+///     move || idle_pool.num_idle(),
+/// );
+/// ```
+#[derive(Debug)]
+#[must_use = "dropping the pool metrics stops observing them"]
+pub struct PoolMetrics {
+    _usage: ObservableHandle,
+    _max: ObservableHandle,
+}
+
+impl PoolMetrics {
+    /// Register the metrics of the pool named `pool_name`, whose `size`, `in_use` and `idle`
+    /// callbacks return its maximum, used and idle connection counts.
+    pub fn register(
+        pool_name: impl Into<String>,
+        size: impl Fn() -> usize + Send + Sync + 'static,
+        in_use: impl Fn() -> usize + Send + Sync + 'static,
+        idle: impl Fn() -> usize + Send + Sync + 'static,
+    ) -> Self {
+        let pool_name = KeyValue::new(POOL_NAME, pool_name.into());
+        let usage_attributes = [
+            [pool_name.clone(), KeyValue::new(STATE, "used")],
+            [pool_name.clone(), KeyValue::new(STATE, "idle")],
+        ];
+        let usage = register_observable_up_down_counter(
+            DB_CLIENT_CONNECTIONS_USAGE,
+            "{connection}",
+            "The number of connections that are currently in the state described by the state attribute",
+            move |counter| {
+                counter.observe(in_use() as i64, &usage_attributes[0]);
+                counter.observe(idle() as i64, &usage_attributes[1]);
+            },
+        );
+        let max = register_observable_up_down_counter(
+            DB_CLIENT_CONNECTIONS_MAX,
+            "{connection}",
+            "The maximum number of open connections allowed",
+            move |counter| counter.observe(size() as i64, std::slice::from_ref(&pool_name)),
+        );
+        Self {
+            _usage: usage,
+            _max: max,
+        }
+    }
+}
+
+/// The first keyword of the statement, upper-cased (e.g. `SELECT`).
+fn operation_name(statement: &str) -> String {
+    statement
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase()
+}
+
+/// Replace the literals of a SQL statement with `?`, so that no parameter values end up in
+/// telemetry:
+/// - the strings quoted with `'` or `"`, with `''` or backslash escapes and an optional `E`,
+///   `N`, `X` or `B` prefix; the identifiers quoted with `"` are then replaced too,
+/// - the dollar-quoted strings of Postgres, `$$...$$` or `$tag$...$tag$`,
+/// - the numbers, with their sign.
+///
+/// A backslash always escapes the next character, and an unterminated literal runs to the end
+/// of the statement, so that an ambiguous statement is over-sanitized rather than leaking.
+/// Comments are kept as they are.
+pub fn sanitize_statement(statement: &str) -> String {
+    let chars: Vec<char> = statement.chars().collect();
+    let mut sanitized = String::with_capacity(statement.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let prev_is_ident = sanitized.chars().next_back().is_some_and(is_ident_char);
+        let next = chars.get(i + 1).copied();
+        if c == '$' && !prev_is_ident {
+            if let Some(tag_len) = dollar_tag_len(&chars[i..]) {
+                i = skip_dollar_quoted(&chars, i, tag_len);
+                sanitized.push('?');
+                continue;
+            }
+        }
+        match c {
+            '\'' | '"' => {
+                i = skip_quoted(&chars, i);
+                sanitized.push('?');
+            }
+            'E' | 'e' | 'N' | 'n' | 'X' | 'x' | 'B' | 'b'
+                if !prev_is_ident && next == Some('\'') =>
+            {
+                i = skip_quoted(&chars, i + 1);
+                sanitized.push('?');
+            }
+            '-' if next.is_some_and(|c| c.is_ascii_digit()) && !follows_operand(&sanitized) => {
+                i = skip_number(&chars, i + 1);
+                sanitized.push('?');
+            }
+            c if c.is_ascii_digit() && !prev_is_ident => {
+                i = skip_number(&chars, i);
+                sanitized.push('?');
+            }
+            c => {
+                sanitized.push(c);
+                i += 1;
+            }
+        }
+    }
+    sanitized
+}
+
+/// The keywords after which `-` is the sign of a number rather than the binary minus.
+const SIGN_KEYWORDS: &[&str] = &[
+    "AND", "AS", "BETWEEN", "BY", "CASE", "DEFAULT", "ELSE", "HAVING", "IN", "INTERVAL", "IS",
+    "LIKE", "LIMIT", "NOT", "OFFSET", "ON", "OR", "RETURN", "SELECT", "SET", "THEN", "VALUES",
+    "WHEN", "WHERE",
+];
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Whether the sanitized statement ends with an operand, after which `-` is the binary minus.
+fn follows_operand(sanitized: &str) -> bool {
+    let trimmed = sanitized.trim_end();
+    match trimmed.chars().next_back() {
+        Some(')' | ']' | '?' | '`') => true,
+        Some(c) if is_ident_char(c) => {
+            let word = &trimmed[trimmed.trim_end_matches(is_ident_char).len()..];
+            !SIGN_KEYWORDS
+                .iter()
+                .any(|keyword| keyword.eq_ignore_ascii_case(word))
+        }
+        _ => false,
+    }
+}
+
+/// The index after the literal opened by the quote at `open`, honoring doubled quotes and
+/// backslash escapes, or the end of the statement if unterminated.
+fn skip_quoted(chars: &[char], open: usize) -> usize {
+    let quote = chars[open];
+    let mut i = open + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            c if c == quote && chars.get(i + 1) == Some(&quote) => i += 2,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// The index after the number starting at `start`, with its fraction and exponent.
+fn skip_number(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while let Some(c) = chars.get(i) {
+        if matches!(c, 'e' | 'E') && matches!(chars.get(i + 1), Some('+' | '-')) {
+            i += 2;
+        } else if c.is_ascii_alphanumeric() || *c == '.' {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+/// The index after the dollar-quoted string opened by the `$tag$` of `tag_len` at `open`,
+/// or the end of the statement if unterminated.
+fn skip_dollar_quoted(chars: &[char], open: usize, tag_len: usize) -> usize {
+    let tag = &chars[open..open + tag_len];
+    let body = open + tag_len;
+    chars[body..]
+        .windows(tag_len)
+        .position(|window| window == tag)
+        .map_or(chars.len(), |end| body + end + tag_len)
+}
+
+/// The length of the `$tag$` opening a dollar-quoted string at the start of `chars`, if any.
+fn dollar_tag_len(chars: &[char]) -> Option<usize> {
+    let tag_len = chars[1..].iter().position(|c| *c == '$')?;
+    let tag = &chars[1..1 + tag_len];
+    let valid = !tag.first().is_some_and(char::is_ascii_digit)
+        && tag.iter().all(|c| c.is_alphanumeric() || *c == '_');
+    valid.then_some(tag_len + 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_statement_replaces_literals() {
+        let cases = [
+            // Numbers, but not within identifiers.
+            (
+                "SELECT * FROM t2 WHERE id = 42",
+                "SELECT * FROM t2 WHERE id = ?",
+            ),
+            ("SELECT col1, 3.14 FROM t", "SELECT col1, ? FROM t"),
+            // Strings with doubled quotes.
+            ("WHERE name = 'O''Brien'", "WHERE name = ?"),
+            // Strings with backslash escapes (MySQL).
+            (
+                r"WHERE name = 'O\'Brien' AND age > 3",
+                "WHERE name = ? AND age > ?",
+            ),
+            (
+                r"WHERE path = 'C:\\' AND id = 1",
+                "WHERE path = ? AND id = ?",
+            ),
+            // Double-quoted strings (MySQL).
+            (r#"WHERE name = "O'Brien""#, "WHERE name = ?"),
+            (r#"WHERE name = "say \"hi\"""#, "WHERE name = ?"),
+            // Prefixed strings.
+            (r"WHERE name = E'O\'Brien'", "WHERE name = ?"),
+            ("VALUES (X'0F', N'a', b'1')", "VALUES (?, ?, ?)"),
+            // Dollar-quoted strings (Postgres), but not placeholders.
+            ("SELECT $$it's$$, $tag$a $$ b$tag$", "SELECT ?, ?"),
+            ("WHERE id = $1 AND v = $2", "WHERE id = $1 AND v = $2"),
+            // Negative numbers, but not subtractions.
+            (
+                "SELECT -5 WHERE v = -1 OR v IN (-2, -3)",
+                "SELECT ? WHERE v = ? OR v IN (?, ?)",
+            ),
+            (
+                "SELECT x - 1, x-1, f(x)-1, (-2.5e-3)",
+                "SELECT x - ?, x-?, f(x)-?, (?)",
+            ),
+            // Unterminated literals run to the end.
+            ("WHERE a = 'abc", "WHERE a = ?"),
+            ("SELECT $$abc", "SELECT ?"),
+        ];
+        for (statement, sanitized) in cases {
+            assert_eq!(sanitize_statement(statement), sanitized, "{statement}");
+        }
+    }
+}
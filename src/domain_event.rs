@@ -0,0 +1,92 @@
+use crate::logs::GLOBAL_LOGGER_PROVIDER;
+
+use opentelemetry::logs::{AnyValue, LogRecord as _, Logger as _, Severity};
+use opentelemetry::trace::TraceContextExt as _;
+use opentelemetry::{Context, KeyValue, Value};
+use serde::Serialize;
+use std::borrow::Cow;
+
+/// Emit the domain event `name` with a serializable payload, see [`emit_event()`].
+///
+/// ```ignore
+/// emit_event!("payment.processed", &payment)?;
+/// ```
+#[macro_export]
+macro_rules! emit_event {
+    ($name:expr, $payload:expr) => {
+        $crate::emit_event($name, $payload)
+    };
+}
+
+/// Emit the domain event `name`, with the fields of `payload` serialized as attributes,
+/// nested keys being flattened with dots (e.g. `card.last4`, `items.0.sku`).
+///
+/// The event is added to the active span if any, and emitted as a log record
+/// with the flattened payload as body otherwise.
+pub fn emit_event<T>(name: impl Into<Cow<'static, str>>, payload: &T) -> anyhow::Result<()>
+where
+    T: Serialize + ?Sized,
+{
+    if !crate::is_enabled() {
+        return Ok(());
+    }
+    let mut attributes = Vec::new();
+    flatten("", serde_json::to_value(payload)?, &mut attributes);
+
+    let cx = Context::current();
+    if cx.has_active_span() {
+        cx.span().add_event(name, attributes);
+    } else if let Some(logger_provider) = GLOBAL_LOGGER_PROVIDER.get() {
+        let logger = crate::logs::scoped_logger(logger_provider, env!("CARGO_PKG_NAME"));
+        let mut record = logger.create_log_record();
+        record.set_severity_number(Severity::Info);
+        record.set_severity_text("INFO");
+        record.add_attribute("event.name", name.into());
+        record.set_body(AnyValue::from_iter(
+            attributes
+                .into_iter()
+                .map(|kv| (kv.key, AnyValue::from(kv.value))),
+        ));
+        logger.emit(record);
+    }
+    Ok(())
+}
+
+fn flatten(prefix: &str, value: serde_json::Value, attributes: &mut Vec<KeyValue>) {
+    let key = |name: &str| {
+        if prefix.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}.{}", prefix, name)
+        }
+    };
+    let prefix_or_value = || {
+        if prefix.is_empty() {
+            "value".to_owned()
+        } else {
+            prefix.to_owned()
+        }
+    };
+    match value {
+        serde_json::Value::Null => {}
+        serde_json::Value::Bool(b) => attributes.push(KeyValue::new(prefix_or_value(), b)),
+        serde_json::Value::Number(n) => {
+            let value: Value = match n.as_i64() {
+                Some(i) => i.into(),
+                None => n.as_f64().unwrap_or_default().into(),
+            };
+            attributes.push(KeyValue::new(prefix_or_value(), value));
+        }
+        serde_json::Value::String(s) => attributes.push(KeyValue::new(prefix_or_value(), s)),
+        serde_json::Value::Array(items) => {
+            for (index, item) in items.into_iter().enumerate() {
+                flatten(&key(&index.to_string()), item, attributes);
+            }
+        }
+        serde_json::Value::Object(fields) => {
+            for (name, field) in fields {
+                flatten(&key(&name), field, attributes);
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use opentelemetry::metrics::Counter;
+use opentelemetry::trace::TraceContextExt as _;
+use opentelemetry::{Context, KeyValue};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+const FEATURE_FLAG: &str = "feature_flag";
+const FEATURE_FLAG_KEY: &str = "feature_flag.key";
+const FEATURE_FLAG_VARIANT: &str = "feature_flag.variant";
+const FEATURE_FLAG_PROVIDER_NAME: &str = "feature_flag.provider_name";
+const FEATURE_FLAG_EVALUATIONS: &str = "feature_flag.evaluations";
+
+static RECORD_METRICS: AtomicBool = AtomicBool::new(false);
+
+static EVALUATIONS_COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+
+pub(crate) fn set_record_metrics(record: bool) {
+    RECORD_METRICS.store(record, Ordering::Relaxed);
+}
+
+/// Record the evaluation of the feature flag `key` to `variant` by `provider`,
+/// as a `feature_flag` event of the current span with the `feature_flag.*` semantic
+/// convention attributes.
+///
+/// The evaluation is also counted into the `feature_flag.evaluations` counter, by key,
+/// variant and provider, if enabled by
+/// [`InitConfig::with_feature_flag_metrics`](crate::InitConfig::with_feature_flag_metrics).
+///
+/// ```ignore
+/// let variant = flags.string_value("checkout-flow", "classic");
+/// record_feature_flag("checkout-flow", variant.clone(), "flagd");
+/// ```
+pub fn record_feature_flag(
+    key: impl Into<Cow<'static, str>>,
+    variant: impl Into<Cow<'static, str>>,
+    provider: impl Into<Cow<'static, str>>,
+) {
+    if !crate::is_enabled() {
+        return;
+    }
+    let attributes = [
+        KeyValue::new(FEATURE_FLAG_KEY, key.into()),
+        KeyValue::new(FEATURE_FLAG_VARIANT, variant.into()),
+        KeyValue::new(FEATURE_FLAG_PROVIDER_NAME, provider.into()),
+    ];
+    if RECORD_METRICS.load(Ordering::Relaxed) {
+        EVALUATIONS_COUNTER
+            .get_or_init(|| {
+                crate::metrics::crate_meter()
+                    .u64_counter(FEATURE_FLAG_EVALUATIONS)
+                    .with_description("Number of feature flag evaluations")
+                    .init()
+            })
+            .add(1, &attributes);
+    }
+    Context::current()
+        .span()
+        .add_event(FEATURE_FLAG, attributes.to_vec());
+}
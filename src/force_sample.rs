@@ -0,0 +1,110 @@
+use crate::sampling::SamplingHook;
+
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::SamplingDecision;
+use opentelemetry::Context;
+use std::sync::{Arc, RwLock};
+use tracing::Level;
+
+/// Configuration of the force-sampling request header, see
+/// [`InitConfig::with_force_sample_config`](crate::InitConfig::with_force_sample_config).
+///
+/// A request carrying the header with one of the accepted tokens, e.g.
+/// `x-otel-force-sample: <token>`, is sampled whatever the sampler, and the log records emitted
+/// within its context are exported down to the configured level, to troubleshoot a single
+/// request in production. The header is recognized by
+/// [`OtelServerService`](crate::middleware::OtelServerService) and [`extract_context`].
+///
+/// [`extract_context`]: crate::extract_context
+#[derive(Debug, Clone, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct ForceSampleConfig {
+    /// The name of the header. Defaults to `x-otel-force-sample`.
+    header: String,
+    /// The accepted values of the header, e.g. secrets shared with the support team,
+    /// so that clients cannot force the sampling at will. The header is ignored while empty,
+    /// which is the default.
+    tokens: Vec<String>,
+    /// The most verbose level of the log records exported within the context of a forced
+    /// request, even if filtered out otherwise. Defaults to `DEBUG`.
+    log_level: Level,
+}
+
+impl Default for ForceSampleConfig {
+    fn default() -> Self {
+        Self {
+            header: "x-otel-force-sample".to_owned(),
+            tokens: Vec::new(),
+            log_level: Level::DEBUG,
+        }
+    }
+}
+
+impl ForceSampleConfig {
+    pub(crate) fn log_level(&self) -> Level {
+        self.log_level
+    }
+}
+
+static FORCE_SAMPLE_CONFIG: RwLock<Option<ForceSampleConfig>> = RwLock::new(None);
+
+pub(crate) fn set_config(config: Option<ForceSampleConfig>) {
+    *FORCE_SAMPLE_CONFIG.write().unwrap() = config;
+}
+
+/// Marks the context of a forced request, with the level of the log records to export.
+#[derive(Debug, Clone, Copy)]
+struct ForcedRequest(Level);
+
+/// Mark `cx` as forced if `extractor` carries the force-sampling header with an accepted token.
+pub(crate) fn force(cx: Context, extractor: &dyn Extractor) -> Context {
+    let config = FORCE_SAMPLE_CONFIG.read().unwrap();
+    let Some(config) = config.as_ref() else {
+        return cx;
+    };
+    let accepted = extractor.get(&config.header).is_some_and(|value| {
+        let value = value.trim();
+        config
+            .tokens
+            .iter()
+            .any(|token| constant_time_eq(token.as_bytes(), value.as_bytes()))
+    });
+    if accepted {
+        cx.with_value(ForcedRequest(config.log_level))
+    } else {
+        cx
+    }
+}
+
+/// Carry the mark of a forced request from `from` over to `to`, e.g. from the remote parent
+/// context of a server span to the context of the span.
+pub(crate) fn propagate(from: &Context, to: Context) -> Context {
+    match from.get::<ForcedRequest>() {
+        Some(forced) => to.with_value(*forced),
+        None => to,
+    }
+}
+
+/// The level down to which the log records of the current context are exported,
+/// if it is that of a forced request.
+pub(crate) fn forced_log_level() -> Option<Level> {
+    Context::map_current(|cx| cx.get::<ForcedRequest>().map(|forced| forced.0))
+}
+
+/// Wrap `hook` so that the spans of the forced requests are sampled.
+pub(crate) fn with_force_sampling(hook: Option<SamplingHook>) -> SamplingHook {
+    SamplingHook(Arc::new(move |params| {
+        let forced = params
+            .parent_context
+            .is_some_and(|cx| cx.get::<ForcedRequest>().is_some());
+        if forced {
+            return Some(SamplingDecision::RecordAndSample);
+        }
+        hook.as_ref().and_then(|hook| (hook.0)(params))
+    }))
+}
+
+/// Compare the tokens in constant time, so that their content does not leak through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
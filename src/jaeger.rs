@@ -0,0 +1,393 @@
+//! Export of spans to a Jaeger agent, over UDP in the Thrift compact protocol,
+//! for legacy infrastructure without an OTLP collector.
+
+use futures_util::future::BoxFuture;
+use opentelemetry::trace::{SpanId, SpanKind, Status, TraceError, TraceId};
+use opentelemetry::{KeyValue, Value};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::Resource;
+use opentelemetry_semantic_conventions::resource::SERVICE_NAME;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::SystemTime;
+
+/// The largest UDP packet accepted by the Jaeger agent.
+const MAX_PACKET_SIZE: usize = 65_000;
+/// The size of the message header, of the batch and list headers and of the struct stops,
+/// around the process and the spans of a packet.
+const PACKET_OVERHEAD: usize = 32;
+
+/// Exports spans to a Jaeger agent, e.g. at `localhost:6831`, see
+/// [`InitConfig::with_jaeger_agent_endpoint`](crate::InitConfig::with_jaeger_agent_endpoint).
+///
+/// The spans of a batch are split into as many UDP packets as needed.
+#[derive(Debug)]
+pub struct JaegerAgentExporter {
+    socket: UdpSocket,
+    /// The encoded Jaeger process of the spans, from the resource.
+    process: Vec<u8>,
+    seq_id: u32,
+}
+
+impl JaegerAgentExporter {
+    /// Create an exporter to the Jaeger agent at `endpoint`.
+    pub fn new(endpoint: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(endpoint)?;
+        Ok(Self {
+            socket,
+            process: encode_process(&Resource::empty()),
+            seq_id: 0,
+        })
+    }
+
+    /// Send `spans`, already encoded, in one `emitBatch` message.
+    fn emit_batch(&mut self, spans: &[Vec<u8>]) -> std::io::Result<()> {
+        self.seq_id = self.seq_id.wrapping_add(1);
+        let mut writer = ThriftWriter::default();
+        writer.message_begin("emitBatch", self.seq_id);
+        writer.field(1, STRUCT);
+        writer.struct_begin();
+        writer.field(1, STRUCT);
+        writer.raw(&self.process);
+        writer.field(2, LIST);
+        writer.list_begin(STRUCT, spans.len());
+        for span in spans {
+            writer.raw(span);
+        }
+        writer.struct_end();
+        writer.stop();
+        self.socket.send(&writer.buf).map(|_| ())
+    }
+}
+
+impl SpanExporter for JaegerAgentExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let budget = MAX_PACKET_SIZE - PACKET_OVERHEAD - self.process.len();
+        let mut result = Ok(());
+        let mut packet = Vec::new();
+        let mut packet_size = 0;
+        for span in &batch {
+            let span = encode_span(span);
+            if span.len() > budget {
+                result = Err(TraceError::from(format!(
+                    "a span of {} bytes exceeds the Jaeger agent packet size",
+                    span.len()
+                )));
+                continue;
+            }
+            if packet_size + span.len() > budget {
+                if let Err(err) = self.emit_batch(&packet) {
+                    result = Err(TraceError::Other(Box::new(err)));
+                }
+                packet.clear();
+                packet_size = 0;
+            }
+            packet_size += span.len();
+            packet.push(span);
+        }
+        if !packet.is_empty() {
+            if let Err(err) = self.emit_batch(&packet) {
+                result = Err(TraceError::Other(Box::new(err)));
+            }
+        }
+        Box::pin(std::future::ready(result))
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.process = encode_process(resource);
+    }
+}
+
+/// Encode the Jaeger process of `resource`: its service name, and its other attributes as tags.
+fn encode_process(resource: &Resource) -> Vec<u8> {
+    let service_name = resource
+        .get(SERVICE_NAME.into())
+        .map_or_else(|| "unknown_service".to_owned(), |name| name.to_string());
+    let tags: Vec<_> = resource
+        .iter()
+        .filter(|(key, _)| key.as_str() != SERVICE_NAME)
+        .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+        .collect();
+    let mut writer = ThriftWriter::default();
+    writer.struct_begin();
+    writer.string_field(1, &service_name);
+    writer.tags_field(2, &tags);
+    writer.struct_end();
+    writer.buf
+}
+
+/// Encode `span` as a Jaeger span, with the conventional tags of its kind, status and scope.
+fn encode_span(span: &SpanData) -> Vec<u8> {
+    let context = &span.span_context;
+    let (trace_id_high, trace_id_low) = split_trace_id(context.trace_id());
+    let mut tags = span.attributes.clone();
+    let kind = match span.span_kind {
+        SpanKind::Client => Some("client"),
+        SpanKind::Server => Some("server"),
+        SpanKind::Producer => Some("producer"),
+        SpanKind::Consumer => Some("consumer"),
+        SpanKind::Internal => None,
+    };
+    if let Some(kind) = kind {
+        tags.push(KeyValue::new("span.kind", kind));
+    }
+    match &span.status {
+        Status::Unset => {}
+        Status::Ok => tags.push(KeyValue::new("otel.status_code", "OK")),
+        Status::Error { description } => {
+            tags.push(KeyValue::new("otel.status_code", "ERROR"));
+            tags.push(KeyValue::new("error", true));
+            if !description.is_empty() {
+                tags.push(KeyValue::new(
+                    "otel.status_description",
+                    description.to_string(),
+                ));
+            }
+        }
+    }
+    let scope = &span.instrumentation_lib;
+    tags.push(KeyValue::new("otel.scope.name", scope.name.to_string()));
+    if let Some(version) = &scope.version {
+        tags.push(KeyValue::new("otel.scope.version", version.to_string()));
+    }
+
+    let mut writer = ThriftWriter::default();
+    writer.struct_begin();
+    writer.i64_field(1, trace_id_low);
+    writer.i64_field(2, trace_id_high);
+    writer.i64_field(3, span_id(context.span_id()));
+    writer.i64_field(4, span_id(span.parent_span_id));
+    writer.string_field(5, &span.name);
+    if !span.links.links.is_empty() {
+        writer.field(6, LIST);
+        writer.list_begin(STRUCT, span.links.links.len());
+        for link in span.links.links.iter() {
+            let (trace_id_high, trace_id_low) = split_trace_id(link.span_context.trace_id());
+            writer.struct_begin();
+            writer.i32_field(1, REF_TYPE_FOLLOWS_FROM);
+            writer.i64_field(2, trace_id_low);
+            writer.i64_field(3, trace_id_high);
+            writer.i64_field(4, span_id(link.span_context.span_id()));
+            writer.struct_end();
+        }
+    }
+    writer.i32_field(7, i32::from(context.trace_flags().to_u8() & 0x01));
+    writer.i64_field(8, micros_since_epoch(span.start_time));
+    let duration = span
+        .end_time
+        .duration_since(span.start_time)
+        .unwrap_or_default();
+    writer.i64_field(9, duration.as_micros() as i64);
+    writer.tags_field(10, &tags);
+    if !span.events.events.is_empty() {
+        writer.field(11, LIST);
+        writer.list_begin(STRUCT, span.events.events.len());
+        for event in span.events.events.iter() {
+            let mut fields = vec![KeyValue::new("event", event.name.to_string())];
+            fields.extend(event.attributes.iter().cloned());
+            writer.struct_begin();
+            writer.i64_field(1, micros_since_epoch(event.timestamp));
+            writer.tags_field(2, &fields);
+            writer.struct_end();
+        }
+    }
+    writer.struct_end();
+    writer.buf
+}
+
+/// The high and low halves of `trace_id`, as Jaeger represents it.
+fn split_trace_id(trace_id: TraceId) -> (i64, i64) {
+    let trace_id = u128::from_be_bytes(trace_id.to_bytes());
+    ((trace_id >> 64) as i64, trace_id as i64)
+}
+
+fn span_id(span_id: SpanId) -> i64 {
+    i64::from_be_bytes(span_id.to_bytes())
+}
+
+fn micros_since_epoch(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+// Types of the Thrift compact protocol.
+const BOOL_TRUE: u8 = 1;
+const BOOL_FALSE: u8 = 2;
+const I32: u8 = 5;
+const I64: u8 = 6;
+const DOUBLE: u8 = 7;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+const PROTOCOL_ID: u8 = 0x82;
+const VERSION: u8 = 1;
+const MESSAGE_ONEWAY: u8 = 4;
+
+const REF_TYPE_FOLLOWS_FROM: i32 = 1;
+
+// Types of the values of Jaeger tags.
+const TAG_STRING: i32 = 0;
+const TAG_DOUBLE: i32 = 1;
+const TAG_BOOL: i32 = 2;
+const TAG_LONG: i32 = 3;
+
+/// A writer of the Thrift compact protocol, for the few types of the Jaeger agent API.
+#[derive(Debug, Default)]
+struct ThriftWriter {
+    buf: Vec<u8>,
+    last_field_id: i16,
+    /// The last field ids of the enclosing structs.
+    struct_stack: Vec<i16>,
+}
+
+impl ThriftWriter {
+    fn message_begin(&mut self, name: &str, seq_id: u32) {
+        self.buf.push(PROTOCOL_ID);
+        self.buf.push(VERSION | (MESSAGE_ONEWAY << 5));
+        self.varint(u64::from(seq_id));
+        self.binary(name.as_bytes());
+    }
+
+    fn struct_begin(&mut self) {
+        self.struct_stack.push(self.last_field_id);
+        self.last_field_id = 0;
+    }
+
+    fn struct_end(&mut self) {
+        self.stop();
+        self.last_field_id = self.struct_stack.pop().unwrap_or_default();
+    }
+
+    fn stop(&mut self) {
+        self.buf.push(0);
+    }
+
+    fn field(&mut self, id: i16, field_type: u8) {
+        let delta = id - self.last_field_id;
+        if (1..=15).contains(&delta) {
+            self.buf.push(((delta as u8) << 4) | field_type);
+        } else {
+            self.buf.push(field_type);
+            self.varint(zigzag(i64::from(id)));
+        }
+        self.last_field_id = id;
+    }
+
+    fn list_begin(&mut self, element_type: u8, len: usize) {
+        if len < 15 {
+            self.buf.push(((len as u8) << 4) | element_type);
+        } else {
+            self.buf.push(0xf0 | element_type);
+            self.varint(len as u64);
+        }
+    }
+
+    fn i32_field(&mut self, id: i16, value: i32) {
+        self.field(id, I32);
+        self.varint(zigzag(i64::from(value)));
+    }
+
+    fn i64_field(&mut self, id: i16, value: i64) {
+        self.field(id, I64);
+        self.varint(zigzag(value));
+    }
+
+    fn bool_field(&mut self, id: i16, value: bool) {
+        self.field(id, if value { BOOL_TRUE } else { BOOL_FALSE });
+    }
+
+    fn double_field(&mut self, id: i16, value: f64) {
+        self.field(id, DOUBLE);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string_field(&mut self, id: i16, value: &str) {
+        self.field(id, BINARY);
+        self.binary(value.as_bytes());
+    }
+
+    /// Write `tags` as a list of Jaeger tags, unless empty.
+    fn tags_field(&mut self, id: i16, tags: &[KeyValue]) {
+        if tags.is_empty() {
+            return;
+        }
+        self.field(id, LIST);
+        self.list_begin(STRUCT, tags.len());
+        for tag in tags {
+            self.struct_begin();
+            self.string_field(1, tag.key.as_str());
+            match &tag.value {
+                Value::Bool(value) => {
+                    self.i32_field(2, TAG_BOOL);
+                    self.bool_field(5, *value);
+                }
+                Value::I64(value) => {
+                    self.i32_field(2, TAG_LONG);
+                    self.i64_field(6, *value);
+                }
+                Value::F64(value) => {
+                    self.i32_field(2, TAG_DOUBLE);
+                    self.double_field(4, *value);
+                }
+                value => {
+                    self.i32_field(2, TAG_STRING);
+                    self.string_field(3, value.as_str().as_ref());
+                }
+            }
+            self.struct_end();
+        }
+    }
+
+    fn binary(&mut self, value: &[u8]) {
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    fn raw(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_thrift_compact_structs() {
+        let mut writer = ThriftWriter::default();
+        writer.struct_begin();
+        writer.string_field(1, "svc");
+        writer.i64_field(3, -1);
+        writer.bool_field(20, true);
+        writer.struct_end();
+        assert_eq!(
+            writer.buf,
+            [
+                0x18, 3, b's', b'v', b'c', // field 1, binary "svc"
+                0x26, 1, // field 3 (delta 2), i64 -1 zigzagged
+                0x01, 40,   // field 20 (delta 17, long form), bool true
+                0x00, // stop
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_trace_ids_into_halves() {
+        let trace_id = TraceId::from_hex("0000000000000001ffffffffffffffff").unwrap();
+        assert_eq!(split_trace_id(trace_id), (1, -1));
+    }
+}
@@ -0,0 +1,130 @@
+//! Root traces and metrics for batch jobs and scheduled tasks.
+
+use crate::{flush_on_invocation_end, tracer_span, Instant};
+
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::trace::{FutureExt as _, SpanBuilder, SpanKind, Status, TraceContextExt};
+use opentelemetry::{Context, KeyValue};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::OnceLock;
+
+const JOB_NAME: &str = "job.name";
+const JOB_OUTCOME: &str = "job.outcome";
+const JOB_DURATION: &str = "job.duration";
+const JOB_RUNS: &str = "job.runs";
+
+static DURATION_HISTOGRAM: OnceLock<Histogram<f64>> = OnceLock::new();
+static RUNS_COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+
+/// Run `job` within the root span of a new trace named `name`, with `job.name` and
+/// `job.outcome` (`success` or `failure`) attributes, and return its result.
+///
+/// The run is counted into the `job.runs` counter and its duration recorded into the
+/// `job.duration` histogram, both by `job.name` and `job.outcome`. The telemetry is then
+/// flushed, so that nothing is lost if the process exits right after the job.
+///
+/// `job` gets the context holding the span, e.g. to propagate it into spawned tasks.
+///
+/// ```ignore
+/// run_job("nightly-sync", |cx| async move { sync(&db).await }).await?;
+/// ```
+pub async fn run_job<T, E, F, Fut>(name: &'static str, job: F) -> Result<T, E>
+where
+    F: FnOnce(Context) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    let builder = SpanBuilder::from_name(name)
+        .with_kind(SpanKind::Internal)
+        .with_attributes([KeyValue::new(JOB_NAME, name)]);
+    // An empty parent context starts a new trace, whatever the current one is.
+    let span = tracer_span(builder, Some(&Context::new()));
+    let cx = Context::current_with_span(span);
+
+    let start = Instant::now();
+    let result = job(cx.clone()).with_context(cx.clone()).await;
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let span = cx.span();
+    let outcome = match &result {
+        Ok(_) => "success",
+        Err(err) => {
+            span.set_status(Status::error(err.to_string()));
+            "failure"
+        }
+    };
+    span.set_attribute(KeyValue::new(JOB_OUTCOME, outcome));
+    span.end();
+
+    let attributes = [
+        KeyValue::new(JOB_NAME, name),
+        KeyValue::new(JOB_OUTCOME, outcome),
+    ];
+    RUNS_COUNTER
+        .get_or_init(|| {
+            crate::metrics::crate_meter()
+                .u64_counter(JOB_RUNS)
+                .with_description("Number of job runs")
+                .init()
+        })
+        .add(1, &attributes);
+    DURATION_HISTOGRAM
+        .get_or_init(|| {
+            crate::metrics::crate_meter()
+                .f64_histogram(JOB_DURATION)
+                .with_unit("s")
+                .with_description("Duration of job runs")
+                .init()
+        })
+        .record(elapsed, &attributes);
+
+    // The export failures are already reported by the global error handler
+    // and counted into the export statistics. Flushing blocks on the export tasks,
+    // which may need the current thread to make progress.
+    #[cfg(not(feature = "wasm"))]
+    match tokio::runtime::Handle::try_current() {
+        Ok(_) => {
+            let _ = tokio::task::spawn_blocking(flush_on_invocation_end).await;
+        }
+        Err(_) => {
+            let _ = flush_on_invocation_end();
+        }
+    }
+    #[cfg(feature = "wasm")]
+    let _ = flush_on_invocation_end();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_executor::block_on;
+
+    #[test]
+    fn run_job_starts_a_new_trace() {
+        let request = tracer_span(SpanBuilder::from_name("request"), Some(&Context::new()));
+        let request_cx = Context::current_with_span(request);
+        let _guard = request_cx.clone().attach();
+
+        let job_cx = block_on(run_job("sync", |cx| async move { Ok::<_, String>(cx) })).unwrap();
+        let job_span_context = job_cx.span().span_context().clone();
+        assert!(job_span_context.is_valid());
+        assert_ne!(
+            job_span_context.trace_id(),
+            request_cx.span().span_context().trace_id()
+        );
+    }
+
+    #[test]
+    fn run_job_returns_the_result_of_the_job() {
+        assert_eq!(
+            block_on(run_job("ok", |_| async { Ok::<_, String>(42) })),
+            Ok(42)
+        );
+        assert_eq!(
+            block_on(run_job("failing", |_| async { Err::<(), _>("boom") })),
+            Err("boom")
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{audit, init_otel, logs, metrics, trace, InitConfig};
+
+use opentelemetry::KeyValue;
+use opentelemetry_semantic_conventions::resource::{
+    CLOUD_PROVIDER, CLOUD_REGION, FAAS_INSTANCE, FAAS_MAX_MEMORY, FAAS_NAME, FAAS_VERSION,
+};
+use std::env;
+
+/// Initialize OpenTelemetry for AWS Lambda.
+///
+/// Batch processors lose data when Lambda freezes the process between invocations,
+/// so the batch configurations are ignored in favor of simple exporters,
+/// and [`flush_on_invocation_end`] must be called at the end of each invocation.
+/// The `faas.*` and `cloud.*` resource attributes are read from the Lambda environment.
+pub async fn init_otel_lambda(init_config: InitConfig) -> anyhow::Result<bool> {
+    let mut resource_attributes = init_config.resource_attributes.clone();
+    resource_attributes.extend(lambda_resource_attributes());
+    let init_config = init_config
+        .with_batch_log_config(None)
+        .with_batch_trace_config(None)
+        .with_resource_attributes(resource_attributes);
+    init_otel(init_config).await
+}
+
+/// Flush the pending logs, audit records, spans and metrics, to be called at the end of each
+/// Lambda invocation, before the process may be frozen.
+pub fn flush_on_invocation_end() -> anyhow::Result<()> {
+    let mut errors = Vec::new();
+    if let Some(logger_provider) = logs::GLOBAL_LOGGER_PROVIDER.get() {
+        errors.extend(
+            logger_provider
+                .force_flush()
+                .into_iter()
+                .filter_map(|r| r.err().map(anyhow::Error::from)),
+        );
+    }
+    if let Some(tracer_provider) = trace::GLOBAL_TRACER_PROVIDER.get() {
+        errors.extend(
+            tracer_provider
+                .force_flush()
+                .into_iter()
+                .filter_map(|r| r.err().map(anyhow::Error::from)),
+        );
+    }
+    if let Some(audit_logger_provider) = audit::AUDIT_LOGGER_PROVIDER.get() {
+        errors.extend(
+            audit_logger_provider
+                .force_flush()
+                .into_iter()
+                .filter_map(|r| r.err().map(anyhow::Error::from)),
+        );
+    }
+    if let Some(meter_provider) = metrics::GLOBAL_MMTER_PROVIDER.get() {
+        if let Err(err) = meter_provider.force_flush() {
+            errors.push(err.into());
+        }
+    }
+    match errors.into_iter().next() {
+        Some(err) => Err(err.context("failed to flush telemetry")),
+        None => Ok(()),
+    }
+}
+
+fn lambda_resource_attributes() -> Vec<KeyValue> {
+    let mut kvs = vec![KeyValue::new(CLOUD_PROVIDER, "aws")];
+    for (key, var) in [
+        (FAAS_NAME, "AWS_LAMBDA_FUNCTION_NAME"),
+        (FAAS_VERSION, "AWS_LAMBDA_FUNCTION_VERSION"),
+        (FAAS_INSTANCE, "AWS_LAMBDA_LOG_STREAM_NAME"),
+        (CLOUD_REGION, "AWS_REGION"),
+    ] {
+        if let Ok(value) = env::var(var) {
+            kvs.push(KeyValue::new(key, value));
+        }
+    }
+    // The memory size is given in MiB, while `faas.max_memory` is in bytes.
+    if let Some(memory_mib) = env::var("AWS_LAMBDA_FUNCTION_MEMORY_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+    {
+        kvs.push(KeyValue::new(FAAS_MAX_MEMORY, memory_mib * 1024 * 1024));
+    }
+    kvs
+}
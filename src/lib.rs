@@ -55,13 +55,7 @@ async fn emit_span() {
         vec![KeyValue::new("event_attribute1", "event_value1")]
     );
     otel_span.add_link(
-        SpanContext::new(
-            TraceId::from_hex("58406520a006649127e371903a2de979").expect("invalid"),
-            SpanId::from_hex("b6d7d7f6d7d6d7f6").expect("invalid"),
-            TraceFlags::default(),
-            false,
-            TraceState::NONE
-        ),
+        traceparent_to_span_context("00-58406520a006649127e371903a2de979-b6d7d7f6d7d6d7f6-00").expect("invalid"),
         vec![
             KeyValue::new("link_attribute1", "link_value1"),
             KeyValue::new("link_attribute2", "link_value2")
@@ -69,13 +63,7 @@ async fn emit_span() {
     );
 
     otel_span.add_link(
-        SpanContext::new(
-            TraceId::from_hex("23401120a001249127e371903f2de971").expect("invalid"),
-            SpanId::from_hex("cd37d765d743d7f6").expect("invalid"),
-            TraceFlags::default(),
-            false,
-            TraceState::NONE
-        ),
+        traceparent_to_span_context("00-23401120a001249127e371903f2de971-cd37d765d743d7f6-00").expect("invalid"),
         vec![
             KeyValue::new("link_attribute1", "link_value1"),
             KeyValue::new("link_attribute2", "link_value2")
@@ -103,36 +91,107 @@ async fn emit_metrics() {
     let meter = meter_provider().meter("stdout-example");
     // let meter = meter("stdout-example");
     let c = meter.u64_counter("example_counter").init();
-    c.add(1, &[KeyValue::new("name", "apple"), KeyValue::new("color", "green")]);
-    c.add(1, &[KeyValue::new("name", "apple"), KeyValue::new("color", "green")]);
-    c.add(2, &[KeyValue::new("name", "apple"), KeyValue::new("color", "red")]);
-    c.add(1, &[KeyValue::new("name", "banana"), KeyValue::new("color", "yellow")]);
-    c.add(11, &[KeyValue::new("name", "banana"), KeyValue::new("color", "yellow")]);
+    c.add(1, attrs!["name" => "apple", "color" => "green"]);
+    c.add(1, attrs!["name" => "apple", "color" => "green"]);
+    c.add(2, attrs!["name" => "apple", "color" => "red"]);
+    c.add(1, attrs!["name" => "banana", "color" => "yellow"]);
+    c.add(11, attrs!["name" => "banana", "color" => "yellow"]);
 
     let h = meter.f64_histogram("example_histogram").init();
-    h.record(1.0, &[KeyValue::new("name", "apple"), KeyValue::new("color", "green")]);
-    h.record(1.0, &[KeyValue::new("name", "apple"), KeyValue::new("color", "green")]);
-    h.record(2.0, &[KeyValue::new("name", "apple"), KeyValue::new("color", "red")]);
-    h.record(1.0, &[KeyValue::new("name", "banana"), KeyValue::new("color", "yellow")]);
-    h.record(11.0, &[KeyValue::new("name", "banana"), KeyValue::new("color", "yellow")]);
+    h.record(1.0, attrs!["name" => "apple", "color" => "green"]);
+    h.record(1.0, attrs!["name" => "apple", "color" => "green"]);
+    h.record(2.0, attrs!["name" => "apple", "color" => "red"]);
+    h.record(1.0, attrs!["name" => "banana", "color" => "yellow"]);
+    h.record(11.0, attrs!["name" => "banana", "color" => "yellow"]);
 }
 ```
 */
 
 #![deny(missing_docs)]
 
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+compile_error!("the `wasm` feature is required on wasm32 targets");
+
+pub mod audit;
+mod baggage;
+mod circuit_breaker;
+pub mod cli;
+pub mod db;
+#[cfg(feature = "serde")]
+mod domain_event;
+mod feature_flag;
+mod force_sample;
+#[cfg(feature = "jaeger-compat")]
+mod jaeger;
+pub mod job;
+mod lambda;
+mod log_buffer;
+mod log_sampling;
 mod logs;
+pub mod messaging;
+mod metric_views;
 mod metrics;
+pub mod middleware;
+mod pre_init;
+#[cfg(not(feature = "wasm"))]
+pub mod process;
+mod propagation;
+mod reconfigure;
+pub mod rpc;
+mod sampling;
+#[cfg(feature = "sentry")]
+mod sentry;
+mod session;
+mod slo;
+mod snapshot;
+mod span_name;
+mod span_queue;
+mod startup_probe;
+mod stats;
+mod tenant;
+pub mod thread;
 mod trace;
+mod trace_summary;
+mod transport;
+mod tree;
+mod verbosity;
 
+use audit::AuditConfig;
 use opentelemetry::global;
+use opentelemetry_sdk::logs::LoggerProvider;
+use opentelemetry_sdk::trace::{IdGenerator as _, RandomIdGenerator, TracerProvider};
 use opentelemetry_sdk::Resource;
-use std::sync::{Mutex, OnceLock};
+use reconfigure::{ReloadableLogExporter, ReloadableSampler, ReloadableSpanExporter};
+use std::collections::HashMap;
+use std::io::IsTerminal as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{Dispatch, Subscriber};
 use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::filter::{filter_fn, FilterExt as _};
 use tracing_subscriber::layer::SubscriberExt as _;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// The monotonic clock, on `performance.now()` with the `wasm` feature.
+#[cfg(not(feature = "wasm"))]
+pub(crate) use std::time::Instant;
+#[cfg(feature = "wasm")]
+pub(crate) use web_time::Instant;
 
 pub use _tracing::*;
+pub use baggage::*;
+pub use circuit_breaker::*;
+#[cfg(feature = "serde")]
+pub use domain_event::*;
+pub use feature_flag::*;
+pub use force_sample::*;
+#[cfg(feature = "jaeger-compat")]
+pub use jaeger::*;
+pub use lambda::*;
+pub use log_buffer::*;
+pub use log_sampling::*;
 pub use logs::*;
 pub use metrics::*;
 pub use opentelemetry::global::{get_text_map_propagator, set_text_map_propagator};
@@ -140,7 +199,24 @@ pub use opentelemetry::{
     Array, InstrumentationLibrary, InstrumentationLibraryBuilder, Key, KeyValue, Value,
 };
 pub use opentelemetry_semantic_conventions as semantic_conventions;
+pub use pre_init::*;
+pub use propagation::*;
+pub use reconfigure::*;
+pub use sampling::*;
+#[cfg(feature = "sentry")]
+pub use sentry::*;
+pub use session::*;
+pub use slo::*;
+pub use snapshot::*;
+pub use span_name::*;
+pub use span_queue::*;
+pub use stats::*;
+pub use tenant::*;
 pub use trace::*;
+pub use trace_summary::*;
+pub use transport::*;
+pub use tree::*;
+pub use verbosity::*;
 mod _tracing {
     pub use tracing;
     // Attribute Macros
@@ -155,41 +231,607 @@ mod _tracing {
 
 static RESOURCE: OnceLock<Resource> = OnceLock::new();
 
+/// The resource shared by all providers.
+pub(crate) fn resource() -> Resource {
+    RESOURCE.get().unwrap().clone()
+}
+
+/// The generated `service.instance.id` of this process, see
+/// [`InitConfig::with_service_instance_id`].
+fn service_instance_id() -> &'static str {
+    static SERVICE_INSTANCE_ID: OnceLock<String> = OnceLock::new();
+    SERVICE_INSTANCE_ID.get_or_init(|| {
+        // A UUID v7: the Unix time in milliseconds followed by random bits.
+        let mut bytes = RandomIdGenerator::default().new_trace_id().to_bytes();
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        bytes[6] = (bytes[6] & 0x0F) | 0x70;
+        bytes[8] = (bytes[8] & 0x3F) | 0x80;
+        let hex = format!("{:032x}", u128::from_be_bytes(bytes));
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    })
+}
+
+/// The schema URL and attributes of the instrumentation scopes created by this crate.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ScopeConfig {
+    pub(crate) schema_url: Option<String>,
+    pub(crate) attributes: Vec<KeyValue>,
+}
+
+static SCOPE: OnceLock<ScopeConfig> = OnceLock::new();
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether telemetry is initialized and enabled, see [`InitConfig::with_enabled`].
+///
+/// The helpers of this crate check it first, so that they cost next to nothing in hot loops
+/// while telemetry is disabled; instrumented code can do the same before building costly
+/// attributes.
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+static DEFAULT_SCOPE: ScopeConfig = ScopeConfig {
+    schema_url: None,
+    attributes: Vec::new(),
+};
+
+/// The scope configuration, see [`InitConfig::with_schema_url`].
+pub(crate) fn scope() -> &'static ScopeConfig {
+    SCOPE.get().unwrap_or(&DEFAULT_SCOPE)
+}
+
 /// OpenTelemetry initialization configuration.
 #[derive(Debug, getset2::WithSetters)]
 #[getset(set_with = "pub")]
 pub struct InitConfig {
+    /// Whether telemetry is enabled. If disabled, see [`InitConfig::disabled`].
+    /// Enabled by default.
+    enabled: bool,
     /// Service name
     service_name: String,
     /// Service version
     service_version: String,
+    /// Additional resource attributes.
+    resource_attributes: Vec<KeyValue>,
+    /// The `service.instance.id` resource attribute,
+    /// see [`InitConfig::with_service_instance_id`].
+    #[getset(skip)]
+    service_instance_id: Option<String>,
+    /// Schema URL of the resource and instrumentation scopes, see [`InitConfig::with_schema_url`].
+    #[getset(skip)]
+    schema_url: Option<String>,
+    /// Attributes of the instrumentation scopes, see [`InitConfig::with_scope_attributes`].
+    #[getset(skip)]
+    scope_attributes: Vec<KeyValue>,
+    /// Filter directives of the `tracing` fmt layer, see [`InitConfig::with_fmt_filter`].
+    #[getset(skip)]
+    fmt_filter: Option<String>,
+    /// Filter directives of the OpenTelemetry log layer, see [`InitConfig::with_otel_log_filter`].
+    #[getset(skip)]
+    otel_log_filter: Option<String>,
+    /// Filter directives of the OpenTelemetry span layer, see [`InitConfig::with_span_filter`].
+    #[getset(skip)]
+    span_filter: Option<String>,
     /// Whether to use the standard output.
     /// The standard output is used by default in debug mode,
     /// and OTLP is used in release mode.
     stdout_exporter: bool,
+    /// The exporter of all signals, see [`InitConfig::with_exporter_kind`].
+    #[getset(skip)]
+    exporter_kind: Option<ExporterKind>,
+    /// The OTLP endpoint of spans, falling back to the `OTEL_EXPORTER_OTLP_*`
+    /// environment variables.
+    traces_endpoint: Option<String>,
+    /// The Jaeger agent the spans are exported to instead of over OTLP,
+    /// see [`InitConfig::with_jaeger_agent_endpoint`].
+    #[cfg(feature = "jaeger-compat")]
+    #[getset(skip)]
+    jaeger_agent_endpoint: Option<String>,
+    /// The OTLP endpoint of log records, falling back to the `OTEL_EXPORTER_OTLP_*`
+    /// environment variables.
+    logs_endpoint: Option<String>,
+    /// The OTLP endpoint of metrics, falling back to the `OTEL_EXPORTER_OTLP_*`
+    /// environment variables.
+    metrics_endpoint: Option<String>,
+    /// If the latency thresholds are configured, the slow spans printed to the standard output
+    /// are highlighted: in color by [`ExporterKind::StdoutTree`], and else with
+    /// a `latency.threshold` attribute. Not applied to OTLP exports.
+    latency_thresholds: Option<LatencyThresholds>,
+    /// Whether the logs are both printed by the `tracing` fmt layer and exported as
+    /// OpenTelemetry log records over OTLP, whatever `stdout_exporter` is.
+    /// Disabled by default: the logs are only printed with the standard output,
+    /// and only exported otherwise. The events within spans are also span events, so that
+    /// with the standard output they are printed again along with their spans.
+    dual_logging: bool,
+    /// Targets whose events are not bridged into log records,
+    /// see [`InitConfig::with_otel_log_excluded_targets`].
+    #[getset(skip)]
+    otel_log_excluded_targets: Arc<[String]>,
+    /// Metric readers registered next to the pushing one,
+    /// see [`InitConfig::with_pull_metric_reader`].
+    #[getset(skip)]
+    pull_metric_readers: Vec<metrics::PullMetricReader>,
+    /// The views of the meter provider, see [`InitConfig::with_exponential_histograms`].
+    #[getset(skip)]
+    metric_views: metric_views::MetricViews,
     /// If the batch log configuration is configured, batch reporting will be enabled.
     batch_log_config: Option<BatchLogConfig>,
+    /// Configuration of the audit log channel, see [`audit`].
+    audit_config: AuditConfig,
+    /// If the log metrics configuration is configured, emitted log records are counted
+    /// into the `log_records_total` counter.
+    log_metrics_config: Option<LogMetricsConfig>,
+    /// Whether the exported log records carry the message template their body was formatted
+    /// from as the `log.template` attribute, next to their fields as attributes, for
+    /// backends grouping the records by template (e.g. Seq or Elastic). The template is
+    /// recovered from the fields of the record, e.g. `user {user} logged in` for
+    /// `info!(user = %name, "user {name} logged in")`, and left out when ambiguous, e.g. when
+    /// the literal text of the message contains a field value. Disabled by default.
+    log_templates: bool,
+    /// If the log sampling configuration is configured, only a fraction of the INFO, DEBUG
+    /// and TRACE records outside sampled traces are exported, see [`LogSamplingConfig`].
+    log_sampling_config: Option<LogSamplingConfig>,
+    /// If the debug log buffer configuration is configured, the DEBUG and TRACE records of
+    /// unsampled traces are only exported if their trace errors, see [`DebugLogBufferConfig`].
+    debug_log_buffer_config: Option<DebugLogBufferConfig>,
+    /// If the trace summary configuration is configured, a log record summarizes each trace
+    /// when its root span closes, see [`TraceSummaryConfig`].
+    trace_summary_config: Option<TraceSummaryConfig>,
+    /// Configuration of the Sentry bridge, see [`InitConfig::with_sentry_config`].
+    #[cfg(feature = "sentry")]
+    #[getset(skip)]
+    sentry_config: Option<SentryConfig>,
     /// If the batch trace configuration is configured, batch reporting will be enabled.
     batch_trace_config: Option<BatchTraceConfig>,
+    /// If the span queue configuration is configured, the spans waiting for the batch
+    /// processor are bounded by its own queue and policy, see [`SpanQueueConfig`].
+    span_queue_config: Option<SpanQueueConfig>,
+    /// If the span latency objectives are configured, the spans lasting longer than
+    /// the objective of their name are reported, see [`SpanSlos`].
+    span_slos: Option<SpanSlos>,
     /// Tracer Provider Config.
     tracer_provider_config: TracerProviderConfig,
+    /// Whether the `tracing` layer records the busy and idle time of spans,
+    /// as `busy_ns` and `idle_ns` attributes. Enabled by default.
+    tracked_inactivity: bool,
+    /// Whether the `tracing` layer records the thread name and id of spans. Enabled by default.
+    threads: bool,
+    /// Whether the `tracing` layer records the source location of spans. Enabled by default.
+    location: bool,
+    /// If the span name rewriter is configured, the names of the started spans are rewritten
+    /// by it, e.g. to turn high-cardinality names into route templates.
+    span_name_rewriter: Option<SpanNameRewriter>,
+    /// Renames of span attributes, from `tracing` field names to attribute names,
+    /// applied at export time. Renaming to `otel.name` or `otel.kind` sets the span name
+    /// or kind instead, as these fields do when recorded on `tracing` spans.
+    field_renames: HashMap<String, String>,
+    /// If the sampling rules are configured, they override the sampler of
+    /// `tracer_provider_config`, which remains the fallback for spans matching no rule.
+    sampling_rules: Option<SamplingRules>,
+    /// The callback deciding the sampling before the sampler,
+    /// see [`InitConfig::with_sampling_hook`].
+    #[getset(skip)]
+    sampling_hook: Option<sampling::SamplingHook>,
+    /// If the force-sampling configuration is configured, the requests carrying its header
+    /// are sampled, and their log records exported down to its level,
+    /// see [`ForceSampleConfig`].
+    force_sample_config: Option<ForceSampleConfig>,
+    /// If the baggage verbosity configuration is configured, the requests whose baggage
+    /// carries a `telemetry.level` entry have their log records exported down to that level,
+    /// see [`BaggageVerbosityConfig`].
+    baggage_verbosity_config: Option<BaggageVerbosityConfig>,
+    /// The text map propagators installed globally.
+    /// If empty, the global propagator is left untouched.
+    propagators: Vec<Propagator>,
+    /// The names of the baggage entries that may cross process boundaries.
+    /// If `None`, all entries are propagated by [`Propagator::Baggage`].
+    baggage_allowlist: Option<Vec<String>>,
+    /// If the baggage limits are configured, they are enforced on the baggage injected
+    /// by [`Propagator::Baggage`].
+    baggage_limits: Option<BaggageLimits>,
+    /// The names of the baggage entries copied onto every started span as attributes,
+    /// by a [`BaggageSpanProcessor`]. Disabled by default.
+    baggage_span_attributes: Option<Vec<String>>,
+    /// If the tenant router is configured, the spans and log records of its tenants
+    /// are exported to their own OTLP endpoints instead of the default ones.
+    tenant_router: Option<TenantRouter>,
+    /// The order in which [`shutdown_all_providers`] shuts down the providers of each signal.
+    /// Signals missing from it are shut down last. Defaults to metrics, logs then traces,
+    /// so that the final metric collection cycle is exported before the tracer provider,
+    /// whose exporter the metric pipeline may ride on, is shut down.
+    shutdown_order: Vec<Signal>,
+    /// What [`init_otel`] does when another global `tracing` subscriber has already been set.
+    /// Defaults to [`ConflictPolicy::Error`].
+    conflict_policy: ConflictPolicy,
+    /// Whether literals are stripped from the `db.statement` attribute of [`db`] spans.
+    /// Enabled by default.
+    sanitize_db_statements: bool,
+    /// Whether [`record_feature_flag`] counts the evaluations into the
+    /// `feature_flag.evaluations` counter. Disabled by default.
+    feature_flag_metrics: bool,
+    /// Whether a global OpenTelemetry error handler is installed to count the items dropped by
+    /// the batch processors into [`export_stats`], printing the errors like the default handler.
+    /// It replaces any handler set with `opentelemetry::global::set_error_handler`, which cannot
+    /// be chained to. Disabled by default.
+    dropped_items_stats: bool,
+    /// Whether a `myotel.startup` span, log record and counter increment are emitted right after
+    /// initialization, and a warning logged if they are not accepted by the exporters,
+    /// to detect a broken telemetry pipeline at startup rather than when the telemetry is
+    /// missing. The providers are flushed for it, which delays initialization up to the export
+    /// timeouts. Disabled by default.
+    startup_probe: bool,
+    /// Seed of the deterministic id generator, see [`InitConfig::with_deterministic_ids`].
+    #[getset(skip)]
+    deterministic_ids: Option<u64>,
+    /// Names or routes of the spans to drop, see [`InitConfig::with_suppress_spans`].
+    #[getset(skip)]
+    suppress_spans: Vec<String>,
+    /// Transport of the OTLP exporters, see [`InitConfig::with_otlp_http_client`].
+    #[getset(skip)]
+    otlp_http_client: Option<transport::SharedHttpClient>,
+    /// Authentication of the OTLP exports, see [`InitConfig::with_otlp_auth_provider`].
+    #[getset(skip)]
+    otlp_auth_provider: Option<Arc<dyn AuthProvider>>,
+    /// Length limit of string attribute values, see [`InitConfig::with_attribute_value_limit`].
+    #[getset(skip)]
+    attribute_value_limit: Option<usize>,
+    /// Whether the standard output is colored, see [`InitConfig::with_ansi`].
+    #[getset(skip)]
+    ansi: Option<bool>,
 }
 
 impl InitConfig {
     /// Create a new InitConfig.
     pub fn new() -> Self {
         Self {
+            enabled: true,
             service_name: Default::default(),
             service_version: Default::default(),
+            resource_attributes: Default::default(),
+            service_instance_id: None,
+            schema_url: None,
+            scope_attributes: Vec::new(),
+            fmt_filter: None,
+            otel_log_filter: None,
+            span_filter: None,
             stdout_exporter: cfg!(debug_assertions),
+            exporter_kind: None,
+            traces_endpoint: None,
+            #[cfg(feature = "jaeger-compat")]
+            jaeger_agent_endpoint: None,
+            logs_endpoint: None,
+            metrics_endpoint: None,
+            latency_thresholds: Default::default(),
+            dual_logging: false,
+            otel_log_excluded_targets: logs::DEFAULT_OTEL_LOG_EXCLUDED_TARGETS
+                .iter()
+                .map(|target| target.to_string())
+                .collect(),
+            pull_metric_readers: Vec::new(),
+            metric_views: Default::default(),
             batch_log_config: Default::default(),
+            audit_config: Default::default(),
+            log_metrics_config: Default::default(),
+            log_templates: false,
+            log_sampling_config: None,
+            debug_log_buffer_config: None,
+            trace_summary_config: None,
+            #[cfg(feature = "sentry")]
+            sentry_config: None,
             batch_trace_config: Default::default(),
+            span_queue_config: None,
+            span_slos: None,
             tracer_provider_config: Default::default(),
+            tracked_inactivity: true,
+            threads: true,
+            location: true,
+            span_name_rewriter: Default::default(),
+            field_renames: Default::default(),
+            sampling_rules: Default::default(),
+            sampling_hook: None,
+            force_sample_config: None,
+            baggage_verbosity_config: None,
+            propagators: Default::default(),
+            baggage_allowlist: Default::default(),
+            baggage_limits: None,
+            baggage_span_attributes: Default::default(),
+            tenant_router: Default::default(),
+            shutdown_order: Signal::DEFAULT_SHUTDOWN_ORDER.to_vec(),
+            conflict_policy: ConflictPolicy::Error,
+            sanitize_db_statements: true,
+            feature_flag_metrics: false,
+            dropped_items_stats: false,
+            startup_probe: false,
+            deterministic_ids: None,
+            suppress_spans: Vec::new(),
+            otlp_http_client: None,
+            otlp_auth_provider: None,
+            attribute_value_limit: None,
+            ansi: None,
         }
     }
+
+    /// Create an InitConfig with telemetry disabled: `init_otel` installs no-op providers
+    /// and only a plain `tracing` fmt layer, so that the instrumented code runs unchanged
+    /// without exporting anything. Same as `InitConfig::new().with_enabled(false)`.
+    pub fn disabled() -> Self {
+        Self::new().with_enabled(false)
+    }
+
+    /// Make the telemetry output reproducible across runs, for golden-file tests:
+    /// trace and span ids are generated from `seed` by a [`DeterministicIdGenerator`],
+    /// and the timestamps of exported spans are shifted so that each span starts at the
+    /// Unix epoch, keeping its duration and the offsets of its events.
+    pub fn with_deterministic_ids(mut self, seed: u64) -> Self {
+        self.deterministic_ids = Some(seed);
+        self
+    }
+
+    /// Drop the spans named or routed like one of `patterns`, such as health-check probes:
+    /// a pattern matches a span named `/healthz` or `GET /healthz`, or whose `http.route`
+    /// attribute is `/healthz`. Spans are dropped both by the sampler, and by the tracing
+    /// layer filter for `tracing` spans named after the pattern.
+    pub fn with_suppress_spans<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.suppress_spans = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Decide the sampling of the spans for which `hook` returns a decision, before the
+    /// configured sampler and sampling rules, e.g. to force-sample the requests carrying
+    /// a debug header or from canary tenants. The other spans are sampled by the sampler.
+    /// The hook is kept when the sampler is replaced with [`ReconfigureHandle::set_sampler`].
+    ///
+    /// ```ignore
+    /// let config = default_config!().with_sampling_hook(|params| {
+    ///     let debug = params.attributes.iter().any(|kv| kv.key.as_str() == "http.debug");
+    ///     debug.then_some(SamplingDecision::RecordAndSample)
+    /// });
+    /// ```
+    pub fn with_sampling_hook(
+        mut self,
+        hook: impl Fn(&SamplingParams<'_>) -> Option<SamplingDecision> + Send + Sync + 'static,
+    ) -> Self {
+        self.sampling_hook = Some(sampling::SamplingHook(Arc::new(hook)));
+        self
+    }
+
+    /// Set the `service.instance.id` resource attribute to `instance_id`, e.g. the name of
+    /// the pod. By default, a UUID v7 is generated once per process, unless the attribute
+    /// is already set by the resource attributes or `OTEL_RESOURCE_ATTRIBUTES`.
+    pub fn with_service_instance_id(mut self, instance_id: &str) -> Self {
+        self.service_instance_id = Some(instance_id.to_owned());
+        self
+    }
+
+    /// Set the schema URL of the resource, and of the instrumentation scopes of the tracer,
+    /// meters and loggers created by this crate, for the telemetry to comply with
+    /// a schema validation.
+    pub fn with_schema_url(mut self, schema_url: impl Into<String>) -> Self {
+        self.schema_url = Some(schema_url.into());
+        self
+    }
+
+    /// Set the attributes of the instrumentation scopes of the tracer, meters and loggers
+    /// created by this crate.
+    pub fn with_scope_attributes(mut self, attributes: impl IntoIterator<Item = KeyValue>) -> Self {
+        self.scope_attributes = attributes.into_iter().collect();
+        self
+    }
+
+    /// Filter the events printed by the `tracing` fmt layer with `directives`, in the
+    /// [`EnvFilter`] syntax (e.g. `info,my_crate=debug`), instead of the `RUST_LOG`
+    /// environment variable, which is shared by all layers and defaults to `info`.
+    pub fn with_fmt_filter(mut self, directives: impl Into<String>) -> Self {
+        self.fmt_filter = Some(directives.into());
+        self
+    }
+
+    /// Filter the events bridged into OpenTelemetry log records with `directives`,
+    /// see [`InitConfig::with_fmt_filter`].
+    pub fn with_otel_log_filter(mut self, directives: impl Into<String>) -> Self {
+        self.otel_log_filter = Some(directives.into());
+        self
+    }
+
+    /// Filter the spans recorded as OpenTelemetry spans, and the events recorded as
+    /// their span events, with `directives`, see [`InitConfig::with_fmt_filter`].
+    /// E.g. spans may be recorded at `debug` while the console output stays at `info`.
+    pub fn with_span_filter(mut self, directives: impl Into<String>) -> Self {
+        self.span_filter = Some(directives.into());
+        self
+    }
+
+    /// Do not bridge the events of `targets` into OpenTelemetry log records, to prevent
+    /// the logs of the export pipeline itself from being exported over the same channel,
+    /// in a feedback loop. A target matches itself and its submodules, or with a trailing
+    /// `*`, any target starting with it. Defaults to `opentelemetry*`, `h2`, `tonic`
+    /// and `hyper`; the events are still printed by the `tracing` fmt layer.
+    pub fn with_otel_log_excluded_targets<I, S>(mut self, targets: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.otel_log_excluded_targets = targets.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Register `reader` on the meter provider next to the periodic reader pushing
+    /// the metrics, so that the same instruments feed both, e.g. a Prometheus reader
+    /// for local scraping while the metrics are pushed over OTLP.
+    ///
+    /// ```ignore
+    /// let registry = prometheus::Registry::new();
+    /// let reader = opentelemetry_prometheus::exporter()
+    ///     .with_registry(registry.clone())
+    ///     .build()?;
+    /// init_otel(default_config!().with_pull_metric_reader(reader)).await?;
+    /// ```
+    pub fn with_pull_metric_reader(
+        mut self,
+        reader: impl opentelemetry_sdk::metrics::reader::MetricReader,
+    ) -> Self {
+        self.pull_metric_readers
+            .push(metrics::PullMetricReader(Box::new(reader)));
+        self
+    }
+
+    /// Aggregate all histograms into base-2 exponential histograms rather than explicit
+    /// buckets, with a scale of at most `max_scale` (between -10 and 20) and at most
+    /// `max_buckets` buckets for each of the positive and negative ranges.
+    pub fn with_exponential_histograms(mut self, max_scale: i8, max_buckets: u32) -> Self {
+        self.metric_views.exponential_histograms = Some(metric_views::ExponentialHistogram {
+            max_scale,
+            max_buckets,
+        });
+        self
+    }
+
+    /// Keep only the `attributes` of the measurements of the instrument named `instrument`,
+    /// dropping the others before aggregation, e.g. to strip high-cardinality attributes
+    /// that would otherwise hold aggregation memory.
+    ///
+    /// ```ignore
+    /// let config = default_config!().with_metric_attribute_allowlist(
+    ///     "http.server.duration",
+    ///     ["http.method", "http.status_code"],
+    /// );
+    /// ```
+    pub fn with_metric_attribute_allowlist(
+        mut self,
+        instrument: impl Into<String>,
+        attributes: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Self {
+        self.metric_views.attribute_allowlists.insert(
+            instrument.into(),
+            attributes.into_iter().map(Into::into).collect(),
+        );
+        self
+    }
+
+    /// Aggregate the histogram named `instrument` into a base-2 exponential histogram,
+    /// overriding [`InitConfig::with_exponential_histograms`] for it.
+    pub fn with_exponential_histogram(
+        mut self,
+        instrument: impl Into<String>,
+        max_scale: i8,
+        max_buckets: u32,
+    ) -> Self {
+        self.metric_views.exponential_histogram.insert(
+            instrument.into(),
+            metric_views::ExponentialHistogram {
+                max_scale,
+                max_buckets,
+            },
+        );
+        self
+    }
+
+    /// Forward the spans ending with an error status and the ERROR log records to Sentry,
+    /// see [`SentryConfig`].
+    #[cfg(feature = "sentry")]
+    pub fn with_sentry_config(mut self, sentry_config: SentryConfig) -> Self {
+        self.sentry_config = Some(sentry_config);
+        self
+    }
+
+    /// Export the spans to the Jaeger agent at `endpoint`, e.g. `localhost:6831`, over UDP in
+    /// the Thrift compact protocol, instead of over OTLP, for legacy Jaeger infrastructure.
+    /// The standard output is still used if selected. The log records and metrics are
+    /// exported as configured otherwise.
+    #[cfg(feature = "jaeger-compat")]
+    pub fn with_jaeger_agent_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.jaeger_agent_endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Export over OTLP/HTTP with JSON encoding through `client`, instead of over gRPC,
+    /// for environments where tonic cannot run, e.g. with a `fetch`-based client on
+    /// edge runtimes.
+    pub fn with_otlp_http_client(mut self, client: impl HttpClient + 'static) -> Self {
+        self.otlp_http_client = Some(transport::SharedHttpClient::new(client));
+        self
+    }
+
+    /// Add the headers of `auth_provider` to each OTLP export request, e.g. to renew
+    /// expiring tokens, as HTTP headers or as gRPC metadata.
+    pub fn with_otlp_auth_provider(mut self, auth_provider: impl AuthProvider + 'static) -> Self {
+        self.otlp_auth_provider = Some(Arc::new(auth_provider));
+        self
+    }
+
+    /// Select the exporter of all signals, overriding [`InitConfig::with_stdout_exporter`].
+    pub fn with_exporter_kind(mut self, exporter_kind: ExporterKind) -> Self {
+        self.stdout_exporter = exporter_kind != ExporterKind::Otlp;
+        self.exporter_kind = Some(exporter_kind);
+        self
+    }
+
+    /// The exporter of all signals, derived from `stdout_exporter` unless selected.
+    fn exporter_kind(&self) -> ExporterKind {
+        match self.exporter_kind {
+            Some(ExporterKind::StdoutTree) if self.stdout_exporter => ExporterKind::StdoutTree,
+            _ if self.stdout_exporter => ExporterKind::Stdout,
+            _ => ExporterKind::Otlp,
+        }
+    }
+
+    /// Truncate the string attribute values of spans and span events longer than
+    /// `limit` bytes at export time, such as SQL statements or payload dumps.
+    /// A truncated value ends with `...{truncated byte count}`, and its span or event
+    /// gets a `truncated=true` attribute.
+    pub fn with_attribute_value_limit(mut self, limit: usize) -> Self {
+        self.attribute_value_limit = Some(limit);
+        self
+    }
+
+    /// Whether the logs and spans printed to the standard output are colored with ANSI
+    /// escape codes. If not set, they are unless the standard output is not a terminal,
+    /// or the `NO_COLOR` or `CI` environment variable is set.
+    pub fn with_ansi(mut self, ansi: bool) -> Self {
+        self.ansi = Some(ansi);
+        self
+    }
+
+    /// Whether the standard output is colored, see [`InitConfig::with_ansi`].
+    fn ansi(&self) -> bool {
+        self.ansi.unwrap_or_else(|| {
+            std::io::stdout().is_terminal()
+                && std::env::var_os("NO_COLOR").map_or(true, |no_color| no_color.is_empty())
+                && std::env::var_os("CI").is_none()
+        })
+    }
+}
+
+impl Default for InitConfig {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Create the default InitConfig.
+///
+/// With `default_config!(with_build_info)`, the build of the calling crate is also recorded
+/// into the resource attributes: its repository, its git commit from the `VERGEN_GIT_SHA`
+/// environment variable, the rustc version from `VERGEN_RUSTC_SEMVER`, and the build profile.
+/// The `VERGEN_*` variables are typically set by a build script using `vergen`, and skipped
+/// when missing.
 #[macro_export]
 macro_rules! default_config {
     () => {
@@ -197,18 +839,434 @@ macro_rules! default_config {
             .with_service_name(env!("CARGO_PKG_NAME").to_owned())
             .with_service_version(env!("CARGO_PKG_VERSION").to_owned())
     };
+    (with_build_info) => {
+        $crate::default_config!().with_resource_attributes($crate::build_info_attributes(
+            option_env!("CARGO_PKG_REPOSITORY"),
+            option_env!("VERGEN_GIT_SHA"),
+            option_env!("VERGEN_RUSTC_SEMVER"),
+            if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            },
+        ))
+    };
+}
+
+/// The resource attributes of `default_config!(with_build_info)`, skipping the empty values.
+#[doc(hidden)]
+pub fn build_info_attributes(
+    repository: Option<&'static str>,
+    git_sha: Option<&'static str>,
+    rustc_version: Option<&'static str>,
+    profile: &'static str,
+) -> Vec<KeyValue> {
+    let mut attributes = vec![KeyValue::new("build.profile", profile)];
+    for (key, value) in [
+        ("vcs.repository.url.full", repository),
+        ("vcs.repository.ref.revision", git_sha),
+        ("process.runtime.version", rustc_version),
+    ] {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            attributes.push(KeyValue::new(key, value));
+        }
+    }
+    if rustc_version.is_some_and(|value| !value.is_empty()) {
+        attributes.push(KeyValue::new("process.runtime.name", "rustc"));
+    }
+    attributes
 }
 
+/// The exporter of all signals, see [`InitConfig::with_exporter_kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExporterKind {
+    /// Print the telemetry to the standard output, spans one by one.
+    Stdout,
+    /// Print the telemetry to the standard output, spans as trees with a [`TreeExporter`].
+    StdoutTree,
+    /// Export the telemetry over OTLP.
+    Otlp,
+}
+
+/// What [`init_otel`] does when another global `tracing` subscriber has already been set,
+/// e.g. by a dependency, see [`InitConfig::with_conflict_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Fail before registering any provider.
+    #[default]
+    Error,
+    /// Register the providers, so that the OpenTelemetry APIs and the instrumentations of
+    /// this crate still export, but warn and leave the existing subscriber in place.
+    /// `tracing` spans and events are then not exported.
+    WarnAndSkipSubscriber,
+    /// Register the providers, and replace the existing subscriber where possible:
+    /// as the global default of `tracing` cannot be replaced once set, the subscriber is
+    /// instead set as the default of the calling thread for its whole lifetime,
+    /// which covers current-thread runtimes. Other threads keep the existing subscriber.
+    ForceReplace,
+}
+
+/// A telemetry signal, see [`InitConfig::with_shutdown_order`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// The logger providers, including the audit one.
+    Logs,
+    /// The meter provider.
+    Metrics,
+    /// The tracer provider.
+    Traces,
+}
+
+impl Signal {
+    const DEFAULT_SHUTDOWN_ORDER: [Signal; 3] = [Signal::Metrics, Signal::Logs, Signal::Traces];
+}
+
+static SHUTDOWN_ORDER: OnceLock<Vec<Signal>> = OnceLock::new();
+
 static INIT: Mutex<bool> = Mutex::new(false);
 
+static RECONFIGURE_HANDLE: OnceLock<ReconfigureHandle> = OnceLock::new();
+
 /// Initialize OpenTelemetry.
+///
+/// Returns `false` if OpenTelemetry has already been initialized. If another global `tracing`
+/// subscriber has already been set, see [`InitConfig::with_conflict_policy`].
 pub async fn init_otel(init_config: InitConfig) -> anyhow::Result<bool> {
     let mut guard = INIT.lock().unwrap();
     if *guard {
         return Ok(false);
     }
+    let conflict_policy = init_config.conflict_policy;
+    if conflict_policy == ConflictPolicy::Error
+        && tracing::dispatcher::has_been_set()
+        && !pre_init::is_installed()
+    {
+        anyhow::bail!("a global default trace dispatcher has already been set");
+    }
+    let startup_probe = init_config.startup_probe;
+    let (layers, handle) = init::<Registry>(init_config)?;
+    let dispatch = Dispatch::new(tracing_subscriber::registry().with(layers));
+    // The pre-initialization buffer is the global subscriber, and forwards to this one.
+    if !pre_init::forward_to(&dispatch) {
+        if let Err(err) = tracing::dispatcher::set_global_default(dispatch.clone()) {
+            match conflict_policy {
+                ConflictPolicy::Error => return Err(err.into()),
+                ConflictPolicy::WarnAndSkipSubscriber => {
+                    tracing::warn!(
+                        "a global tracing subscriber is already set, \
+                         tracing spans and events are not exported by OpenTelemetry"
+                    );
+                }
+                ConflictPolicy::ForceReplace => {
+                    tracing::warn!(
+                        "a global tracing subscriber is already set, \
+                         replacing it on the current thread only"
+                    );
+                    std::mem::forget(tracing::dispatcher::set_default(&dispatch));
+                }
+            }
+        }
+    }
+    if let Some(handle) = handle {
+        let _ = RECONFIGURE_HANDLE.set(handle);
+    }
     *guard = true;
+    drop(guard);
+    if startup_probe {
+        startup_probe::run().await;
+    }
+    Ok(true)
+}
 
+/// The handle to re-configure the providers installed by [`init_otel`] at runtime,
+/// or `None` if OpenTelemetry has not been initialized yet or is disabled.
+pub fn reconfigure_handle() -> Option<&'static ReconfigureHandle> {
+    RECONFIGURE_HANDLE.get()
+}
+
+/// Initialize OpenTelemetry like [`init_otel`], but return the composed subscriber instead of
+/// installing it as the global default, for applications embedding several subscribers or
+/// scoping one per task (e.g. with `tracing::subscriber::with_default` or
+/// `WithSubscriber::with_subscriber`).
+///
+/// The providers are still registered globally, so that this may only be called once,
+/// instead of `init_otel`. They are shut down when the returned guard is dropped.
+pub async fn init_scoped_otel(
+    init_config: InitConfig,
+) -> anyhow::Result<(impl Subscriber + Send + Sync, OtelGuard)> {
+    let mut guard = INIT.lock().unwrap();
+    if *guard {
+        anyhow::bail!("OpenTelemetry has already been initialized");
+    }
+    let startup_probe = init_config.startup_probe;
+    let (layers, handle) = init::<Registry>(init_config)?;
+    *guard = true;
+    drop(guard);
+    if startup_probe {
+        startup_probe::run().await;
+    }
+    Ok((
+        tracing_subscriber::registry().with(layers),
+        OtelGuard { handle },
+    ))
+}
+
+/// Initialize OpenTelemetry like [`init_scoped_otel`], but return its layers instead of
+/// the composed subscriber, for applications building their own subscriber to splice them in:
+///
+/// ```ignore
+/// let (layers, _guard) = myotel::layers(default_config!()).await?;
+/// tracing_subscriber::registry()
+///     .with(my_layer)
+///     .with(layers)
+///     .init();
+/// ```
+///
+/// The layers carry their own filters, see [`InitConfig::with_fmt_filter`].
+pub async fn layers<S>(init_config: InitConfig) -> anyhow::Result<(Vec<BoxLayer<S>>, OtelGuard)>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    let mut guard = INIT.lock().unwrap();
+    if *guard {
+        anyhow::bail!("OpenTelemetry has already been initialized");
+    }
+    let startup_probe = init_config.startup_probe;
+    let (layers, handle) = init(init_config)?;
+    *guard = true;
+    drop(guard);
+    if startup_probe {
+        startup_probe::run().await;
+    }
+    Ok((layers, OtelGuard { handle }))
+}
+
+/// Shuts down all providers when dropped, see [`init_scoped_otel`] and [`layers`].
+#[derive(Debug)]
+pub struct OtelGuard {
+    handle: Option<ReconfigureHandle>,
+}
+
+impl OtelGuard {
+    /// The reconfiguration handle, `None` if the configuration is disabled.
+    pub fn handle(&self) -> Option<&ReconfigureHandle> {
+        self.handle.as_ref()
+    }
+}
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        shutdown_all_providers();
+    }
+}
+
+/// Initialize OpenTelemetry with `init_config` for a single test, for test suites using
+/// a different configuration per test, e.g. across `#[tokio::test]` functions.
+///
+/// Unlike [`init_otel`], the providers are built for the test alone rather than registered
+/// globally, so that tests running in parallel keep their own telemetry, and it may be called
+/// any number of times, before or after `init_otel`. Their subscriber is set as the default
+/// of the current thread until the returned guard is dropped, which covers the default
+/// current-thread runtime of `#[tokio::test]`; on a multi-thread runtime, the tasks spawned by
+/// the test are instrumented with [`OtelTestGuard::dispatch`] (e.g. with
+/// `WithSubscriber::with_subscriber`). The providers are shut down when the guard is dropped.
+///
+/// The process-wide settings of `init_config` (e.g. the propagators, the OTLP HTTP client or
+/// the audit channel) are not applied, and the helpers of this crate using the global
+/// providers (e.g. [`tracer`], [`audit!`] or the metrics of [`middleware`]) keep using those
+/// of `init_otel`, if any; the test can use the providers of the guard instead.
+pub async fn reinit_otel_for_tests(mut init_config: InitConfig) -> anyhow::Result<OtelTestGuard> {
+    if !init_config.enabled {
+        let dispatch = Dispatch::new(tracing_subscriber::registry().with(fmt_layers::<Registry>(
+            init_config.fmt_filter.as_deref(),
+            init_config.ansi(),
+        )?));
+        return Ok(OtelTestGuard {
+            _default: tracing::dispatcher::set_default(&dispatch),
+            dispatch,
+            tracer_provider: None,
+            logger_provider: None,
+            meter_provider: None,
+            handle: None,
+        });
+    }
+    let (resource, scope) = resource_and_scope(&mut init_config);
+    let (meter_provider, metric_reader) = metrics::init_metrics(
+        init_config.stdout_exporter,
+        init_config.metrics_endpoint.as_deref(),
+        std::mem::take(&mut init_config.pull_metric_readers),
+        std::mem::take(&mut init_config.metric_views),
+        resource.clone(),
+    )?;
+    let pipeline = init_logs_and_trace::<Registry>(init_config, &resource, &scope)?;
+    let handle = ReconfigureHandle::new(
+        pipeline.sampler,
+        pipeline.span_exporter,
+        pipeline.log_exporter,
+        metric_reader,
+        resource,
+    );
+    let dispatch = Dispatch::new(tracing_subscriber::registry().with(pipeline.layers));
+    Ok(OtelTestGuard {
+        _default: tracing::dispatcher::set_default(&dispatch),
+        dispatch,
+        tracer_provider: Some(pipeline.tracer_provider),
+        logger_provider: pipeline.logger_provider,
+        meter_provider: Some(meter_provider),
+        handle: Some(handle),
+    })
+}
+
+/// The telemetry of a test, see [`reinit_otel_for_tests`].
+#[derive(Debug)]
+pub struct OtelTestGuard {
+    dispatch: Dispatch,
+    _default: tracing::dispatcher::DefaultGuard,
+    tracer_provider: Option<TracerProvider>,
+    logger_provider: Option<LoggerProvider>,
+    meter_provider: Option<SdkMeterProvider>,
+    handle: Option<ReconfigureHandle>,
+}
+
+impl OtelTestGuard {
+    /// The subscriber of the test, to instrument the tasks it spawns on other threads.
+    pub fn dispatch(&self) -> &Dispatch {
+        &self.dispatch
+    }
+
+    /// The tracer provider of the test, `None` if the configuration is disabled.
+    pub fn tracer_provider(&self) -> Option<&TracerProvider> {
+        self.tracer_provider.as_ref()
+    }
+
+    /// The logger provider of the test, `None` if the log records are not exported through
+    /// OpenTelemetry or the configuration is disabled.
+    pub fn logger_provider(&self) -> Option<&LoggerProvider> {
+        self.logger_provider.as_ref()
+    }
+
+    /// The meter provider of the test, `None` if the configuration is disabled.
+    pub fn meter_provider(&self) -> Option<&SdkMeterProvider> {
+        self.meter_provider.as_ref()
+    }
+
+    /// The reconfiguration handle of the providers of the test,
+    /// `None` if the configuration is disabled.
+    pub fn handle(&self) -> Option<&ReconfigureHandle> {
+        self.handle.as_ref()
+    }
+}
+
+impl Drop for OtelTestGuard {
+    fn drop(&mut self) {
+        let tracer_provider = self.tracer_provider.take();
+        let logger_provider = self.logger_provider.take();
+        let meter_provider = self.meter_provider.take();
+        // Shutting down blocks on the export tasks, which may need the current thread to make
+        // progress (e.g. on a current-thread runtime), so it must not be waited for here.
+        std::thread::spawn(move || {
+            if let Some(logger_provider) = logger_provider {
+                let _ = logger_provider.shutdown();
+            }
+            if let Some(tracer_provider) = tracer_provider {
+                let _ = tracer_provider.shutdown();
+            }
+            if let Some(meter_provider) = meter_provider {
+                let _ = meter_provider.shutdown();
+            }
+        });
+    }
+}
+
+/// A layer returned by [`layers`].
+pub type BoxLayer<S> = Box<dyn Layer<S> + Send + Sync>;
+
+/// Build and register the providers, and return the layers of the subscriber to install.
+fn init<S>(
+    mut init_config: InitConfig,
+) -> anyhow::Result<(Vec<BoxLayer<S>>, Option<ReconfigureHandle>)>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    let _ = SHUTDOWN_ORDER.set(std::mem::take(&mut init_config.shutdown_order));
+    ENABLED.store(init_config.enabled, Ordering::Relaxed);
+    if !init_config.enabled {
+        snapshot::init_snapshot(&init_config);
+        return Ok((init_disabled(init_config)?, None));
+    }
+
+    let (resource, scope) = resource_and_scope(&mut init_config);
+    let _ = RESOURCE.set(resource.clone());
+    let _ = SCOPE.set(scope.clone());
+    #[cfg(feature = "sentry")]
+    let _ = sentry::SENTRY_CONFIG.set(init_config.sentry_config.take());
+
+    propagation::init_propagators(
+        &init_config.propagators,
+        init_config.baggage_allowlist.as_deref(),
+        init_config.baggage_limits,
+    );
+    db::set_sanitize_statements(init_config.sanitize_db_statements);
+    feature_flag::set_record_metrics(init_config.feature_flag_metrics);
+    force_sample::set_config(init_config.force_sample_config.clone());
+    verbosity::set_config(init_config.baggage_verbosity_config.clone());
+    if init_config.dropped_items_stats {
+        stats::init_error_handler()?;
+    }
+    transport::set_otlp_http_client(
+        init_config
+            .otlp_http_client
+            .clone()
+            .map(|client| client.with_auth_provider(init_config.otlp_auth_provider.clone())),
+    );
+    #[cfg(not(feature = "wasm"))]
+    transport::set_otlp_auth_provider(init_config.otlp_auth_provider.clone());
+    snapshot::init_snapshot(&init_config);
+
+    // Metrics are initialized first, so that the logs pipeline can record into them.
+    let (meter_provider, metric_reader) = metrics::init_metrics(
+        init_config.stdout_exporter,
+        init_config.metrics_endpoint.as_deref(),
+        std::mem::take(&mut init_config.pull_metric_readers),
+        std::mem::take(&mut init_config.metric_views),
+        resource.clone(),
+    )?;
+    metrics::register_meter_provider(meter_provider);
+    audit::init_audit(
+        init_config.stdout_exporter,
+        std::mem::take(&mut init_config.audit_config),
+        resource.clone(),
+    )?;
+    let pipeline = init_logs_and_trace(init_config, &resource, &scope)?;
+    trace::register_trace(pipeline.tracer, pipeline.tracer_provider);
+    if let Some(logger_provider) = pipeline.logger_provider {
+        logs::register_logger_provider(logger_provider);
+    }
+
+    let handle = ReconfigureHandle::new(
+        pipeline.sampler,
+        pipeline.span_exporter,
+        pipeline.log_exporter,
+        metric_reader,
+        resource,
+    );
+    Ok((pipeline.layers, Some(handle)))
+}
+
+/// The layers and the providers of the logs and trace pipelines, before they are registered.
+struct LogsAndTrace<S> {
+    layers: Vec<BoxLayer<S>>,
+    tracer: Tracer,
+    tracer_provider: TracerProvider,
+    sampler: ReloadableSampler,
+    span_exporter: ReloadableSpanExporter,
+    logger_provider: Option<LoggerProvider>,
+    log_exporter: Option<ReloadableLogExporter>,
+}
+
+/// The resource shared by all providers, and the scope of the instrumentation scopes,
+/// from `init_config`.
+fn resource_and_scope(init_config: &mut InitConfig) -> (Resource, ScopeConfig) {
     let mut kvs = vec![KeyValue::new(
         semantic_conventions::resource::TELEMETRY_SDK_LANGUAGE,
         "rust",
@@ -225,68 +1283,228 @@ pub async fn init_otel(init_config: InitConfig) -> anyhow::Result<bool> {
             init_config.service_version.clone(),
         ));
     }
-    RESOURCE
-        .set(Resource::default().merge(&Resource::new(kvs)))
-        .unwrap();
+    kvs.extend(init_config.resource_attributes.iter().cloned());
+    let instance_id_key = semantic_conventions::resource::SERVICE_INSTANCE_ID;
+    if let Some(instance_id) = init_config.service_instance_id.take() {
+        kvs.push(KeyValue::new(instance_id_key, instance_id));
+    } else if !kvs.iter().any(|kv| kv.key.as_str() == instance_id_key)
+        && Resource::default().get(Key::new(instance_id_key)).is_none()
+    {
+        kvs.push(KeyValue::new(instance_id_key, service_instance_id()));
+    }
+    let resource = match &init_config.schema_url {
+        Some(schema_url) => Resource::from_schema_url(kvs, schema_url.clone()),
+        None => Resource::new(kvs),
+    };
+    let scope = ScopeConfig {
+        schema_url: init_config.schema_url.clone(),
+        attributes: std::mem::take(&mut init_config.scope_attributes),
+    };
+    (Resource::default().merge(&resource), scope)
+}
 
-    init_logs_and_trace(
-        init_config.service_name,
-        init_config.service_version,
-        init_config.stdout_exporter,
-        init_config.batch_log_config,
-        init_config.batch_trace_config,
+fn init_logs_and_trace<S>(
+    init_config: InitConfig,
+    resource: &Resource,
+    scope: &ScopeConfig,
+) -> anyhow::Result<LogsAndTrace<S>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    let span_filter = layer_filter(init_config.span_filter.as_deref())?;
+    let fmt_filter = layer_filter(init_config.fmt_filter.as_deref())?;
+    // The log records of the requests raising their verbosity pass either filter.
+    let max_request_level = verbosity::max_request_level(
         init_config
-            .tracer_provider_config
-            .with_resource(RESOURCE.get().unwrap().clone()),
-    )?;
-    metrics::init_metrics(init_config.stdout_exporter)?;
-
-    Ok(true)
-}
+            .force_sample_config
+            .as_ref()
+            .map(ForceSampleConfig::log_level),
+        init_config.baggage_verbosity_config.as_ref(),
+    );
+    let request_log_filter = filter_fn(|metadata| verbosity::request_enabled(metadata.level()))
+        .with_max_level_hint(max_request_level);
+    let otel_log_filter =
+        layer_filter(init_config.otel_log_filter.as_deref())?.or(request_log_filter);
 
-fn init_logs_and_trace(
-    service_name: String,
-    service_version: String,
-    use_stdout_exporter: bool,
-    batch_log_config: Option<BatchLogConfig>,
-    batch_trace_config: Option<BatchTraceConfig>,
-    tracer_provider_config: TracerProviderConfig,
-) -> anyhow::Result<()> {
-    let env_filter_layer =
-        EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
+    let use_stdout_exporter = init_config.stdout_exporter;
+    let stdout_tree = init_config.exporter_kind() == ExporterKind::StdoutTree;
+    let ansi = init_config.ansi();
+    let mut tracer_provider_config = init_config
+        .tracer_provider_config
+        .with_resource(resource.clone());
+    if let Some(seed) = init_config.deterministic_ids {
+        tracer_provider_config =
+            tracer_provider_config.with_id_generator(DeterministicIdGenerator::new(seed));
+    }
+    let suppress_spans: Arc<[String]> = init_config.suppress_spans.into();
 
-    let tracer = trace::init_trace(
-        service_name,
-        service_version,
+    let baggage_span_attributes = match init_config.tenant_router {
+        Some(_) => Some(tenant::with_tenant_baggage(
+            init_config.baggage_span_attributes,
+        )),
+        None => init_config.baggage_span_attributes,
+    };
+    let (tracer, tracer_provider, sampler, span_exporter) = trace::init_trace(
+        init_config.service_name,
+        init_config.service_version,
         use_stdout_exporter,
-        batch_trace_config,
+        init_config.traces_endpoint.as_deref(),
+        init_config.batch_trace_config,
+        init_config.span_queue_config,
         tracer_provider_config,
+        match init_config.force_sample_config {
+            Some(_) => Some(force_sample::with_force_sampling(init_config.sampling_hook)),
+            None => init_config.sampling_hook,
+        },
+        init_config.sampling_rules,
+        suppress_spans.clone(),
+        trace::SpanExportOptions {
+            fixed_clock: init_config.deterministic_ids.is_some(),
+            attribute_value_limit: init_config.attribute_value_limit,
+            field_renames: init_config.field_renames,
+            tenant_router: init_config.tenant_router.clone(),
+            stdout_tree,
+            ansi,
+            latency_thresholds: init_config
+                .latency_thresholds
+                .filter(|_| use_stdout_exporter),
+            #[cfg(feature = "jaeger-compat")]
+            jaeger_agent_endpoint: init_config.jaeger_agent_endpoint,
+        },
+        baggage_span_attributes,
+        init_config.span_name_rewriter,
+        init_config.span_slos,
+        scope,
     )?;
-    let tracer_layer = OpenTelemetryLayer::new(tracer);
-
-    let subscriber = tracing_subscriber::registry()
-        .with(env_filter_layer)
-        .with(tracer_layer);
+    let tracer_layer = OpenTelemetryLayer::new(tracer.clone())
+        .with_tracked_inactivity(init_config.tracked_inactivity)
+        .with_threads(init_config.threads)
+        .with_location(init_config.location)
+        .with_filter(filter_fn(move |metadata| {
+            !metadata.is_span() || !sampling::is_suppressed(&suppress_spans, metadata.name(), None)
+        }))
+        .with_filter(span_filter);
+    let mut layers: Vec<BoxLayer<S>> = Vec::new();
+    if let Some(trace_summary_config) = init_config.trace_summary_config {
+        // Comes first, so that the root spans still hold their OpenTelemetry data on close.
+        let summary_filter = layer_filter(init_config.span_filter.as_deref())?;
+        let summary_layer =
+            trace_summary::TraceSummaryLayer::new(trace_summary_config).with_filter(summary_filter);
+        layers.push(Box::new(summary_layer));
+    }
+    layers.push(Box::new(tracer_layer));
 
-    if use_stdout_exporter {
+    if use_stdout_exporter || init_config.dual_logging {
         let fmt_layer = tracing_subscriber::fmt::layer()
             .with_target(true)
             .with_file(true)
             .with_line_number(true)
             .with_thread_ids(true)
-            .pretty();
-        tracing::subscriber::set_global_default(subscriber.with(fmt_layer))?;
-    } else {
-        let logger_layer = logs::init_logs(use_stdout_exporter, batch_log_config)?;
-        tracing::subscriber::set_global_default(subscriber.with(logger_layer))?;
+            .with_ansi(ansi)
+            .pretty()
+            .with_filter(fmt_filter);
+        layers.push(Box::new(fmt_layer));
     }
+    let logs = if !use_stdout_exporter || init_config.dual_logging {
+        // With dual logging, the log records are exported over OTLP even with the standard
+        // output exporter, as `fmt_layer` already prints them.
+        let (logger_layer, logger_provider, log_exporter) = logs::init_logs(
+            false,
+            init_config.logs_endpoint.as_deref(),
+            init_config.batch_log_config,
+            init_config.log_metrics_config,
+            init_config.log_templates,
+            init_config.tenant_router.as_ref(),
+            resource.clone(),
+        )?;
+        Some((logger_layer, logger_provider, Some(log_exporter)))
+    } else {
+        // The log records are printed by `fmt_layer`, but still go through their processors.
+        logs::init_log_processors(init_config.log_metrics_config, resource.clone())
+            .map(|(logger_layer, logger_provider)| (logger_layer, logger_provider, None))
+    };
+    let (logger_provider, log_exporter) = match logs {
+        Some((logger_layer, logger_provider, log_exporter)) => {
+            let excluded_targets = init_config.otel_log_excluded_targets;
+            let log_sampling_config = init_config.log_sampling_config;
+            let log_buffer_config = init_config.debug_log_buffer_config;
+            let logger_layer = logs::ReentrancyGuardLayer(logger_layer);
+            let logger_layer =
+                log_sampling::LogSamplingLayer::new(logger_layer, log_sampling_config);
+            let logger_layer =
+                log_buffer::DebugLogBufferLayer::new(logger_layer, log_buffer_config)
+                    .with_filter(filter_fn(move |metadata| {
+                        !logs::is_excluded_target(&excluded_targets, metadata.target())
+                    }))
+                    .with_filter(otel_log_filter);
+            layers.push(Box::new(logger_layer));
+            (Some(logger_provider), log_exporter)
+        }
+        None => (None, None),
+    };
+    Ok(LogsAndTrace {
+        layers,
+        tracer,
+        tracer_provider,
+        sampler,
+        span_exporter,
+        logger_provider,
+        log_exporter,
+    })
+}
+
+fn init_disabled<S>(init_config: InitConfig) -> anyhow::Result<Vec<BoxLayer<S>>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    let fmt_layers = fmt_layers(init_config.fmt_filter.as_deref(), init_config.ansi())?;
+    metrics::init_noop_metrics();
+    logs::init_noop_logs();
+    trace::init_noop_trace(init_config.service_name, init_config.service_version);
+    Ok(fmt_layers)
+}
 
-    Ok(())
+/// The layers of a disabled configuration, printing the events to the standard output.
+fn fmt_layers<S>(fmt_filter: Option<&str>, ansi: bool) -> anyhow::Result<Vec<BoxLayer<S>>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+{
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_target(true)
+        .with_ansi(ansi)
+        .with_filter(layer_filter(fmt_filter)?);
+    Ok(vec![Box::new(fmt_layer)])
 }
 
-/// Shut down the current logger, tracer and meter providers.
+/// The filter of a layer, from its `directives` if configured,
+/// or else from the `RUST_LOG` environment variable, defaulting to `info`.
+fn layer_filter(directives: Option<&str>) -> anyhow::Result<EnvFilter> {
+    match directives {
+        Some(directives) => Ok(EnvFilter::try_new(directives)?),
+        None => Ok(EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?),
+    }
+}
+
+/// Shut down the current logger, audit logger, tracer and meter providers,
+/// in the order of [`InitConfig::with_shutdown_order`].
+///
+/// The meter provider is force-flushed, and its observable callbacks drained,
+/// before being shut down, so that the final collection cycle is exported in full.
 pub fn shutdown_all_providers() {
-    logs::shutdown_logger_provider();
-    global::shutdown_tracer_provider();
-    metrics::shutdown_meter_provider();
+    let order = SHUTDOWN_ORDER.get().map_or(&[][..], Vec::as_slice);
+    let missing = Signal::DEFAULT_SHUTDOWN_ORDER
+        .into_iter()
+        .filter(|signal| !order.contains(signal));
+    for signal in order.iter().copied().chain(missing) {
+        match signal {
+            Signal::Logs => {
+                logs::shutdown_logger_provider();
+                if let Some(audit_logger_provider) = audit::AUDIT_LOGGER_PROVIDER.get() {
+                    let _ = audit_logger_provider.shutdown();
+                }
+            }
+            Signal::Metrics => metrics::shutdown_meter_provider(),
+            Signal::Traces => global::shutdown_tracer_provider(),
+        }
+    }
 }
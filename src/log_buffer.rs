@@ -0,0 +1,266 @@
+use crate::{log_sampling, logs};
+
+use opentelemetry::logs::{LogResult, Logger as _, LoggerProvider as _};
+use opentelemetry::InstrumentationLibrary;
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+use opentelemetry_sdk::logs::{LogProcessor, LogRecord, Logger, LoggerProvider};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Configuration of the debug log buffer, which holds back the DEBUG and TRACE records of
+/// each unsampled trace, and exports them only if the trace errors before its root span
+/// closes, see
+/// [`InitConfig::with_debug_log_buffer_config`](crate::InitConfig::with_debug_log_buffer_config).
+///
+/// A trace errors with an ERROR record, or a span whose `otel.status_code` field is `ERROR`.
+/// The records of sampled traces, and those emitted outside any span, are exported directly.
+/// The DEBUG records must still be enabled by the filter of the log layer,
+/// see [`InitConfig::with_otel_log_filter`](crate::InitConfig::with_otel_log_filter).
+#[derive(Debug, Clone, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct DebugLogBufferConfig {
+    /// The maximum number of records buffered per trace, beyond which the oldest ones are
+    /// dropped. Defaults to 1000.
+    max_records_per_trace: usize,
+    /// The maximum number of traces buffered at once, beyond which the records of new traces
+    /// are dropped. Defaults to 1024.
+    max_traces: usize,
+}
+
+impl Default for DebugLogBufferConfig {
+    fn default() -> Self {
+        Self {
+            max_records_per_trace: 1000,
+            max_traces: 1024,
+        }
+    }
+}
+
+/// Wraps the log bridge layer so that the debug records are buffered per trace
+/// according to a [`DebugLogBufferConfig`], or bridged directly without configuration.
+pub(crate) struct DebugLogBufferLayer<L> {
+    inner: L,
+    buffer: Option<DebugLogBuffer>,
+}
+
+impl<L> DebugLogBufferLayer<L> {
+    pub(crate) fn new(inner: L, config: Option<DebugLogBufferConfig>) -> Self {
+        Self {
+            inner,
+            buffer: config.map(DebugLogBuffer::new),
+        }
+    }
+}
+
+impl<S, L> Layer<S> for DebugLogBufferLayer<L>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    L: Layer<S>,
+{
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.enabled(metadata, ctx)
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(buffer) = &self.buffer {
+            let mut visitor = ErrorStatusVisitor(false);
+            attrs.record(&mut visitor);
+            if visitor.0 {
+                buffer.mark_errored(id, &ctx);
+            }
+        }
+        self.inner.on_new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(buffer) = &self.buffer {
+            let mut visitor = ErrorStatusVisitor(false);
+            values.record(&mut visitor);
+            if visitor.0 {
+                buffer.mark_errored(id, &ctx);
+            }
+        }
+        self.inner.on_record(id, values, ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        match &self.buffer {
+            Some(buffer) => buffer.on_event(&self.inner, event, ctx),
+            None => self.inner.on_event(event, ctx),
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        if let Some(buffer) = &self.buffer {
+            if ctx.span(&id).is_some_and(|span| span.parent().is_none()) {
+                buffer.traces.lock().unwrap().remove(&id);
+            }
+        }
+        self.inner.on_close(id, ctx);
+    }
+}
+
+struct DebugLogBuffer {
+    config: DebugLogBufferConfig,
+    /// Bridges the buffered events into records, captured by a [`CaptureLogProcessor`].
+    capture: OpenTelemetryTracingBridge<LoggerProvider, Logger>,
+    /// The buffered records, by root span of their trace.
+    traces: Mutex<HashMap<Id, TraceLogs>>,
+}
+
+#[derive(Default)]
+struct TraceLogs {
+    errored: bool,
+    records: VecDeque<(LogRecord, InstrumentationLibrary)>,
+}
+
+impl DebugLogBuffer {
+    fn new(config: DebugLogBufferConfig) -> Self {
+        let capture_provider = LoggerProvider::builder()
+            .with_log_processor(CaptureLogProcessor)
+            .build();
+        Self {
+            config,
+            capture: OpenTelemetryTracingBridge::new(&capture_provider),
+            traces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn on_event<S, L>(&self, inner: &L, event: &Event<'_>, ctx: Context<'_, S>)
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+        L: Layer<S>,
+    {
+        let root = ctx
+            .event_scope(event)
+            .and_then(|scope| scope.from_root().next())
+            .map(|root| root.id());
+        let Some(root) = root.filter(|_| !logs::is_in_log_bridge()) else {
+            inner.on_event(event, ctx);
+            return;
+        };
+        let level = *event.metadata().level();
+        if level == Level::ERROR {
+            self.flush(root);
+            inner.on_event(event, ctx);
+            return;
+        }
+        if level < Level::DEBUG || log_sampling::current_trace(&ctx).1 || self.is_errored(&root) {
+            inner.on_event(event, ctx);
+            return;
+        }
+        self.capture.on_event(event, ctx);
+        let captured = CAPTURED.with(RefCell::take);
+        let mut traces = self.traces.lock().unwrap();
+        if !traces.contains_key(&root) && traces.len() >= self.config.max_traces {
+            return;
+        }
+        let records = &mut traces.entry(root).or_default().records;
+        for record in captured {
+            if records.len() >= self.config.max_records_per_trace {
+                records.pop_front();
+            }
+            records.push_back(record);
+        }
+    }
+
+    fn is_errored(&self, root: &Id) -> bool {
+        self.traces
+            .lock()
+            .unwrap()
+            .get(root)
+            .is_some_and(|trace| trace.errored)
+    }
+
+    /// Mark the trace of the span `id` as errored.
+    fn mark_errored<S>(&self, id: &Id, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let root = ctx
+            .span(id)
+            .and_then(|span| span.scope().from_root().next())
+            .map(|root| root.id());
+        if let Some(root) = root {
+            self.flush(root);
+        }
+    }
+
+    /// Export the buffered records of the trace of `root`, and then its records directly.
+    fn flush(&self, root: Id) {
+        let records = {
+            let mut traces = self.traces.lock().unwrap();
+            let trace = traces.entry(root).or_default();
+            trace.errored = true;
+            std::mem::take(&mut trace.records)
+        };
+        let Some(logger_provider) = logs::GLOBAL_LOGGER_PROVIDER.get() else {
+            return;
+        };
+        logs::in_log_bridge(|| {
+            for (record, library) in records {
+                let mut logger = logger_provider
+                    .logger_builder(library.name)
+                    .with_attributes(library.attributes);
+                if let Some(version) = library.version {
+                    logger = logger.with_version(version);
+                }
+                if let Some(schema_url) = library.schema_url {
+                    logger = logger.with_schema_url(schema_url);
+                }
+                logger.build().emit(record);
+            }
+        });
+    }
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Vec<(LogRecord, InstrumentationLibrary)>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+/// A log processor keeping the emitted records aside, for [`DebugLogBuffer`] to buffer them.
+#[derive(Debug)]
+struct CaptureLogProcessor;
+
+impl LogProcessor for CaptureLogProcessor {
+    fn emit(&self, data: &mut LogRecord, instrumentation: &InstrumentationLibrary) {
+        CAPTURED.with(|captured| {
+            captured
+                .borrow_mut()
+                .push((data.clone(), instrumentation.clone()))
+        });
+    }
+
+    fn force_flush(&self) -> LogResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> LogResult<()> {
+        Ok(())
+    }
+}
+
+/// Finds whether the recorded fields set the OpenTelemetry status of the span to error.
+pub(crate) struct ErrorStatusVisitor(pub(crate) bool);
+
+impl Visit for ErrorStatusVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "otel.status_code" && value.eq_ignore_ascii_case("error") {
+            self.0 = true;
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "otel.status_code" {
+            self.record_str(field, &format!("{value:?}"));
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::Instant;
+
+use opentelemetry::trace::{SamplingDecision, TraceContextExt as _, TraceId};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+
+/// The maximum number of (target, trace) pairs counted within a window.
+/// Beyond it, the counts restart before the window ends.
+const MAX_SAMPLED_KEYS: usize = 10_000;
+
+/// Configuration of the log sampling, which exports all WARN and ERROR records, but only
+/// a fraction of the less severe records of each (target, trace) pair within a window,
+/// see [`InitConfig::with_log_sampling_config`](crate::InitConfig::with_log_sampling_config).
+///
+/// The records emitted within a sampled trace are all exported, so that it stays complete.
+#[derive(Debug, Clone, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct LogSamplingConfig {
+    /// The fraction of the INFO, DEBUG and TRACE records exported, between 0 and 1.
+    /// Defaults to 0.1: the 1st, 11th, 21st... records of each (target, trace) pair.
+    ratio: f64,
+    /// The window after which the records are counted again from zero. Defaults to one minute.
+    window: Duration,
+}
+
+impl Default for LogSamplingConfig {
+    fn default() -> Self {
+        Self {
+            ratio: 0.1,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Wraps the log bridge layer so that only the records selected by a [`LogSamplingConfig`]
+/// are bridged, or all of them without configuration.
+pub(crate) struct LogSamplingLayer<L> {
+    inner: L,
+    sampler: Option<LogSampler>,
+}
+
+impl<L> LogSamplingLayer<L> {
+    pub(crate) fn new(inner: L, config: Option<LogSamplingConfig>) -> Self {
+        Self {
+            inner,
+            sampler: config.map(LogSampler::new),
+        }
+    }
+}
+
+impl<S, L> Layer<S> for LogSamplingLayer<L>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    L: Layer<S>,
+{
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.enabled(metadata, ctx)
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let sampled = match &self.sampler {
+            Some(sampler) => sampler.should_export(event.metadata(), &ctx),
+            None => true,
+        };
+        if sampled {
+            self.inner.on_event(event, ctx);
+        }
+    }
+}
+
+struct LogSampler {
+    ratio: f64,
+    window: Duration,
+    counts: Mutex<WindowCounts>,
+}
+
+/// The number of records seen per (target, trace) pair since `start`.
+struct WindowCounts {
+    start: Instant,
+    counts: HashMap<(&'static str, TraceId), u64>,
+}
+
+impl LogSampler {
+    fn new(config: LogSamplingConfig) -> Self {
+        Self {
+            ratio: config.ratio.clamp(0.0, 1.0),
+            window: config.window,
+            counts: Mutex::new(WindowCounts {
+                start: Instant::now(),
+                counts: HashMap::new(),
+            }),
+        }
+    }
+
+    fn should_export<S>(&self, metadata: &'static Metadata<'static>, ctx: &Context<'_, S>) -> bool
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        if *metadata.level() <= Level::WARN {
+            return true;
+        }
+        let (trace_id, sampled) = current_trace(ctx);
+        if sampled {
+            return true;
+        }
+        let mut window = self.counts.lock().unwrap();
+        if window.start.elapsed() >= self.window || window.counts.len() >= MAX_SAMPLED_KEYS {
+            window.start = Instant::now();
+            window.counts.clear();
+        }
+        let count = window
+            .counts
+            .entry((metadata.target(), trace_id))
+            .or_default();
+        let seen = *count as f64;
+        *count += 1;
+        // Spreads the exported records evenly, starting with the first one.
+        (seen * self.ratio).ceil() < ((seen + 1.0) * self.ratio).ceil()
+    }
+}
+
+/// The trace of the current `tracing` span, and whether it is sampled.
+///
+/// The current dispatcher cannot be re-entered while it handles an event, so that this reads
+/// the OpenTelemetry data of the span rather than `Span::context`. The sampling decision of
+/// a root span is only known once its context has been built, e.g. for a child span.
+pub(crate) fn current_trace<S>(ctx: &Context<'_, S>) -> (TraceId, bool)
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    match ctx.lookup_current() {
+        Some(span) => span_trace(&span),
+        None => (TraceId::INVALID, false),
+    }
+}
+
+/// The trace of `span`, and whether it is sampled, see [`current_trace`].
+pub(crate) fn span_trace<R>(span: &SpanRef<'_, R>) -> (TraceId, bool)
+where
+    R: for<'span> LookupSpan<'span>,
+{
+    let extensions = span.extensions();
+    let Some(data) = extensions.get::<OtelData>() else {
+        return (TraceId::INVALID, false);
+    };
+    let parent_span = data.parent_cx.span();
+    let parent = parent_span.span_context();
+    if parent.is_valid() {
+        return (parent.trace_id(), parent.is_sampled());
+    }
+    let sampled = data
+        .builder
+        .sampling_result
+        .as_ref()
+        .is_some_and(|result| result.decision == SamplingDecision::RecordAndSample);
+    (data.builder.trace_id.unwrap_or(TraceId::INVALID), sampled)
+}
@@ -1,20 +1,51 @@
 pub use opentelemetry_sdk::logs::BatchConfig as BatchLogConfig;
 
-use std::sync::OnceLock;
-use crate::RESOURCE;
+use crate::reconfigure::ReloadableLogExporter;
+use crate::stats::StatsLogExporter;
+use crate::tenant::TenantBaggageLogProcessor;
+use crate::TenantRouter;
+use opentelemetry::logs::{ AnyValue, LogResult, LoggerProvider as _ };
+use opentelemetry::metrics::{ Counter, Meter };
+use opentelemetry::{ InstrumentationLibrary, KeyValue };
 use opentelemetry_appender_tracing::layer;
+use opentelemetry_otlp::{ Protocol, WithExportConfig as _ };
+use opentelemetry_sdk::export::logs::LogExporter as SdkLogExporter;
+use opentelemetry_sdk::logs::{ Builder as LoggerProviderBuilder, LogProcessor, LogRecord };
+#[cfg(not(feature = "wasm"))]
 use opentelemetry_sdk::runtime::Tokio;
-use opentelemetry_sdk::{ logs::BatchLogProcessor, logs::Logger, logs::LoggerProvider };
+use opentelemetry_sdk::Resource;
+#[cfg(not(feature = "wasm"))]
+use opentelemetry_sdk::logs::BatchLogProcessor;
+use opentelemetry_sdk::{ logs::Logger, logs::LoggerProvider };
 use opentelemetry_stdout::LogExporter;
+use std::cell::Cell;
+use std::sync::OnceLock;
+use tracing::{ Event, Metadata, Subscriber };
+use tracing_subscriber::layer::{ Context, Layer };
 
 /// The global `Logger` provider singleton.
-static GLOBAL_LOGGER_PROVIDER: OnceLock<LoggerProvider> = OnceLock::new();
+pub(crate) static GLOBAL_LOGGER_PROVIDER: OnceLock<LoggerProvider> = OnceLock::new();
 
 /// Returns the global LoggerProvider
 pub fn logger_provider() -> &'static LoggerProvider {
     GLOBAL_LOGGER_PROVIDER.get().unwrap()
 }
 
+/// The logger named `name`, whose scope is configured by
+/// [`InitConfig::with_schema_url`](crate::InitConfig::with_schema_url) and
+/// [`InitConfig::with_scope_attributes`](crate::InitConfig::with_scope_attributes).
+pub(crate) fn scoped_logger(
+    logger_provider: &LoggerProvider,
+    name: &'static str
+) -> Logger {
+    let scope = crate::scope();
+    let mut logger = logger_provider.logger_builder(name).with_attributes(scope.attributes.clone());
+    if let Some(schema_url) = &scope.schema_url {
+        logger = logger.with_schema_url(schema_url.clone());
+    }
+    logger.build()
+}
+
 /// Shut down the current logger provider.
 /// This will invoke the shutdown method on all log processors.
 /// log processors should export remaining logs before return.
@@ -24,39 +55,458 @@ pub(crate) fn shutdown_logger_provider() {
     }
 }
 
+/// The targets whose events are not bridged into log records by default,
+/// see [`InitConfig::with_otel_log_excluded_targets`](crate::InitConfig::with_otel_log_excluded_targets).
+pub(crate) const DEFAULT_OTEL_LOG_EXCLUDED_TARGETS: [&str; 4] = [
+    "opentelemetry*",
+    "h2",
+    "tonic",
+    "hyper",
+];
+
+/// Whether `target` matches one of the `excluded` targets: either a module path such as `h2`,
+/// matching itself and its submodules, or a prefix ending with `*` such as `opentelemetry*`.
+pub(crate) fn is_excluded_target(excluded: &[String], target: &str) -> bool {
+    excluded.iter().any(|excluded| {
+        match excluded.strip_suffix('*') {
+            Some(prefix) => target.starts_with(prefix),
+            None =>
+                target
+                    .strip_prefix(excluded.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::")),
+        }
+    })
+}
+
+thread_local! {
+    static IN_LOG_BRIDGE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Wraps the log bridge layer so that the events emitted while it handles an event,
+/// e.g. by an exporter sending the record synchronously, are not bridged in turn,
+/// which could otherwise recursively generate log records.
+pub(crate) struct ReentrancyGuardLayer<L>(pub(crate) L);
+
+impl<S: Subscriber, L: Layer<S>> Layer<S> for ReentrancyGuardLayer<L> {
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.0.enabled(metadata, ctx)
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if is_in_log_bridge() {
+            return;
+        }
+        in_log_bridge(|| self.0.on_event(event, ctx));
+    }
+}
+
+/// Whether the current thread is bridging an event into log records.
+pub(crate) fn is_in_log_bridge() -> bool {
+    IN_LOG_BRIDGE.with(Cell::get)
+}
+
+/// Run `f` as the log bridge, so that the events it emits are not bridged in turn.
+pub(crate) fn in_log_bridge<R>(f: impl FnOnce() -> R) -> R {
+    let was_in_bridge = IN_LOG_BRIDGE.with(|in_bridge| in_bridge.replace(true));
+    let result = f();
+    IN_LOG_BRIDGE.with(|in_bridge| in_bridge.set(was_in_bridge));
+    result
+}
+
+/// Configuration of the log-to-metrics processor,
+/// which counts emitted log records into the `log_records_total{level,target}` counter.
+#[derive(Debug, Clone, Default, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct LogMetricsConfig {
+    /// Only the records whose target starts with one of these prefixes are counted.
+    /// All targets are counted if empty.
+    target_allowlist: Vec<String>,
+}
+
+/// A log processor counting emitted log records by level and target.
+#[derive(Debug)]
+pub struct LogMetricsProcessor {
+    counter: Counter<u64>,
+    target_allowlist: Vec<String>,
+}
+
+impl LogMetricsProcessor {
+    /// Create a new LogMetricsProcessor, registering its counter on the global meter provider.
+    pub fn new(config: LogMetricsConfig) -> Self {
+        Self::with_meter(config, &crate::metrics::crate_meter())
+    }
+
+    fn with_meter(config: LogMetricsConfig, meter: &Meter) -> Self {
+        let counter = meter
+            .u64_counter("log_records_total")
+            .with_description("Number of emitted log records")
+            .init();
+        Self {
+            counter,
+            target_allowlist: config.target_allowlist,
+        }
+    }
+}
+
+impl LogProcessor for LogMetricsProcessor {
+    fn emit(&self, data: &mut LogRecord, _instrumentation: &InstrumentationLibrary) {
+        let target = data.target.as_deref().unwrap_or_default();
+        if
+            !self.target_allowlist.is_empty() &&
+            !self.target_allowlist.iter().any(|prefix| target.starts_with(prefix.as_str()))
+        {
+            return;
+        }
+        self.counter.add(
+            1,
+            &[
+                KeyValue::new("level", data.severity_text.unwrap_or_default()),
+                KeyValue::new("target", target.to_owned()),
+            ]
+        );
+    }
+
+    fn force_flush(&self) -> LogResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> LogResult<()> {
+        Ok(())
+    }
+}
+
+const LOG_TEMPLATE: &str = "log.template";
+
+/// A log processor adding the message template of each log record as the `log.template`
+/// attribute, for backends grouping the records by template.
+///
+/// The `tracing` macros interpolate the message before it reaches the bridge, so the template
+/// is recovered from the body by replacing the values of the fields of the record with `{field}`
+/// placeholders: `info!(user = %name, "user {name} logged in")` has the template
+/// `user {user} logged in`. Only whole words are replaced. As the literal text of the message
+/// may contain a field value too, the records whose template is ambiguous carry none, so that
+/// the records of a callsite never get different templates, see `message_template`.
+#[derive(Debug)]
+pub(crate) struct LogTemplateProcessor;
+
+impl LogProcessor for LogTemplateProcessor {
+    fn emit(&self, data: &mut LogRecord, _instrumentation: &InstrumentationLibrary) {
+        let Some(AnyValue::String(body)) = &data.body else {
+            return;
+        };
+        let fields = data.attributes_iter().filter_map(|(key, value)| {
+            let value = match value {
+                AnyValue::String(value) => value.to_string(),
+                AnyValue::Int(value) => value.to_string(),
+                AnyValue::Double(value) => value.to_string(),
+                AnyValue::Boolean(value) => value.to_string(),
+                _ => return None,
+            };
+            // The metadata of the events, e.g. `code.lineno`, is not part of the message.
+            (!key.as_str().starts_with("code.")).then_some((key.as_str(), value))
+        });
+        if let Some(template) = message_template(body.as_str(), fields) {
+            data.add_attribute(LOG_TEMPLATE, template);
+        }
+    }
+
+    fn force_flush(&self) -> LogResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> LogResult<()> {
+        Ok(())
+    }
+}
+
+/// Replace the whole-word occurrence of the value of each field in `body` with a `{field}`
+/// placeholder, the fields whose value does not occur being left out of the message.
+///
+/// `None` if the template is ambiguous, i.e. if a value occurs more than once or overlaps
+/// another: `info!(status = 200, "sent 200 bytes, status {status}")` cannot tell which `200`
+/// is the field, and guessing would give different templates for different statuses.
+fn message_template<'a>(
+    body: &str,
+    fields: impl Iterator<Item = (&'a str, String)>
+) -> Option<String> {
+    let mut placeholders: Vec<(usize, usize, &str)> = Vec::new();
+    for (key, value) in fields {
+        if value.is_empty() {
+            continue;
+        }
+        let mut occurrences = body
+            .match_indices(value.as_str())
+            .map(|(start, _)| (start, start + value.len()))
+            .filter(|&(start, end)| is_word(body, start, end));
+        let Some((start, end)) = occurrences.next() else {
+            continue;
+        };
+        if occurrences.next().is_some() ||
+            placeholders.iter().any(|&(s, e, _)| start < e && s < end)
+        {
+            return None;
+        }
+        placeholders.push((start, end, key));
+    }
+    placeholders.sort_unstable();
+    let mut template = String::with_capacity(body.len());
+    let mut last = 0;
+    for (start, end, key) in placeholders {
+        template.push_str(&body[last..start]);
+        template.push('{');
+        template.push_str(key);
+        template.push('}');
+        last = end;
+    }
+    template.push_str(&body[last..]);
+    Some(template)
+}
+
+/// Whether `body[start..end]` is not part of a longer word.
+fn is_word(body: &str, start: usize, end: usize) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    !body[..start].chars().next_back().is_some_and(is_word_char) &&
+        !body[end..].chars().next().is_some_and(is_word_char)
+}
+
 pub(crate) fn init_logs(
     use_stdout_exporter: bool,
-    batch_log_config: Option<BatchLogConfig>
-) -> anyhow::Result<layer::OpenTelemetryTracingBridge<LoggerProvider, Logger>> {
+    otlp_endpoint: Option<&str>,
+    batch_log_config: Option<BatchLogConfig>,
+    log_metrics_config: Option<LogMetricsConfig>,
+    log_templates: bool,
+    tenant_router: Option<&TenantRouter>,
+    resource: Resource
+) -> anyhow::Result<
+    (
+        layer::OpenTelemetryTracingBridge<LoggerProvider, Logger>,
+        LoggerProvider,
+        ReloadableLogExporter,
+    )
+> {
+    let log_exporter = ReloadableLogExporter::new(
+        build_log_exporter(use_stdout_exporter, otlp_endpoint)?
+    );
     let mut logger_provider = LoggerProvider::builder();
-    if use_stdout_exporter {
-        let log_exporter = LogExporter::default();
-        if let Some(logs_batch_config) = batch_log_config {
-            let batch = BatchLogProcessor::builder(log_exporter, Tokio)
-                .with_batch_config(logs_batch_config)
-                .build();
-            logger_provider = logger_provider.with_log_processor(batch);
-        } else {
-            logger_provider = logger_provider.with_simple_exporter(log_exporter);
-        }
+    // Before the exporting processor, which exports the records as they are.
+    if log_templates {
+        logger_provider = logger_provider.with_log_processor(LogTemplateProcessor);
+    }
+    logger_provider = with_log_processors(logger_provider, log_metrics_config);
+    if let Some(tenant_router) = tenant_router {
+        logger_provider = logger_provider.with_log_processor(TenantBaggageLogProcessor);
+        let tenant_exporter = tenant_router.log_exporter(
+            use_stdout_exporter,
+            log_exporter.clone()
+        )?;
+        logger_provider = with_log_exporter(logger_provider, tenant_exporter, batch_log_config);
     } else {
-        let log_exporter = opentelemetry_otlp::new_exporter().tonic().build_log_exporter()?;
-        if let Some(logs_batch_config) = batch_log_config {
-            let batch = BatchLogProcessor::builder(log_exporter, Tokio)
-                .with_batch_config(logs_batch_config)
-                .build();
-            logger_provider = logger_provider.with_log_processor(batch);
-        } else {
-            logger_provider = logger_provider.with_simple_exporter(log_exporter);
-        }
+        logger_provider = with_log_exporter(
+            logger_provider,
+            log_exporter.clone(),
+            batch_log_config
+        );
     }
-    let logger_provider = logger_provider.with_resource(RESOURCE.get().unwrap().clone()).build();
+    let logger_provider = logger_provider.with_resource(resource).build();
 
     let logger_layer: layer::OpenTelemetryTracingBridge<
         LoggerProvider,
         opentelemetry_sdk::logs::Logger
     > = layer::OpenTelemetryTracingBridge::new(&logger_provider);
 
-    GLOBAL_LOGGER_PROVIDER.set(logger_provider).unwrap();
-    Ok(logger_layer)
+    Ok((logger_layer, logger_provider, log_exporter))
+}
+
+/// Build a logger provider with the processors of the log records but no exporter, for the
+/// standard output exporter, whose log records are printed by the `fmt` layer instead.
+/// Returns `None` if no such processor is configured.
+pub(crate) fn init_log_processors(
+    log_metrics_config: Option<LogMetricsConfig>,
+    resource: Resource
+) -> Option<(layer::OpenTelemetryTracingBridge<LoggerProvider, Logger>, LoggerProvider)> {
+    #[cfg(feature = "sentry")]
+    let has_processors = log_metrics_config.is_some() || crate::sentry::sentry_config().is_some();
+    #[cfg(not(feature = "sentry"))]
+    let has_processors = log_metrics_config.is_some();
+    if !has_processors {
+        return None;
+    }
+    let logger_provider = with_log_processors(LoggerProvider::builder(), log_metrics_config)
+        .with_resource(resource)
+        .build();
+    let logger_layer = layer::OpenTelemetryTracingBridge::new(&logger_provider);
+    Some((logger_layer, logger_provider))
+}
+
+/// Add the processors of the log records, which do not export them.
+fn with_log_processors(
+    mut logger_provider: LoggerProviderBuilder,
+    log_metrics_config: Option<LogMetricsConfig>
+) -> LoggerProviderBuilder {
+    if let Some(log_metrics_config) = log_metrics_config {
+        logger_provider = logger_provider.with_log_processor(
+            LogMetricsProcessor::new(log_metrics_config)
+        );
+    }
+    #[cfg(feature = "sentry")]
+    if let Some(sentry_config) = crate::sentry::sentry_config() {
+        logger_provider = logger_provider.with_log_processor(
+            crate::sentry::SentryLogProcessor(sentry_config)
+        );
+    }
+    logger_provider
+}
+
+/// Register `logger_provider` as the global one.
+pub(crate) fn register_logger_provider(logger_provider: LoggerProvider) {
+    let _ = GLOBAL_LOGGER_PROVIDER.set(logger_provider);
+}
+
+/// Add `log_exporter` to `logger_provider`, batched with `batch_log_config` if any.
+pub(crate) fn with_log_exporter<E: SdkLogExporter + 'static>(
+    logger_provider: LoggerProviderBuilder,
+    log_exporter: E,
+    batch_log_config: Option<BatchLogConfig>
+) -> LoggerProviderBuilder {
+    #[cfg(not(feature = "wasm"))]
+    if let Some(logs_batch_config) = batch_log_config {
+        let batch = BatchLogProcessor::builder(log_exporter, Tokio)
+            .with_batch_config(logs_batch_config)
+            .build();
+        return logger_provider.with_log_processor(batch);
+    }
+    // The batch processor needs the Tokio runtime, so the records are exported as they are emitted.
+    #[cfg(feature = "wasm")]
+    let _ = batch_log_config;
+    logger_provider.with_simple_exporter(log_exporter)
+}
+
+/// Install a logger provider without processors, which drops all log records.
+pub(crate) fn init_noop_logs() {
+    let _ = GLOBAL_LOGGER_PROVIDER.set(LoggerProvider::builder().build());
+}
+
+/// Build the log exporter, either to the standard output or to OTLP.
+/// The OTLP endpoint falls back to the `OTEL_EXPORTER_OTLP_*` environment variables.
+pub(crate) fn build_log_exporter(
+    use_stdout_exporter: bool,
+    otlp_endpoint: Option<&str>
+) -> anyhow::Result<Box<dyn SdkLogExporter>> {
+    if use_stdout_exporter {
+        return Ok(Box::new(StatsLogExporter(Box::new(LogExporter::default()))));
+    }
+    if let Some(http_client) = crate::transport::otlp_http_client() {
+        let mut builder = opentelemetry_otlp
+            ::new_exporter()
+            .http()
+            .with_http_client(http_client)
+            .with_protocol(Protocol::HttpJson);
+        if let Some(otlp_endpoint) = otlp_endpoint {
+            builder = builder.with_endpoint(otlp_endpoint);
+        }
+        return Ok(Box::new(StatsLogExporter(Box::new(builder.build_log_exporter()?))));
+    }
+    #[cfg(feature = "wasm")]
+    return Err(crate::transport::no_otlp_http_client());
+    #[cfg(not(feature = "wasm"))]
+    {
+        let mut builder = crate::transport::otlp_tonic_builder();
+        if let Some(otlp_endpoint) = otlp_endpoint {
+            builder = builder.with_endpoint(otlp_endpoint);
+        }
+        Ok(Box::new(StatsLogExporter(Box::new(builder.build_log_exporter()?))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template<'a>(body: &str, fields: &[(&'a str, &str)]) -> Option<String> {
+        message_template(body, fields.iter().map(|&(key, value)| (key, value.to_owned())))
+    }
+
+    #[test]
+    fn log_metrics_count_the_allowlisted_targets_by_level() {
+        use crate::pre_init::SharedManualReader;
+        use opentelemetry::logs::{ LogRecord as _, Logger as _ };
+        use opentelemetry::metrics::MeterProvider as _;
+        use opentelemetry_sdk::metrics::data::{ ResourceMetrics, Sum };
+        use opentelemetry_sdk::metrics::reader::MetricReader as _;
+        use opentelemetry_sdk::metrics::SdkMeterProvider;
+
+        let reader = SharedManualReader::new();
+        let meter_provider = SdkMeterProvider::builder().with_reader(reader.clone()).build();
+        let config = LogMetricsConfig::default().with_target_allowlist(vec!["app::".to_owned()]);
+        let processor = LogMetricsProcessor::with_meter(config, &meter_provider.meter("test"));
+        let logger = LoggerProvider::builder().build().logger("test");
+        let library = InstrumentationLibrary::default();
+        let records = [
+            ("app::db", "WARN"),
+            ("app::http", "WARN"),
+            ("app::db", "ERROR"),
+            ("hyper", "WARN"),
+        ];
+        for (target, level) in records {
+            let mut record = logger.create_log_record();
+            record.set_target(target);
+            record.set_severity_text(level);
+            processor.emit(&mut record, &library);
+        }
+
+        let mut metrics = ResourceMetrics {
+            resource: Resource::empty(),
+            scope_metrics: Vec::new(),
+        };
+        reader.collect(&mut metrics).unwrap();
+        let metric = &metrics.scope_metrics[0].metrics[0];
+        assert_eq!(metric.name, "log_records_total");
+        let sum = metric.data.as_any().downcast_ref::<Sum<u64>>().unwrap();
+        let mut counts: Vec<_> = sum.data_points
+            .iter()
+            .map(|point| {
+                let attribute = |key: &str| {
+                    point.attributes
+                        .iter()
+                        .find(|kv| kv.key.as_str() == key)
+                        .map(|kv| kv.value.to_string())
+                        .unwrap_or_default()
+                };
+                (attribute("target"), attribute("level"), point.value)
+            })
+            .collect();
+        counts.sort();
+        let expected = [
+            ("app::db".to_owned(), "ERROR".to_owned(), 1),
+            ("app::db".to_owned(), "WARN".to_owned(), 1),
+            ("app::http".to_owned(), "WARN".to_owned(), 1),
+        ];
+        assert_eq!(counts, expected);
+    }
+
+    #[test]
+    fn message_template_replaces_field_values() {
+        let cases: &[(&str, &[(&str, &str)], Option<&str>)] = &[
+            // (body, fields, template)
+            ("user alice logged in", &[("user", "alice")], Some("user {user} logged in")),
+            (
+                "sent 512 bytes to 10.0.0.1",
+                &[("size", "512"), ("peer", "10.0.0.1")],
+                Some("sent {size} bytes to {peer}"),
+            ),
+            // Fields left out of the message.
+            ("logged in", &[("user", "alice")], Some("logged in")),
+            // Whole words only.
+            ("user alice2 logged in", &[("user", "alice")], Some("user alice2 logged in")),
+            ("retry 1 of 10", &[("max", "10")], Some("retry 1 of {max}")),
+            // A literal colliding with a value.
+            ("sent 200 bytes, status 200", &[("status", "200")], None),
+            ("alice invited alice", &[("from", "alice"), ("to", "alice")], None),
+            // Overlapping values.
+            ("hello john smith", &[("name", "john smith"), ("first", "john")], None),
+        ];
+        for (body, fields, expected) in cases {
+            assert_eq!(template(body, fields).as_deref(), *expected, "{body}");
+        }
+    }
 }
@@ -0,0 +1,313 @@
+//! Trace context propagation and span helpers for message-driven services.
+
+use crate::{tracer_span, TraceSpan};
+
+#[cfg(feature = "lapin")]
+use lapin::types::{AMQPValue, FieldTable};
+use opentelemetry::global::get_text_map_propagator;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{Link, SpanBuilder, SpanKind, TraceContextExt};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_semantic_conventions::attribute::{
+    MESSAGING_KAFKA_MESSAGE_KEY, MESSAGING_KAFKA_OFFSET,
+};
+use opentelemetry_semantic_conventions::trace::{
+    MESSAGING_CONSUMER_GROUP_NAME, MESSAGING_DESTINATION_NAME, MESSAGING_DESTINATION_PARTITION_ID,
+    MESSAGING_OPERATION_TYPE, MESSAGING_SYSTEM,
+};
+#[cfg(feature = "rdkafka")]
+use rdkafka::message::{Header, Headers, Message, OwnedHeaders};
+use std::borrow::Cow;
+
+/// Metadata of a consumed Kafka record, recorded as `messaging.*` attributes.
+#[derive(Debug, Clone, Default)]
+pub struct KafkaRecordInfo<'a> {
+    /// The topic the record was consumed from.
+    pub topic: &'a str,
+    /// The partition the record was consumed from.
+    pub partition: i32,
+    /// The offset of the record in its partition.
+    pub offset: i64,
+    /// The record key, if any.
+    pub key: Option<&'a str>,
+    /// The consumer group, if any.
+    pub consumer_group: Option<&'a str>,
+}
+
+/// Inject the current context into message headers, using the global propagator.
+pub fn inject(headers: &mut impl Injector) {
+    get_text_map_propagator(|propagator| propagator.inject(headers));
+}
+
+/// Start a consumer span named `name` as a child of the current context,
+/// linked to the producing span extracted from the message `headers`
+/// with the global propagator.
+pub fn consume_span(name: impl Into<Cow<'static, str>>, headers: &impl Extractor) -> TraceSpan {
+    consume_span_with_attributes(name, headers, Vec::new())
+}
+
+/// Like [`consume_span`], with additional span attributes.
+pub fn consume_span_with_attributes(
+    name: impl Into<Cow<'static, str>>,
+    headers: &impl Extractor,
+    attributes: Vec<KeyValue>,
+) -> TraceSpan {
+    let producer_cx = get_text_map_propagator(|propagator| propagator.extract(headers));
+    let producer_span_context = producer_cx.span().span_context().clone();
+
+    let mut builder = SpanBuilder::from_name(name)
+        .with_kind(SpanKind::Consumer)
+        .with_attributes(attributes);
+    if producer_span_context.is_valid() {
+        builder = builder.with_links(vec![Link::with_context(producer_span_context)]);
+    }
+    tracer_span(builder, Some(&Context::current()))
+}
+
+/// Inject the current context into Kafka record headers, using the global propagator.
+pub fn inject_kafka_headers(headers: &mut impl Injector) {
+    inject(headers)
+}
+
+/// Start a producer span named `{topic} publish` as a child of the current context.
+/// Make it active (e.g. with `mark_span_as_active`) before calling [`inject_kafka_headers`],
+/// so that consumers link to it.
+pub fn start_kafka_producer_span(topic: &str) -> TraceSpan {
+    let builder = SpanBuilder::from_name(format!("{} publish", topic))
+        .with_kind(SpanKind::Producer)
+        .with_attributes([
+            KeyValue::new(MESSAGING_SYSTEM, "kafka"),
+            KeyValue::new(MESSAGING_OPERATION_TYPE, "publish"),
+            KeyValue::new(MESSAGING_DESTINATION_NAME, topic.to_owned()),
+        ]);
+    tracer_span(builder, None)
+}
+
+/// Start a consumer span named `{topic} process` as a child of the current context,
+/// linked to the producing span extracted from the record `headers`.
+pub fn start_kafka_consumer_span(
+    record: &KafkaRecordInfo<'_>,
+    headers: &impl Extractor,
+) -> TraceSpan {
+    let mut attributes = vec![
+        KeyValue::new(MESSAGING_SYSTEM, "kafka"),
+        KeyValue::new(MESSAGING_OPERATION_TYPE, "process"),
+        KeyValue::new(MESSAGING_DESTINATION_NAME, record.topic.to_owned()),
+        KeyValue::new(
+            MESSAGING_DESTINATION_PARTITION_ID,
+            record.partition.to_string(),
+        ),
+        KeyValue::new(MESSAGING_KAFKA_OFFSET, record.offset),
+    ];
+    if let Some(key) = record.key {
+        attributes.push(KeyValue::new(MESSAGING_KAFKA_MESSAGE_KEY, key.to_owned()));
+    }
+    if let Some(consumer_group) = record.consumer_group {
+        attributes.push(KeyValue::new(
+            MESSAGING_CONSUMER_GROUP_NAME,
+            consumer_group.to_owned(),
+        ));
+    }
+    consume_span_with_attributes(format!("{} process", record.topic), headers, attributes)
+}
+
+/// Adapts raw Kafka record headers (`(key, value bytes)` pairs) to an [`Injector`].
+#[derive(Debug)]
+pub struct KafkaHeadersInjector<'a>(pub &'a mut Vec<(String, Vec<u8>)>);
+
+impl Injector for KafkaHeadersInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.retain(|(k, _)| k != key);
+        self.0.push((key.to_owned(), value.into_bytes()));
+    }
+}
+
+/// Adapts raw Kafka record headers (`(key, value bytes)` pairs) to an [`Extractor`].
+/// Values that are not valid UTF-8 are ignored.
+#[derive(Debug)]
+pub struct KafkaHeadersExtractor<'a>(pub &'a [(String, Vec<u8>)]);
+
+impl Extractor for KafkaHeadersExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .and_then(|(_, v)| std::str::from_utf8(v).ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.iter().map(|(k, _)| k.as_str()).collect()
+    }
+}
+
+/// Adapts the headers of an rdkafka record to produce to an [`Injector`],
+/// see [`inject_rdkafka_headers`].
+#[cfg(feature = "rdkafka")]
+#[derive(Debug)]
+pub struct RdKafkaHeadersInjector(pub OwnedHeaders);
+
+#[cfg(feature = "rdkafka")]
+impl Injector for RdKafkaHeadersInjector {
+    fn set(&mut self, key: &str, value: String) {
+        let headers = std::mem::replace(&mut self.0, OwnedHeaders::new());
+        self.0 = headers.insert(Header {
+            key,
+            value: Some(value.as_str()),
+        });
+    }
+}
+
+/// Adapts the headers of a consumed rdkafka message to an [`Extractor`].
+/// Values that are not valid UTF-8 are ignored.
+#[cfg(feature = "rdkafka")]
+#[derive(Debug)]
+pub struct RdKafkaHeadersExtractor<'a, H>(pub Option<&'a H>);
+
+#[cfg(feature = "rdkafka")]
+impl<H: Headers> Extractor for RdKafkaHeadersExtractor<'_, H> {
+    fn get(&self, key: &str) -> Option<&str> {
+        let headers = self.0?;
+        (0..headers.count())
+            .map(|idx| headers.get(idx))
+            .find(|header| header.key.eq_ignore_ascii_case(key))
+            .and_then(|header| header.value)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.map_or_else(Vec::new, |headers| {
+            (0..headers.count())
+                .map(|idx| headers.get(idx).key)
+                .collect()
+        })
+    }
+}
+
+/// Inject the current context into the `headers` of an rdkafka record to produce,
+/// e.g. a `FutureRecord`, keeping its other headers.
+///
+/// ```ignore
+/// let _guard = mark_span_as_active(start_kafka_producer_span("orders"));
+/// let mut record = FutureRecord::to("orders").payload(&payload).key(&key);
+/// record.headers = Some(inject_rdkafka_headers(record.headers.take()));
+/// producer.send(record, timeout).await?;
+/// ```
+#[cfg(feature = "rdkafka")]
+pub fn inject_rdkafka_headers(headers: Option<OwnedHeaders>) -> OwnedHeaders {
+    let mut injector = RdKafkaHeadersInjector(headers.unwrap_or_else(OwnedHeaders::new));
+    inject(&mut injector);
+    injector.0
+}
+
+/// Start a consumer span named `{topic} process` for the consumed rdkafka `message`, with its
+/// `messaging.*` attributes, as a child of the current context and linked to the producing span
+/// extracted from its headers, see [`start_kafka_consumer_span`].
+///
+/// ```ignore
+/// let message = consumer.recv().await?;
+/// let _guard = mark_span_as_active(start_rdkafka_consumer_span(&message, Some("billing")));
+/// ```
+#[cfg(feature = "rdkafka")]
+pub fn start_rdkafka_consumer_span<M: Message>(
+    message: &M,
+    consumer_group: Option<&str>,
+) -> TraceSpan {
+    let record = KafkaRecordInfo {
+        topic: message.topic(),
+        partition: message.partition(),
+        offset: message.offset(),
+        key: message.key().and_then(|key| std::str::from_utf8(key).ok()),
+        consumer_group,
+    };
+    start_kafka_consumer_span(&record, &RdKafkaHeadersExtractor(message.headers()))
+}
+
+/// Adapts the headers of an async-nats message to publish to an [`Injector`].
+///
+/// ```ignore
+/// let mut headers = async_nats::HeaderMap::new();
+/// inject(&mut NatsHeadersInjector(&mut headers));
+/// client.publish_with_headers("orders", headers, payload).await?;
+/// ```
+#[cfg(feature = "async-nats")]
+#[derive(Debug)]
+pub struct NatsHeadersInjector<'a>(pub &'a mut async_nats::HeaderMap);
+
+#[cfg(feature = "async-nats")]
+impl Injector for NatsHeadersInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(key, value.as_str());
+    }
+}
+
+/// Adapts the headers of a received async-nats message to an [`Extractor`].
+///
+/// ```ignore
+/// let headers = message.headers.clone().unwrap_or_default();
+/// let span = consume_span("orders process", &NatsHeadersExtractor(&headers));
+/// ```
+#[cfg(feature = "async-nats")]
+#[derive(Debug)]
+pub struct NatsHeadersExtractor<'a>(pub &'a async_nats::HeaderMap);
+
+#[cfg(feature = "async-nats")]
+impl Extractor for NatsHeadersExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(async_nats::HeaderValue::as_str)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.iter().map(|(name, _)| name.as_ref()).collect()
+    }
+}
+
+/// Adapts the headers of a lapin (AMQP) message to publish to an [`Injector`],
+/// the values being set as long strings.
+///
+/// ```ignore
+/// let mut headers = FieldTable::default();
+/// inject(&mut AmqpHeadersInjector(&mut headers));
+/// let properties = BasicProperties::default().with_headers(headers);
+/// ```
+#[cfg(feature = "lapin")]
+#[derive(Debug)]
+pub struct AmqpHeadersInjector<'a>(pub &'a mut FieldTable);
+
+#[cfg(feature = "lapin")]
+impl Injector for AmqpHeadersInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0
+            .insert(key.into(), AMQPValue::LongString(value.into()));
+    }
+}
+
+/// Adapts the headers of a delivered lapin (AMQP) message to an [`Extractor`].
+/// Values that are neither strings nor valid UTF-8 are ignored.
+///
+/// ```ignore
+/// let headers = delivery.properties.headers().clone().unwrap_or_default();
+/// let span = consume_span("orders process", &AmqpHeadersExtractor(&headers));
+/// ```
+#[cfg(feature = "lapin")]
+#[derive(Debug)]
+pub struct AmqpHeadersExtractor<'a>(pub &'a FieldTable);
+
+#[cfg(feature = "lapin")]
+impl Extractor for AmqpHeadersExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        let (_, value) = self
+            .0
+            .inner()
+            .iter()
+            .find(|(name, _)| name.as_str() == key)?;
+        match value {
+            AMQPValue::LongString(value) => std::str::from_utf8(value.as_bytes()).ok(),
+            AMQPValue::ShortString(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.inner().keys().map(|key| key.as_str()).collect()
+    }
+}
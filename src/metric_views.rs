@@ -0,0 +1,64 @@
+use opentelemetry::Key;
+use opentelemetry_sdk::metrics::{Aggregation, Instrument, InstrumentKind, Stream};
+use std::collections::HashMap;
+
+/// The views of the meter provider, changing how the measurements of some instruments are
+/// aggregated or which of their attributes are kept, see the `with_exponential_histograms`
+/// and `with_metric_attribute_allowlist` methods of [`InitConfig`](crate::InitConfig).
+///
+/// They are merged into a single view, so that an instrument matching several settings still
+/// produces a single stream.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MetricViews {
+    /// The exponential histogram aggregation of all histograms, if any.
+    pub(crate) exponential_histograms: Option<ExponentialHistogram>,
+    /// The exponential histogram aggregation by histogram name, overriding the default one.
+    pub(crate) exponential_histogram: HashMap<String, ExponentialHistogram>,
+    /// The attributes kept by instrument name, the others being dropped before aggregation.
+    pub(crate) attribute_allowlists: HashMap<String, Vec<Key>>,
+}
+
+/// The parameters of a base-2 exponential histogram aggregation.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ExponentialHistogram {
+    pub(crate) max_scale: i8,
+    pub(crate) max_buckets: u32,
+}
+
+impl MetricViews {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.exponential_histograms.is_none()
+            && self.exponential_histogram.is_empty()
+            && self.attribute_allowlists.is_empty()
+    }
+
+    /// The stream of `inst`, if any setting applies to it.
+    pub(crate) fn stream(&self, inst: &Instrument) -> Option<Stream> {
+        let histogram = Some(inst)
+            .filter(|inst| inst.kind == Some(InstrumentKind::Histogram))
+            .and_then(|inst| {
+                self.exponential_histogram
+                    .get(inst.name.as_ref())
+                    .or(self.exponential_histograms.as_ref())
+            });
+        let allowlist = self.attribute_allowlists.get(inst.name.as_ref());
+        if histogram.is_none() && allowlist.is_none() {
+            return None;
+        }
+        let mut stream = Stream::new()
+            .name(inst.name.clone())
+            .description(inst.description.clone())
+            .unit(inst.unit.clone());
+        if let Some(histogram) = histogram {
+            stream = stream.aggregation(Aggregation::Base2ExponentialHistogram {
+                max_size: histogram.max_buckets,
+                max_scale: histogram.max_scale,
+                record_min_max: true,
+            });
+        }
+        if let Some(allowlist) = allowlist {
+            stream = stream.allowed_attribute_keys(allowlist.iter().cloned());
+        }
+        Some(stream)
+    }
+}
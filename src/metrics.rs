@@ -1,13 +1,28 @@
-use crate::RESOURCE;
+use crate::reconfigure::{ ReloadableMetricReader, ReloadableMetricsExporter };
 
 use opentelemetry::global;
-use opentelemetry_sdk::metrics::reader::{ DefaultAggregationSelector, DefaultTemporalitySelector };
-use opentelemetry_sdk::metrics::PeriodicReader;
+use opentelemetry_otlp::{ Protocol, WithExportConfig as _ };
+use opentelemetry_sdk::metrics::exporter::PushMetricsExporter;
+use opentelemetry::metrics::Result as MetricsResult;
+use opentelemetry_sdk::metrics::data::{ ResourceMetrics, Temporality };
+use opentelemetry_sdk::metrics::reader::{
+    AggregationSelector,
+    DefaultAggregationSelector,
+    DefaultTemporalitySelector,
+    MetricReader,
+    TemporalitySelector,
+};
+use opentelemetry_sdk::metrics::{ Aggregation, Instrument, InstrumentKind, Pipeline };
+use opentelemetry_sdk::Resource;
 pub use opentelemetry_sdk::metrics::SdkMeterProvider;
-use opentelemetry_sdk::runtime::Tokio;
 use opentelemetry_stdout::MetricsExporter;
-use std::sync::OnceLock;
-pub use opentelemetry::metrics::{ Meter, MeterProvider as _ };
+use std::borrow::Cow;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::{ Arc, OnceLock, RwLock, Weak };
+use tokio::sync::{ mpsc, Semaphore };
+use crate::metric_views::MetricViews;
+use crate::stats::StatsMetricsExporter;
+pub use opentelemetry::metrics::{ AsyncInstrument, Gauge, Meter, MeterProvider as _, UpDownCounter };
 pub use opentelemetry::global::{ meter, meter_with_version };
 
 /// Re-export opentelemetry::metrics;
@@ -18,41 +33,402 @@ pub mod otel_metrics {
 // OTEL_METRIC_EXPORT_INTERVAL
 // OTEL_METRIC_EXPORT_TIMEOUT
 
+/// Build the `&[KeyValue]` attributes of a measurement, whose keys are constants
+/// created once at compile time rather than on each recording.
+///
+/// ```ignore
+/// counter.add(1, attrs!["name" => "apple", "color" => "green"]);
+/// ```
+#[macro_export]
+macro_rules! attrs {
+    ($($key:expr => $value:expr),* $(,)?) => {
+        &[
+            $(
+                $crate::KeyValue::new(
+                    {
+                        const KEY: $crate::Key = $crate::Key::from_static_str($key);
+                        KEY
+                    },
+                    $value
+                )
+            ),*
+        ]
+    };
+}
+
+/// Declare `pub static` [`Key`](crate::Key)s of static strings, built at compile time,
+/// so that the attribute keys used throughout the code never allocate.
+/// Cloning them is as cheap as copying a `&'static str`.
+///
+/// ```ignore
+/// otel_keys! {
+///     /// The id of the user.
+///     USER_ID = "user.id",
+///     TENANT = "tenant.id",
+/// }
+///
+/// span.set_attribute(KeyValue::new(USER_ID.clone(), user_id));
+/// ```
+#[macro_export]
+macro_rules! otel_keys {
+    ($($(#[$meta:meta])* $name:ident = $key:expr),* $(,)?) => {
+        $(
+            $(#[$meta])*
+            pub static $name: $crate::Key = $crate::Key::from_static_str($key);
+        )*
+    };
+}
+
+/// The meter of this crate, whose scope is configured by
+/// [`InitConfig::with_schema_url`](crate::InitConfig::with_schema_url) and
+/// [`InitConfig::with_scope_attributes`](crate::InitConfig::with_scope_attributes).
+pub(crate) fn crate_meter() -> Meter {
+    let scope = crate::scope();
+    global::meter_with_version(
+        env!("CARGO_PKG_NAME"),
+        None::<&'static str>,
+        scope.schema_url.clone(),
+        Some(scope.attributes.clone())
+    )
+}
+
 /// The global `Meter` provider singleton.
-static GLOBAL_MMTER_PROVIDER: OnceLock<SdkMeterProvider> = OnceLock::new();
+pub(crate) static GLOBAL_MMTER_PROVIDER: OnceLock<SdkMeterProvider> = OnceLock::new();
 
 /// Returns the global SdkMeterProvider
+///
+/// Before OpenTelemetry is initialized, returns that of the pre-initialization buffer if
+/// installed, see [`install_pre_init_buffer`](crate::install_pre_init_buffer), or else one
+/// without readers, whose measurements are dropped.
 pub fn meter_provider() -> &'static SdkMeterProvider {
-    GLOBAL_MMTER_PROVIDER.get().unwrap()
+    static NOOP_METER_PROVIDER: OnceLock<SdkMeterProvider> = OnceLock::new();
+    GLOBAL_MMTER_PROVIDER.get()
+        .or_else(crate::pre_init::pre_init_meter_provider)
+        .unwrap_or_else(|| NOOP_METER_PROVIDER.get_or_init(|| SdkMeterProvider::builder().build()))
 }
 
-/// Shut down the current meter provider.
+/// Whether the callbacks of observable instruments are closed, see [`drain_observable_callbacks`].
+/// Running callbacks hold the read lock.
+static OBSERVABLE_CALLBACKS_CLOSED: RwLock<bool> = RwLock::new(false);
+
+/// Shut down the current meter provider, without truncating the final collection cycle:
+/// its readers are force-flushed first, then the observable callbacks are drained.
 pub(crate) fn shutdown_meter_provider() {
     if let Some(meter_provider) = GLOBAL_MMTER_PROVIDER.get() {
+        let _ = meter_provider.force_flush();
+        drain_observable_callbacks();
         let _ = meter_provider.shutdown();
     }
 }
 
-pub(crate) fn init_metrics(use_stdout_exporter: bool) -> anyhow::Result<()> {
-    let periodic_reader = if use_stdout_exporter {
-        let exporter = MetricsExporter::default();
-        PeriodicReader::builder(exporter, Tokio).build()
-    } else {
-        let exporter = opentelemetry_otlp
-            ::new_exporter()
-            .tonic()
-            .build_metrics_exporter(
-                Box::new(DefaultAggregationSelector::new()),
-                Box::new(DefaultTemporalitySelector::new())
-            )?;
-        PeriodicReader::builder(exporter, Tokio).build()
-    };
+/// Wait for the running callbacks of observable instruments to return,
+/// and keep any other from running until the metrics are initialized again.
+fn drain_observable_callbacks() {
+    *OBSERVABLE_CALLBACKS_CLOSED.write().unwrap() = true;
+}
+
+pub(crate) fn init_metrics(
+    use_stdout_exporter: bool,
+    otlp_endpoint: Option<&str>,
+    pull_metric_readers: Vec<PullMetricReader>,
+    metric_views: MetricViews,
+    resource: Resource
+) -> anyhow::Result<(SdkMeterProvider, ReloadableMetricReader)> {
+    let exporter = ReloadableMetricsExporter::new(
+        build_metrics_exporter(use_stdout_exporter, otlp_endpoint)?
+    );
+    let metric_reader = ReloadableMetricReader::new(exporter);
+
+    let mut meter_provider = SdkMeterProvider::builder()
+        .with_resource(resource)
+        .with_reader(metric_reader.clone());
+    for pull_metric_reader in pull_metric_readers {
+        meter_provider = meter_provider.with_reader(pull_metric_reader);
+    }
+    if !metric_views.is_empty() {
+        meter_provider = meter_provider.with_view(move |inst: &Instrument| {
+            metric_views.stream(inst)
+        });
+    }
+    Ok((meter_provider.build(), metric_reader))
+}
 
-    let meter_provider = SdkMeterProvider::builder()
-        .with_resource(RESOURCE.get().unwrap().clone())
-        .with_reader(periodic_reader)
-        .build();
+/// Register `meter_provider` as the global one, and open the callbacks of observable
+/// instruments.
+pub(crate) fn register_meter_provider(meter_provider: SdkMeterProvider) {
+    *OBSERVABLE_CALLBACKS_CLOSED.write().unwrap() = false;
     global::set_meter_provider(meter_provider.clone());
-    GLOBAL_MMTER_PROVIDER.set(meter_provider).unwrap();
-    Ok(())
+    let _ = GLOBAL_MMTER_PROVIDER.set(meter_provider);
+}
+
+/// A metric reader registered on the meter provider next to the periodic reader
+/// pushing the metrics, see
+/// [`InitConfig::with_pull_metric_reader`](crate::InitConfig::with_pull_metric_reader).
+#[derive(Debug)]
+pub(crate) struct PullMetricReader(pub(crate) Box<dyn MetricReader>);
+
+impl AggregationSelector for PullMetricReader {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.0.aggregation(kind)
+    }
+}
+
+impl TemporalitySelector for PullMetricReader {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.0.temporality(kind)
+    }
+}
+
+impl MetricReader for PullMetricReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.0.register_pipeline(pipeline)
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> MetricsResult<()> {
+        self.0.collect(rm)
+    }
+
+    fn force_flush(&self) -> MetricsResult<()> {
+        self.0.force_flush()
+    }
+
+    fn shutdown(&self) -> MetricsResult<()> {
+        self.0.shutdown()
+    }
+}
+
+/// Create an `i64` up-down counter named `name` on the meter of this crate,
+/// for values that go up and down, such as the number of in-flight requests.
+///
+/// ```ignore
+/// let in_flight = up_down_counter("http.server.active_requests", "{request}", "Active requests");
+/// in_flight.add(1, &[]);
+/// // ...
+/// in_flight.add(-1, &[]);
+/// ```
+pub fn up_down_counter(
+    name: impl Into<Cow<'static, str>>,
+    unit: impl Into<Cow<'static, str>>,
+    description: impl Into<Cow<'static, str>>
+) -> UpDownCounter<i64> {
+    crate_meter()
+        .i64_up_down_counter(name)
+        .with_unit(unit)
+        .with_description(description)
+        .init()
+}
+
+/// Create an `f64` gauge named `name` on the meter of this crate, see [`up_down_counter`].
+/// Each recording replaces the previous value, such as the current temperature.
+pub fn gauge(
+    name: impl Into<Cow<'static, str>>,
+    unit: impl Into<Cow<'static, str>>,
+    description: impl Into<Cow<'static, str>>
+) -> Gauge<f64> {
+    crate_meter()
+        .f64_gauge(name)
+        .with_unit(unit)
+        .with_description(description)
+        .init()
+}
+
+/// Handle of an observable instrument registered by [`register_observable_gauge`] and its
+/// counterparts. Dropping it unregisters the callback, which then stops observing;
+/// the instrument itself stays registered, and with cumulative temporality,
+/// its last observed values are still exported.
+#[derive(Debug)]
+#[must_use = "dropping the handle unregisters the callback"]
+pub struct ObservableHandle {
+    active: Arc<AtomicBool>,
+}
+
+impl ObservableHandle {
+    /// Unregister the callback, same as dropping the handle.
+    pub fn unregister(self) {}
+}
+
+impl Drop for ObservableHandle {
+    fn drop(&mut self) {
+        self.active.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Wrap `callback` so that it only runs while the returned handle is alive,
+/// and the observable callbacks are not drained.
+fn observable_callback<M>(
+    callback: impl Fn(&dyn AsyncInstrument<M>) + Send + Sync + 'static
+) -> (ObservableHandle, impl Fn(&dyn AsyncInstrument<M>) + Send + Sync + 'static) {
+    let active = Arc::new(AtomicBool::new(true));
+    let handle = ObservableHandle { active: active.clone() };
+    let callback = move |observer: &dyn AsyncInstrument<M>| {
+        let closed = OBSERVABLE_CALLBACKS_CLOSED.read().unwrap();
+        if !*closed && active.load(Ordering::Relaxed) {
+            callback(observer)
+        }
+    };
+    (handle, callback)
+}
+
+/// Register an `f64` observable gauge named `name` on the meter of this crate,
+/// whose `callback` observes the current value(s) at each collection.
+///
+/// ```ignore
+/// let _handle = register_observable_gauge("queue.depth", "{item}", "Queued items", |gauge| {
+///     gauge.observe(queue.len() as f64, &[])
+/// });
+/// ```
+pub fn register_observable_gauge(
+    name: impl Into<Cow<'static, str>>,
+    unit: impl Into<Cow<'static, str>>,
+    description: impl Into<Cow<'static, str>>,
+    callback: impl Fn(&dyn AsyncInstrument<f64>) + Send + Sync + 'static
+) -> ObservableHandle {
+    let (handle, callback) = observable_callback(callback);
+    crate_meter()
+        .f64_observable_gauge(name)
+        .with_unit(unit)
+        .with_description(description)
+        .with_callback(callback)
+        .init();
+    handle
+}
+
+/// Register a `u64` observable counter, see [`register_observable_gauge`].
+/// The callback observes the cumulative total.
+pub fn register_observable_counter(
+    name: impl Into<Cow<'static, str>>,
+    unit: impl Into<Cow<'static, str>>,
+    description: impl Into<Cow<'static, str>>,
+    callback: impl Fn(&dyn AsyncInstrument<u64>) + Send + Sync + 'static
+) -> ObservableHandle {
+    let (handle, callback) = observable_callback(callback);
+    crate_meter()
+        .u64_observable_counter(name)
+        .with_unit(unit)
+        .with_description(description)
+        .with_callback(callback)
+        .init();
+    handle
+}
+
+/// Register an `i64` observable up-down counter, see [`register_observable_gauge`].
+/// The callback observes the current total, which may decrease.
+pub fn register_observable_up_down_counter(
+    name: impl Into<Cow<'static, str>>,
+    unit: impl Into<Cow<'static, str>>,
+    description: impl Into<Cow<'static, str>>,
+    callback: impl Fn(&dyn AsyncInstrument<i64>) + Send + Sync + 'static
+) -> ObservableHandle {
+    let (handle, callback) = observable_callback(callback);
+    crate_meter()
+        .i64_observable_up_down_counter(name)
+        .with_unit(unit)
+        .with_description(description)
+        .with_callback(callback)
+        .init();
+    handle
+}
+
+/// Register an observable gauge named `name` reporting the number of messages queued in
+/// the bounded channel of `sender`, to make its backpressure visible.
+///
+/// The gauge only holds a weak sender, so it does not keep the channel open,
+/// and stops observing once the channel is closed.
+///
+/// ```ignore
+/// let (tx, rx) = tokio::sync::mpsc::channel(128);
+/// let _handle = observe_channel_depth("jobs.queue.depth", &tx);
+/// ```
+pub fn observe_channel_depth<T: Send + 'static>(
+    name: impl Into<Cow<'static, str>>,
+    sender: &mpsc::Sender<T>
+) -> ObservableHandle {
+    let sender = sender.downgrade();
+    register_observable_gauge(name, "{message}", "Messages queued in the channel", move |gauge| {
+        if let Some(sender) = sender.upgrade() {
+            gauge.observe((sender.max_capacity() - sender.capacity()) as f64, &[]);
+        }
+    })
+}
+
+/// Register an observable gauge named `name` reporting the available permits of `semaphore`,
+/// to make its backpressure visible.
+///
+/// The gauge only holds a weak reference, so it does not keep the semaphore alive,
+/// and stops observing once it is dropped.
+///
+/// ```ignore
+/// let permits = Arc::new(Semaphore::new(16));
+/// let _handle = observe_semaphore("db.pool.available", &permits);
+/// ```
+pub fn observe_semaphore(
+    name: impl Into<Cow<'static, str>>,
+    semaphore: &Arc<Semaphore>
+) -> ObservableHandle {
+    let semaphore = Arc::downgrade(semaphore);
+    register_observable_gauge(name, "{permit}", "Available semaphore permits", move |gauge| {
+        if let Some(semaphore) = semaphore.upgrade() {
+            gauge.observe(semaphore.available_permits() as f64, &[]);
+        }
+    })
+}
+
+/// Install a meter provider without readers, which drops all measurements.
+/// The `opentelemetry` global meter provider is left as the default no-op one.
+pub(crate) fn init_noop_metrics() {
+    let _ = GLOBAL_MMTER_PROVIDER.set(SdkMeterProvider::builder().build());
+}
+
+/// Build the push metrics exporter, either to the standard output or to OTLP.
+/// The OTLP endpoint falls back to the `OTEL_EXPORTER_OTLP_*` environment variables.
+pub(crate) fn build_metrics_exporter(
+    use_stdout_exporter: bool,
+    otlp_endpoint: Option<&str>
+) -> anyhow::Result<Box<dyn PushMetricsExporter>> {
+    if use_stdout_exporter {
+        return Ok(Box::new(StatsMetricsExporter(Box::new(MetricsExporter::default()))));
+    }
+    if let Some(http_client) = crate::transport::otlp_http_client() {
+        let mut builder = opentelemetry_otlp
+            ::new_exporter()
+            .http()
+            .with_http_client(http_client)
+            .with_protocol(Protocol::HttpJson);
+        if let Some(otlp_endpoint) = otlp_endpoint {
+            builder = builder.with_endpoint(otlp_endpoint);
+        }
+        return Ok(
+            Box::new(
+                StatsMetricsExporter(
+                    Box::new(
+                        builder.build_metrics_exporter(
+                            Box::new(DefaultAggregationSelector::new()),
+                            Box::new(DefaultTemporalitySelector::new())
+                        )?
+                    )
+                )
+            )
+        );
+    }
+    #[cfg(feature = "wasm")]
+    return Err(crate::transport::no_otlp_http_client());
+    #[cfg(not(feature = "wasm"))]
+    {
+        let mut builder = crate::transport::otlp_tonic_builder();
+        if let Some(otlp_endpoint) = otlp_endpoint {
+            builder = builder.with_endpoint(otlp_endpoint);
+        }
+        Ok(
+            Box::new(
+                StatsMetricsExporter(
+                    Box::new(
+                        builder.build_metrics_exporter(
+                            Box::new(DefaultAggregationSelector::new()),
+                            Box::new(DefaultTemporalitySelector::new())
+                        )?
+                    )
+                )
+            )
+        )
+    }
 }
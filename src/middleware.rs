@@ -0,0 +1,610 @@
+//! Tower middleware tracing HTTP requests and recording their metrics, and HTTP access logs.
+
+use crate::force_sample;
+use crate::logs::GLOBAL_LOGGER_PROVIDER;
+use crate::tracer_span;
+use crate::Instant;
+
+use http::header::{CONTENT_LENGTH, USER_AGENT};
+use http::{Request, Response};
+use opentelemetry::global::get_text_map_propagator;
+use opentelemetry::logs::{LogRecord as _, Logger as _, Severity};
+use opentelemetry::metrics::{Histogram, UpDownCounter};
+use opentelemetry::trace::{SpanBuilder, SpanKind, Status, TraceContextExt};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_semantic_conventions::attribute::{
+    CLIENT_ADDRESS, ERROR_TYPE, HTTP_REQUEST_METHOD, HTTP_REQUEST_RESEND_COUNT,
+    HTTP_RESPONSE_BODY_SIZE, HTTP_RESPONSE_STATUS_CODE, HTTP_ROUTE, NETWORK_PROTOCOL_VERSION,
+    SERVER_ADDRESS, SERVER_PORT, URL_FULL, URL_PATH, URL_QUERY, URL_SCHEME, USER_AGENT_ORIGINAL,
+};
+use pin_project_lite::pin_project;
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use tower_layer::Layer;
+use tower_service::Service;
+
+const HTTP_ACCESS: &str = "http.access";
+const HTTP_SERVER_REQUEST_DURATION: &str = "http.server.request.duration";
+
+static CLIENT_DURATION_HISTOGRAM: OnceLock<Histogram<f64>> = OnceLock::new();
+
+fn client_duration_histogram() -> &'static Histogram<f64> {
+    CLIENT_DURATION_HISTOGRAM.get_or_init(|| {
+        crate::metrics::crate_meter()
+            .f64_histogram("http.client.duration")
+            .with_unit("s")
+            .with_description("Duration of HTTP client requests")
+            .init()
+    })
+}
+
+/// The instruments of the HTTP server metrics, see [`OtelServerService`].
+struct ServerInstruments {
+    duration: Histogram<f64>,
+    active_requests: UpDownCounter<i64>,
+    request_body_size: Histogram<u64>,
+}
+
+static SERVER_INSTRUMENTS: OnceLock<ServerInstruments> = OnceLock::new();
+
+fn server_instruments() -> &'static ServerInstruments {
+    SERVER_INSTRUMENTS.get_or_init(|| {
+        let meter = crate::metrics::crate_meter();
+        ServerInstruments {
+            duration: meter
+                .f64_histogram(HTTP_SERVER_REQUEST_DURATION)
+                .with_unit("s")
+                .with_description("Duration of HTTP server requests")
+                .init(),
+            active_requests: meter
+                .i64_up_down_counter("http.server.active_requests")
+                .with_unit("{request}")
+                .with_description("Number of active HTTP server requests")
+                .init(),
+            request_body_size: meter
+                .u64_histogram("http.server.request.body.size")
+                .with_unit("By")
+                .with_description("Size of HTTP server request bodies")
+                .init(),
+        }
+    })
+}
+
+/// Record a retry of the in-flight client request as an `http.retry` event,
+/// with the `http.request.resend_count` attribute.
+///
+/// Call it from a retry policy (e.g. `tower::retry::Policy::retry`) running
+/// inside an [`OtelClientService`], where the client span is the active span.
+pub fn record_retry_attempt(resend_count: u32, reason: impl Display) {
+    let cx = Context::current();
+    let span = cx.span();
+    span.add_event(
+        "http.retry",
+        vec![
+            KeyValue::new(HTTP_REQUEST_RESEND_COUNT, resend_count as i64),
+            KeyValue::new("http.retry.reason", reason.to_string()),
+        ],
+    );
+    span.set_attribute(KeyValue::new(
+        HTTP_REQUEST_RESEND_COUNT,
+        resend_count as i64,
+    ));
+}
+
+/// The request side of an HTTP access log record, see [`emit_access_log`].
+#[derive(Debug, Clone, Default)]
+pub struct AccessLogRequest {
+    /// The request method, e.g. `GET`.
+    pub method: String,
+    /// The path of the request URL.
+    pub path: String,
+    /// The query of the request URL, without the leading `?`.
+    pub query: Option<String>,
+    /// The matched route template, e.g. `/users/{id}`.
+    pub route: Option<String>,
+    /// The address of the client.
+    pub client_address: Option<String>,
+    /// The `User-Agent` header.
+    pub user_agent: Option<String>,
+}
+
+impl AccessLogRequest {
+    /// Read the method, URL and `User-Agent` header of `req`.
+    pub fn from_request<B>(req: &Request<B>) -> Self {
+        Self {
+            method: req.method().as_str().to_owned(),
+            path: req.uri().path().to_owned(),
+            query: req.uri().query().map(str::to_owned),
+            route: None,
+            client_address: None,
+            user_agent: req
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned),
+        }
+    }
+}
+
+/// The response side of an HTTP access log record, see [`emit_access_log`].
+#[derive(Debug, Clone, Default)]
+pub struct AccessLogResponse {
+    /// The response status code.
+    pub status: u16,
+    /// The size of the response body in bytes, if known.
+    pub body_size: Option<u64>,
+}
+
+impl AccessLogResponse {
+    /// Read the status and `Content-Length` header of `resp`.
+    pub fn from_response<B>(resp: &Response<B>) -> Self {
+        Self {
+            status: resp.status().as_u16(),
+            body_size: resp
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok()),
+        }
+    }
+}
+
+/// Emit one INFO log record for a served HTTP request, with the HTTP semantic convention
+/// attributes and its duration in seconds as `http.server.request.duration`.
+///
+/// The record is correlated with the active span if any, i.e. the server span of the request
+/// when called from within its handler or middleware.
+///
+/// ```ignore
+/// let req_info = AccessLogRequest::from_request(&req);
+/// let start = Instant::now();
+/// let resp = next.run(req).await;
+/// emit_access_log(&req_info, &AccessLogResponse::from_response(&resp), start.elapsed());
+/// ```
+pub fn emit_access_log(req: &AccessLogRequest, resp: &AccessLogResponse, latency: Duration) {
+    if !crate::is_enabled() {
+        return;
+    }
+    let Some(logger_provider) = GLOBAL_LOGGER_PROVIDER.get() else {
+        return;
+    };
+    let logger = crate::logs::scoped_logger(logger_provider, env!("CARGO_PKG_NAME"));
+    let mut record = logger.create_log_record();
+    record.set_severity_number(Severity::Info);
+    record.set_severity_text("INFO");
+    record.set_body(
+        format!(
+            "{} {} {} {}ms",
+            req.method,
+            req.route.as_deref().unwrap_or(&req.path),
+            resp.status,
+            latency.as_millis()
+        )
+        .into(),
+    );
+    record.add_attribute("event.name", HTTP_ACCESS);
+    record.add_attribute(HTTP_REQUEST_METHOD, req.method.clone());
+    record.add_attribute(URL_PATH, req.path.clone());
+    if let Some(query) = &req.query {
+        record.add_attribute(URL_QUERY, query.clone());
+    }
+    if let Some(route) = &req.route {
+        record.add_attribute(HTTP_ROUTE, route.clone());
+    }
+    if let Some(client_address) = &req.client_address {
+        record.add_attribute(CLIENT_ADDRESS, client_address.clone());
+    }
+    if let Some(user_agent) = &req.user_agent {
+        record.add_attribute(USER_AGENT_ORIGINAL, user_agent.clone());
+    }
+    record.add_attribute(HTTP_RESPONSE_STATUS_CODE, resp.status as i64);
+    if let Some(body_size) = resp.body_size {
+        record.add_attribute(HTTP_RESPONSE_BODY_SIZE, body_size as i64);
+    }
+    record.add_attribute(HTTP_SERVER_REQUEST_DURATION, latency.as_secs_f64());
+    logger.emit(record);
+}
+
+/// A [`Layer`] wrapping services with [`OtelServerService`].
+///
+/// The server spans and the server metrics are toggled independently, e.g. to keep the
+/// metrics of a route whose spans are too noisy. Both are enabled by default.
+#[derive(Debug, Clone, Copy, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct OtelServerLayer {
+    /// Whether each request gets a server span.
+    spans: bool,
+    /// Whether the HTTP server metrics are recorded.
+    metrics: bool,
+}
+
+impl Default for OtelServerLayer {
+    fn default() -> Self {
+        Self {
+            spans: true,
+            metrics: true,
+        }
+    }
+}
+
+impl OtelServerLayer {
+    /// Create a new OtelServerLayer, with both spans and metrics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for OtelServerLayer {
+    type Service = OtelServerService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        OtelServerService {
+            inner,
+            layer: *self,
+        }
+    }
+}
+
+/// Wraps an HTTP server service, following the HTTP semantic conventions: each request gets
+/// a server span continuing the trace context extracted from its headers with the global
+/// propagator, and is recorded into the `http.server.request.duration`,
+/// `http.server.active_requests` and `http.server.request.body.size` metrics, with the
+/// `http.request.method`, `url.scheme`, `network.protocol.version`,
+/// `http.response.status_code` and `error.type` attributes.
+///
+/// The request body size is only known from the `Content-Length` header.
+#[derive(Debug, Clone)]
+pub struct OtelServerService<S> {
+    inner: S,
+    layer: OtelServerLayer,
+}
+
+impl<S> OtelServerService<S> {
+    /// Create a new OtelServerService wrapping `inner`, with both spans and metrics.
+    pub fn new(inner: S) -> Self {
+        OtelServerLayer::new().layer(inner)
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for OtelServerService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Display,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = OtelServerFuture<S::Future>;
+
+    fn poll_ready(&mut self, task_cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(task_cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let method = req.method().as_str().to_owned();
+        let otel_cx = self.layer.spans.then(|| {
+            let headers = HeaderExtractor(req.headers());
+            let parent_cx = get_text_map_propagator(|propagator| propagator.extract(&headers));
+            let parent_cx = force_sample::force(parent_cx, &headers);
+            let mut attributes = vec![
+                KeyValue::new(HTTP_REQUEST_METHOD, method.clone()),
+                KeyValue::new(URL_PATH, req.uri().path().to_owned()),
+            ];
+            if let Some(query) = req.uri().query() {
+                attributes.push(KeyValue::new(URL_QUERY, query.to_owned()));
+            }
+            if let Some(user_agent) = req
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+            {
+                attributes.push(KeyValue::new(USER_AGENT_ORIGINAL, user_agent.to_owned()));
+            }
+            let builder = SpanBuilder::from_name(method.clone())
+                .with_kind(SpanKind::Server)
+                .with_attributes(attributes);
+            let span = tracer_span(builder, Some(&parent_cx));
+            force_sample::propagate(&parent_cx, Context::current_with_span(span))
+        });
+        let metrics = self.layer.metrics.then(|| {
+            let mut attributes = vec![
+                KeyValue::new(HTTP_REQUEST_METHOD, method),
+                KeyValue::new(
+                    URL_SCHEME,
+                    req.uri().scheme_str().unwrap_or("http").to_owned(),
+                ),
+            ];
+            let instruments = server_instruments();
+            instruments.active_requests.add(1, &attributes);
+            let _active = ActiveRequest {
+                attributes: attributes.clone(),
+            };
+            if let Some(body_size) = req
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+            {
+                instruments.request_body_size.record(body_size, &attributes);
+            }
+            if let Some(version) = protocol_version(req.version()) {
+                attributes.push(KeyValue::new(NETWORK_PROTOCOL_VERSION, version));
+            }
+            ServerMetrics {
+                attributes,
+                _active,
+            }
+        });
+
+        let inner = match &otel_cx {
+            Some(otel_cx) => {
+                let _guard = otel_cx.clone().attach();
+                self.inner.call(req)
+            }
+            None => self.inner.call(req),
+        };
+        OtelServerFuture {
+            inner,
+            otel_cx,
+            metrics,
+            start: Instant::now(),
+        }
+    }
+}
+
+/// The `network.protocol.version` of an HTTP version.
+fn protocol_version(version: http::Version) -> Option<&'static str> {
+    match version {
+        http::Version::HTTP_09 => Some("0.9"),
+        http::Version::HTTP_10 => Some("1.0"),
+        http::Version::HTTP_11 => Some("1.1"),
+        http::Version::HTTP_2 => Some("2"),
+        http::Version::HTTP_3 => Some("3"),
+        _ => None,
+    }
+}
+
+/// The server metrics of an in-flight request.
+#[derive(Debug)]
+struct ServerMetrics {
+    /// The attributes of the duration and body size measurements.
+    attributes: Vec<KeyValue>,
+    _active: ActiveRequest,
+}
+
+/// Counts a request into `http.server.active_requests` until it is dropped,
+/// so that cancelled requests are not counted forever.
+#[derive(Debug)]
+struct ActiveRequest {
+    attributes: Vec<KeyValue>,
+}
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        server_instruments()
+            .active_requests
+            .add(-1, &self.attributes);
+    }
+}
+
+pin_project! {
+    /// The response future of [`OtelServerService`].
+    #[derive(Debug)]
+    pub struct OtelServerFuture<F> {
+        #[pin]
+        inner: F,
+        otel_cx: Option<Context>,
+        metrics: Option<ServerMetrics>,
+        start: Instant,
+    }
+}
+
+impl<F, ResBody, E> Future for OtelServerFuture<F>
+where
+    F: Future<Output = Result<Response<ResBody>, E>>,
+    E: Display,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, task_cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = match this.otel_cx {
+            Some(otel_cx) => {
+                let _guard = otel_cx.clone().attach();
+                std::task::ready!(this.inner.poll(task_cx))
+            }
+            None => std::task::ready!(this.inner.poll(task_cx)),
+        };
+
+        let (status, error_type) = match &result {
+            Ok(response) => {
+                let status = response.status();
+                // Only the server errors are errors of a server span, the client errors being
+                // the client's.
+                let error_type = status.is_server_error().then(|| status.as_str().to_owned());
+                (Some(status.as_u16() as i64), error_type)
+            }
+            Err(_) => (None, Some("_OTHER".to_owned())),
+        };
+        if let Some(otel_cx) = this.otel_cx.take() {
+            let span = otel_cx.span();
+            if let Some(status) = status {
+                span.set_attribute(KeyValue::new(HTTP_RESPONSE_STATUS_CODE, status));
+            }
+            match &result {
+                Err(err) => span.set_status(Status::error(err.to_string())),
+                Ok(response) if response.status().is_server_error() => {
+                    span.set_status(Status::error(response.status().to_string()))
+                }
+                Ok(_) => {}
+            }
+            span.end();
+        }
+        if let Some(mut metrics) = this.metrics.take() {
+            if let Some(status) = status {
+                metrics
+                    .attributes
+                    .push(KeyValue::new(HTTP_RESPONSE_STATUS_CODE, status));
+            }
+            if let Some(error_type) = error_type {
+                metrics
+                    .attributes
+                    .push(KeyValue::new(ERROR_TYPE, error_type));
+            }
+            server_instruments()
+                .duration
+                .record(this.start.elapsed().as_secs_f64(), &metrics.attributes);
+        }
+        Poll::Ready(result)
+    }
+}
+
+/// A [`Layer`] wrapping services with [`OtelClientService`].
+#[derive(Debug, Clone, Default)]
+pub struct OtelClientLayer {
+    _private: (),
+}
+
+impl OtelClientLayer {
+    /// Create a new OtelClientLayer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for OtelClientLayer {
+    type Service = OtelClientService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        OtelClientService::new(inner)
+    }
+}
+
+/// Wraps an HTTP client service: each request gets a client span, the trace context is
+/// injected into the request headers with the global propagator, and the request duration
+/// is recorded into the `http.client.duration` histogram.
+///
+/// Retry attempts recorded with [`record_retry_attempt`] within the wrapped service
+/// become events of the client span.
+#[derive(Debug, Clone)]
+pub struct OtelClientService<S> {
+    inner: S,
+}
+
+impl<S> OtelClientService<S> {
+    /// Create a new OtelClientService wrapping `inner`.
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for OtelClientService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Display,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = OtelClientFuture<S::Future>;
+
+    fn poll_ready(&mut self, task_cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(task_cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let method = req.method().as_str().to_owned();
+        let mut attributes = vec![
+            KeyValue::new(HTTP_REQUEST_METHOD, method.clone()),
+            KeyValue::new(URL_FULL, req.uri().to_string()),
+        ];
+        let mut metric_attributes = vec![KeyValue::new(HTTP_REQUEST_METHOD, method.clone())];
+        if let Some(host) = req.uri().host() {
+            attributes.push(KeyValue::new(SERVER_ADDRESS, host.to_owned()));
+            metric_attributes.push(KeyValue::new(SERVER_ADDRESS, host.to_owned()));
+        }
+        if let Some(port) = req.uri().port_u16() {
+            attributes.push(KeyValue::new(SERVER_PORT, port as i64));
+        }
+
+        let builder = SpanBuilder::from_name(method)
+            .with_kind(SpanKind::Client)
+            .with_attributes(attributes);
+        let span = tracer_span(builder, Some(&Context::current()));
+        let otel_cx = Context::current_with_span(span);
+        get_text_map_propagator(|propagator| {
+            propagator.inject_context(&otel_cx, &mut HeaderInjector(req.headers_mut()))
+        });
+
+        let inner = {
+            let _guard = otel_cx.clone().attach();
+            self.inner.call(req)
+        };
+        OtelClientFuture {
+            inner,
+            otel_cx,
+            metric_attributes,
+            start: Instant::now(),
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of [`OtelClientService`].
+    #[derive(Debug)]
+    pub struct OtelClientFuture<F> {
+        #[pin]
+        inner: F,
+        otel_cx: Context,
+        metric_attributes: Vec<KeyValue>,
+        start: Instant,
+    }
+}
+
+impl<F, ResBody, E> Future for OtelClientFuture<F>
+where
+    F: Future<Output = Result<Response<ResBody>, E>>,
+    E: Display,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, task_cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = {
+            let _guard = this.otel_cx.clone().attach();
+            std::task::ready!(this.inner.poll(task_cx))
+        };
+
+        let span = this.otel_cx.span();
+        match &result {
+            Ok(response) => {
+                let status = response.status();
+                span.set_attribute(KeyValue::new(
+                    HTTP_RESPONSE_STATUS_CODE,
+                    status.as_u16() as i64,
+                ));
+                this.metric_attributes.push(KeyValue::new(
+                    HTTP_RESPONSE_STATUS_CODE,
+                    status.as_u16() as i64,
+                ));
+                if status.is_client_error() || status.is_server_error() {
+                    span.set_status(Status::error(status.to_string()));
+                    this.metric_attributes
+                        .push(KeyValue::new(ERROR_TYPE, status.as_str().to_owned()));
+                }
+            }
+            Err(err) => {
+                span.set_status(Status::error(err.to_string()));
+                this.metric_attributes
+                    .push(KeyValue::new(ERROR_TYPE, "_OTHER"));
+            }
+        }
+        span.end();
+        client_duration_histogram()
+            .record(this.start.elapsed().as_secs_f64(), this.metric_attributes);
+        Poll::Ready(result)
+    }
+}
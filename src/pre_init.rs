@@ -0,0 +1,617 @@
+use crate::logs::GLOBAL_LOGGER_PROVIDER;
+use crate::trace;
+
+use opentelemetry::logs::{AnyValue, LogRecord as _, Logger as _, Severity};
+use opentelemetry::metrics::Result as MetricsResult;
+use opentelemetry::trace::{Span as _, SpanBuilder, TraceContextExt as _, Tracer as _};
+use opentelemetry::{global, Context, KeyValue, Value};
+use opentelemetry_sdk::logs::TraceContext;
+use opentelemetry_sdk::metrics::data::{ResourceMetrics, Temporality};
+use opentelemetry_sdk::metrics::reader::{AggregationSelector, MetricReader, TemporalitySelector};
+use opentelemetry_sdk::metrics::{
+    Aggregation, InstrumentKind, ManualReader, Pipeline, SdkMeterProvider,
+};
+use opentelemetry_sdk::Resource;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::SystemTime;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Current, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Dispatch, Event, Level, Metadata, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+
+/// The ids of the spans created before initialization have this bit set, so that they are
+/// told apart from the ids of the registry, which are small.
+const PRE_INIT_ID_BIT: u64 = 1 << 63;
+
+/// Configuration of the pre-initialization buffer, see [`install_pre_init_buffer`].
+#[derive(Debug, Clone, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct PreInitBufferConfig {
+    /// The maximum number of log records queued before initialization,
+    /// beyond which the new ones are dropped. Defaults to 1024.
+    max_records: usize,
+    /// The maximum number of spans queued before initialization,
+    /// beyond which the new ones are dropped. Defaults to 256.
+    max_spans: usize,
+    /// The most verbose level queued. Defaults to `INFO`.
+    level: Level,
+}
+
+impl Default for PreInitBufferConfig {
+    fn default() -> Self {
+        Self {
+            max_records: 1024,
+            max_spans: 256,
+            level: Level::INFO,
+        }
+    }
+}
+
+static PRE_INIT: OnceLock<Arc<PreInit>> = OnceLock::new();
+
+/// The meter provider installed globally with the pre-initialization buffer, which aggregates
+/// the measurements of the instruments created before initialization.
+static PRE_INIT_METRICS: OnceLock<PreInitMetrics> = OnceLock::new();
+
+struct PreInitMetrics {
+    /// Kept alive here, as dropping the last handle of a provider shuts it down.
+    provider: SdkMeterProvider,
+    reader: SharedManualReader,
+}
+
+thread_local! {
+    /// The pre-initialization spans entered by the current thread.
+    static ENTERED: RefCell<Vec<Id>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Install a global `tracing` subscriber queuing the log records and spans emitted before
+/// [`init_otel`](crate::init_otel), which are otherwise lost, and replaying them into the
+/// providers once it completes. Call it first thing in `main`.
+///
+/// `init_otel` then makes this subscriber forward to its own rather than installing it, so that
+/// the conflict policy does not apply. The replayed spans keep their start and end times, and
+/// the replayed log records their timestamps and the spans they were emitted in. They skip the
+/// filters of the [`InitConfig`](crate::InitConfig), and those of a disabled configuration are
+/// dropped.
+///
+/// It also installs a global meter provider aggregating the measurements of the instruments
+/// created before initialization, which are then exported along with the metrics of `init_otel`,
+/// with a cumulative temporality. They are not seen by the pull metric readers.
+///
+/// Fails if a global `tracing` subscriber is already set.
+pub fn install_pre_init_buffer(config: PreInitBufferConfig) -> anyhow::Result<()> {
+    let pre_init = Arc::new(PreInit {
+        config,
+        target: OnceLock::new(),
+        buffer: Mutex::new(Buffer::default()),
+    });
+    tracing::dispatcher::set_global_default(Dispatch::new(PreInitSubscriber(pre_init.clone())))?;
+    let _ = PRE_INIT.set(pre_init);
+
+    let reader = SharedManualReader::new();
+    let provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    global::set_meter_provider(provider.clone());
+    let _ = PRE_INIT_METRICS.set(PreInitMetrics { provider, reader });
+    Ok(())
+}
+
+/// The meter provider of the pre-initialization buffer, if installed.
+pub(crate) fn pre_init_meter_provider() -> Option<&'static SdkMeterProvider> {
+    PRE_INIT_METRICS.get().map(|pre_init| &pre_init.provider)
+}
+
+/// Append the metrics of the instruments created before initialization to `metrics`,
+/// so that they are exported along with the others.
+pub(crate) fn append_pre_init_metrics(metrics: &mut ResourceMetrics) {
+    let Some(pre_init) = PRE_INIT_METRICS.get() else {
+        return;
+    };
+    let mut pre_init_metrics = ResourceMetrics {
+        resource: Resource::empty(),
+        scope_metrics: Vec::new(),
+    };
+    if let Err(err) = pre_init.reader.collect(&mut pre_init_metrics) {
+        global::handle_error(err);
+        return;
+    }
+    metrics.scope_metrics.extend(
+        pre_init_metrics
+            .scope_metrics
+            .into_iter()
+            .filter(|scope_metrics| !scope_metrics.metrics.is_empty()),
+    );
+}
+
+/// Whether the pre-initialization buffer is the global `tracing` subscriber.
+pub(crate) fn is_installed() -> bool {
+    PRE_INIT.get().is_some()
+}
+
+/// Make the pre-initialization buffer, if installed, forward to `dispatch` and replay its
+/// content. Returns whether it was installed.
+pub(crate) fn forward_to(dispatch: &Dispatch) -> bool {
+    let Some(pre_init) = PRE_INIT.get() else {
+        return false;
+    };
+    if pre_init.target.set(dispatch.clone()).is_err() {
+        return true;
+    }
+    // The interest in the callsites registered so far was that of the buffer.
+    tracing::callsite::rebuild_interest_cache();
+    let buffer = std::mem::take(&mut *pre_init.buffer.lock().unwrap());
+    buffer.replay();
+    true
+}
+
+struct PreInit {
+    config: PreInitBufferConfig,
+    /// The subscriber of `init_otel`, once initialized.
+    target: OnceLock<Dispatch>,
+    buffer: Mutex<Buffer>,
+}
+
+#[derive(Default)]
+struct Buffer {
+    next_id: u64,
+    /// By id, i.e. in creation order, so that parents come before their children.
+    spans: BTreeMap<u64, BufferedSpan>,
+    records: VecDeque<BufferedRecord>,
+}
+
+struct BufferedSpan {
+    metadata: &'static Metadata<'static>,
+    parent: Option<u64>,
+    attributes: Vec<KeyValue>,
+    start_time: SystemTime,
+    end_time: Option<SystemTime>,
+    /// The number of handles of the span, which ends when the last one closes.
+    refs: usize,
+}
+
+struct BufferedRecord {
+    metadata: &'static Metadata<'static>,
+    span: Option<u64>,
+    body: Option<String>,
+    attributes: Vec<KeyValue>,
+    timestamp: SystemTime,
+}
+
+impl Buffer {
+    /// Emit the queued spans and log records into the providers.
+    fn replay(self) {
+        let mut contexts = BTreeMap::new();
+        if let Some(tracer) = trace::try_tracer() {
+            for (id, span) in self.spans {
+                let parent_cx = span
+                    .parent
+                    .and_then(|parent| contexts.get(&parent))
+                    .cloned()
+                    .unwrap_or_else(Context::new);
+                let builder = SpanBuilder::from_name(span.metadata.name())
+                    .with_start_time(span.start_time)
+                    .with_attributes(span.attributes);
+                let mut otel_span = tracer.build_with_context(builder, &parent_cx);
+                otel_span.end_with_timestamp(span.end_time.unwrap_or_else(SystemTime::now));
+                contexts.insert(id, parent_cx.with_span(otel_span));
+            }
+        }
+        let Some(logger_provider) = GLOBAL_LOGGER_PROVIDER.get() else {
+            return;
+        };
+        for buffered in self.records {
+            let logger = crate::logs::scoped_logger(logger_provider, buffered.metadata.target());
+            let mut record = logger.create_log_record();
+            let (severity, severity_text) = severity(buffered.metadata.level());
+            record.set_severity_number(severity);
+            record.set_severity_text(severity_text);
+            record.set_target(buffered.metadata.target());
+            record.set_timestamp(buffered.timestamp);
+            if let Some(body) = buffered.body {
+                record.set_body(AnyValue::from(body));
+            }
+            for attribute in buffered.attributes {
+                record.add_attribute(attribute.key, any_value(attribute.value));
+            }
+            let span_context = buffered
+                .span
+                .and_then(|span| contexts.get(&span))
+                .map(|cx| cx.span().span_context().clone());
+            if let Some(span_context) = span_context {
+                record.trace_context = Some(TraceContext::from(&span_context));
+            }
+            logger.emit(record);
+        }
+    }
+}
+
+fn any_value(value: Value) -> AnyValue {
+    match value {
+        Value::Bool(value) => value.into(),
+        Value::I64(value) => value.into(),
+        Value::F64(value) => value.into(),
+        Value::String(value) => AnyValue::String(value),
+        value => value.to_string().into(),
+    }
+}
+
+fn severity(level: &Level) -> (Severity, &'static str) {
+    match *level {
+        Level::TRACE => (Severity::Trace, "TRACE"),
+        Level::DEBUG => (Severity::Debug, "DEBUG"),
+        Level::INFO => (Severity::Info, "INFO"),
+        Level::WARN => (Severity::Warn, "WARN"),
+        _ => (Severity::Error, "ERROR"),
+    }
+}
+
+/// Collects the fields of a span or an event, the `message` field being the body.
+#[derive(Default)]
+struct FieldVisitor {
+    body: Option<String>,
+    attributes: Vec<KeyValue>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.body = Some(value.to_owned());
+        } else {
+            self.attributes
+                .push(KeyValue::new(field.name(), value.to_owned()));
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.attributes.push(KeyValue::new(field.name(), value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.attributes
+            .push(KeyValue::new(field.name(), value as i64));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.attributes.push(KeyValue::new(field.name(), value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.attributes.push(KeyValue::new(field.name(), value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+/// The current pre-initialization span of this thread.
+fn entered() -> Option<Id> {
+    ENTERED.with(|entered| entered.borrow().last().cloned())
+}
+
+fn is_pre_init(id: &Id) -> bool {
+    id.into_u64() & PRE_INIT_ID_BIT != 0
+}
+
+/// Queues the spans and events until initialized, and then forwards them to the subscriber
+/// of `init_otel`.
+struct PreInitSubscriber(Arc<PreInit>);
+
+impl PreInitSubscriber {
+    fn target(&self) -> Option<&Dispatch> {
+        self.0.target.get()
+    }
+}
+
+impl Subscriber for PreInitSubscriber {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        match self.target() {
+            Some(target) => target.register_callsite(metadata),
+            // Not cached as never, so that the callsites are enabled once forwarding.
+            None => Interest::sometimes(),
+        }
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        match self.target() {
+            Some(target) => target.max_level_hint(),
+            None => None,
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        match self.target() {
+            Some(target) => target.enabled(metadata),
+            None => metadata.level() <= &self.0.config.level,
+        }
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        if let Some(target) = self.target() {
+            // The spans queued before initialization are unknown to the target.
+            if attrs.parent().is_some_and(is_pre_init) {
+                return target.new_span(&Attributes::new_root(attrs.metadata(), attrs.values()));
+            }
+            return target.new_span(attrs);
+        }
+        let parent = if attrs.is_contextual() {
+            entered()
+        } else {
+            attrs.parent().cloned()
+        };
+        let mut buffer = self.0.buffer.lock().unwrap();
+        buffer.next_id += 1;
+        let id = buffer.next_id | PRE_INIT_ID_BIT;
+        if buffer.spans.len() < self.0.config.max_spans {
+            let mut visitor = FieldVisitor::default();
+            attrs.record(&mut visitor);
+            buffer.spans.insert(
+                id,
+                BufferedSpan {
+                    metadata: attrs.metadata(),
+                    parent: parent.map(|parent| parent.into_u64()),
+                    attributes: visitor.attributes,
+                    start_time: SystemTime::now(),
+                    end_time: None,
+                    refs: 1,
+                },
+            );
+        }
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if !is_pre_init(span) {
+            if let Some(target) = self.target() {
+                target.record(span, values);
+            }
+            return;
+        }
+        if let Some(span) = self
+            .0
+            .buffer
+            .lock()
+            .unwrap()
+            .spans
+            .get_mut(&span.into_u64())
+        {
+            let mut visitor = FieldVisitor::default();
+            values.record(&mut visitor);
+            span.attributes.extend(visitor.attributes);
+        }
+    }
+
+    fn record_follows_from(&self, span: &Id, follows: &Id) {
+        if let Some(target) = self.target() {
+            if !is_pre_init(span) && !is_pre_init(follows) {
+                target.record_follows_from(span, follows);
+            }
+        }
+    }
+
+    fn event_enabled(&self, event: &Event<'_>) -> bool {
+        match self.target() {
+            Some(target) => target.event_enabled(event),
+            None => true,
+        }
+    }
+
+    fn event(&self, event: &Event<'_>) {
+        if let Some(target) = self.target() {
+            if !event.parent().is_some_and(is_pre_init) {
+                target.event(event);
+            }
+            return;
+        }
+        let span = if event.is_contextual() {
+            entered()
+        } else {
+            event.parent().cloned()
+        };
+        let mut buffer = self.0.buffer.lock().unwrap();
+        if buffer.records.len() >= self.0.config.max_records {
+            return;
+        }
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        buffer.records.push_back(BufferedRecord {
+            metadata: event.metadata(),
+            span: span.map(|span| span.into_u64()),
+            body: visitor.body,
+            attributes: visitor.attributes,
+            timestamp: SystemTime::now(),
+        });
+    }
+
+    fn enter(&self, span: &Id) {
+        if is_pre_init(span) {
+            ENTERED.with(|entered| entered.borrow_mut().push(span.clone()));
+        } else if let Some(target) = self.target() {
+            target.enter(span);
+        }
+    }
+
+    fn exit(&self, span: &Id) {
+        if is_pre_init(span) {
+            ENTERED.with(|entered| {
+                let mut entered = entered.borrow_mut();
+                if let Some(index) = entered.iter().rposition(|entered| entered == span) {
+                    entered.remove(index);
+                }
+            });
+        } else if let Some(target) = self.target() {
+            target.exit(span);
+        }
+    }
+
+    fn clone_span(&self, id: &Id) -> Id {
+        if !is_pre_init(id) {
+            return match self.target() {
+                Some(target) => target.clone_span(id),
+                None => id.clone(),
+            };
+        }
+        if let Some(span) = self.0.buffer.lock().unwrap().spans.get_mut(&id.into_u64()) {
+            span.refs += 1;
+        }
+        id.clone()
+    }
+
+    fn try_close(&self, id: Id) -> bool {
+        if !is_pre_init(&id) {
+            return match self.target() {
+                Some(target) => target.try_close(id),
+                None => false,
+            };
+        }
+        let mut buffer = self.0.buffer.lock().unwrap();
+        let Some(span) = buffer.spans.get_mut(&id.into_u64()) else {
+            return true;
+        };
+        span.refs -= 1;
+        if span.refs > 0 {
+            return false;
+        }
+        span.end_time = Some(SystemTime::now());
+        true
+    }
+
+    fn current_span(&self) -> Current {
+        if let Some(target) = self.target() {
+            return target.current_span();
+        }
+        let Some(id) = entered() else {
+            return Current::none();
+        };
+        match self.0.buffer.lock().unwrap().spans.get(&id.into_u64()) {
+            Some(span) => Current::new(id, span.metadata),
+            None => Current::unknown(),
+        }
+    }
+}
+
+/// A manual reader shared with the meter provider it is registered on, to collect from it.
+#[derive(Debug, Clone)]
+pub(crate) struct SharedManualReader(Arc<ManualReader>);
+
+impl SharedManualReader {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(ManualReader::builder().build()))
+    }
+}
+
+impl AggregationSelector for SharedManualReader {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.0.aggregation(kind)
+    }
+}
+
+impl TemporalitySelector for SharedManualReader {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.0.temporality(kind)
+    }
+}
+
+impl MetricReader for SharedManualReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.0.register_pipeline(pipeline)
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> MetricsResult<()> {
+        self.0.collect(rm)
+    }
+
+    fn force_flush(&self) -> MetricsResult<()> {
+        self.0.force_flush()
+    }
+
+    fn shutdown(&self) -> MetricsResult<()> {
+        self.0.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pre_init(config: PreInitBufferConfig) -> Arc<PreInit> {
+        Arc::new(PreInit {
+            config,
+            target: OnceLock::new(),
+            buffer: Mutex::new(Buffer::default()),
+        })
+    }
+
+    fn with_pre_init(pre_init: &Arc<PreInit>, f: impl FnOnce()) {
+        tracing::subscriber::with_default(PreInitSubscriber(pre_init.clone()), f)
+    }
+
+    #[test]
+    fn queues_the_records_and_spans_within_the_limits() {
+        let pre_init = pre_init(PreInitBufferConfig::default().with_max_records(2));
+        with_pre_init(&pre_init, || {
+            let span = tracing::info_span!("load_config", path = "app.toml");
+            let _entered = span.enter();
+            tracing::info!(attempt = 1, "loading");
+            tracing::debug!("below the level");
+            tracing::warn!("deprecated key");
+            tracing::error!("beyond the limit");
+        });
+
+        let buffer = pre_init.buffer.lock().unwrap();
+        let (&span_id, span) = buffer.spans.iter().next().unwrap();
+        assert_ne!(span_id & PRE_INIT_ID_BIT, 0);
+        assert_eq!(span.metadata.name(), "load_config");
+        assert_eq!(span.attributes, [KeyValue::new("path", "app.toml")]);
+        assert!(span.end_time.is_some());
+
+        let bodies: Vec<_> = buffer
+            .records
+            .iter()
+            .map(|record| record.body.as_deref())
+            .collect();
+        assert_eq!(bodies, [Some("loading"), Some("deprecated key")]);
+        let record = &buffer.records[0];
+        assert_eq!(record.span, Some(span_id));
+        assert_eq!(record.attributes, [KeyValue::new("attempt", 1_i64)]);
+    }
+
+    #[test]
+    fn spans_beyond_the_limit_are_not_queued() {
+        let pre_init = pre_init(PreInitBufferConfig::default().with_max_spans(1));
+        with_pre_init(&pre_init, || {
+            let outer = tracing::info_span!("outer");
+            let _entered = outer.enter();
+            let inner = tracing::info_span!("inner");
+            let _entered = inner.enter();
+            tracing::info!("within inner");
+        });
+
+        let buffer = pre_init.buffer.lock().unwrap();
+        assert_eq!(buffer.spans.len(), 1);
+        let (&outer_id, outer) = buffer.spans.iter().next().unwrap();
+        assert_eq!(outer.metadata.name(), "outer");
+        // The record points at the unqueued span, and is replayed without a span context.
+        let record = &buffer.records[0];
+        assert!(record.span.is_some_and(|span| span != outer_id));
+    }
+
+    #[test]
+    fn forwards_instead_of_queuing_once_initialized() {
+        let pre_init = pre_init(PreInitBufferConfig::default());
+        let _ = pre_init.target.set(Dispatch::none());
+        with_pre_init(&pre_init, || {
+            let _span = tracing::info_span!("after_init").entered();
+            tracing::info!("after init");
+        });
+
+        let buffer = pre_init.buffer.lock().unwrap();
+        assert!(buffer.spans.is_empty());
+        assert!(buffer.records.is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+//! Trace context propagation to spawned child processes, through environment variables,
+//! and traced child processes.
+
+use crate::tracer_span;
+
+use opentelemetry::global::get_text_map_propagator;
+use opentelemetry::trace::{SpanBuilder, Status, TraceContextExt as _};
+use opentelemetry::{Array, Context, KeyValue, StringValue, Value};
+use opentelemetry_semantic_conventions::attribute::{
+    PROCESS_COMMAND, PROCESS_COMMAND_ARGS, PROCESS_EXIT_CODE, PROCESS_PID,
+};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::process::{Command, ExitStatus, Output, Stdio};
+
+/// The environment variable carrying the propagation field `field`, e.g. `TRACEPARENT`.
+fn env_var_name(field: &str) -> String {
+    field.to_ascii_uppercase().replace('-', "_")
+}
+
+/// The environment variables carrying `cx`, using the global propagator.
+fn context_env_vars(cx: &Context) -> Vec<(String, String)> {
+    let mut fields = HashMap::new();
+    get_text_map_propagator(|propagator| propagator.inject_context(cx, &mut fields));
+    fields
+        .into_iter()
+        .map(|(field, value)| (env_var_name(&field), value))
+        .collect()
+}
+
+/// Inject the current context into the environment of `command`, using the global propagator,
+/// so that the work of the child process appears under the current trace, see
+/// [`extract_from_env`].
+///
+/// The fields are carried by upper-cased environment variables, e.g. `TRACEPARENT`,
+/// `TRACESTATE` and `BAGGAGE`.
+///
+/// ```ignore
+/// let mut command = Command::new("cargo");
+/// command.arg("build");
+/// inject_into_env(&mut command);
+/// let status = command.status()?;
+/// ```
+pub fn inject_into_env(command: &mut Command) {
+    command.envs(context_env_vars(&Context::current()));
+}
+
+/// Extract the context injected by the parent process with [`inject_into_env`] from the
+/// environment of this process, into a new root context with the global propagator.
+///
+/// ```ignore
+/// let parent_cx = extract_from_env();
+/// let span = tracer_span(SpanBuilder::from_name("build"), Some(&parent_cx));
+/// ```
+pub fn extract_from_env() -> Context {
+    get_text_map_propagator(|propagator| {
+        let fields: HashMap<String, String> = propagator
+            .fields()
+            .filter_map(|field| {
+                let value = std::env::var(env_var_name(field)).ok()?;
+                Some((field.to_owned(), value))
+            })
+            .collect();
+        propagator.extract_with_context(&Context::new(), &fields)
+    })
+}
+
+/// Wraps a [`tokio::process::Command`], so that running it opens a span covering the lifetime
+/// of the child process, with the `process.command`, `process.command_args`, `process.pid`
+/// and `process.exit.code` attributes, as a child of the current context. The span context
+/// is injected into the environment of the child process, see [`inject_into_env`].
+///
+/// A child process failing to spawn or exiting unsuccessfully sets the error status of the span.
+///
+/// ```ignore
+/// let status = TracedCommand::new("cargo").arg("build").status().await?;
+/// ```
+#[derive(Debug)]
+pub struct TracedCommand {
+    inner: tokio::process::Command,
+    program: String,
+    args: Vec<String>,
+}
+
+impl TracedCommand {
+    /// Create a command running `program`, see [`tokio::process::Command::new`].
+    pub fn new(program: impl AsRef<OsStr>) -> Self {
+        Self {
+            inner: tokio::process::Command::new(program.as_ref()),
+            program: program.as_ref().to_string_lossy().into_owned(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Add an argument to pass to the program.
+    pub fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self {
+        self.args.push(arg.as_ref().to_string_lossy().into_owned());
+        self.inner.arg(arg);
+        self
+    }
+
+    /// Add arguments to pass to the program.
+    pub fn args(&mut self, args: impl IntoIterator<Item = impl AsRef<OsStr>>) -> &mut Self {
+        for arg in args {
+            self.arg(arg);
+        }
+        self
+    }
+
+    /// The wrapped command, e.g. to set its environment, working directory or standard I/O.
+    /// Its arguments should be added with [`TracedCommand::arg`], to be recorded.
+    pub fn command_mut(&mut self) -> &mut tokio::process::Command {
+        &mut self.inner
+    }
+
+    /// Run the command and wait for it to exit, see [`tokio::process::Command::status`].
+    pub async fn status(&mut self) -> io::Result<ExitStatus> {
+        let cx = self.start_span();
+        let result = match self.inner.spawn() {
+            Ok(mut child) => {
+                if let Some(pid) = child.id() {
+                    cx.span()
+                        .set_attribute(KeyValue::new(PROCESS_PID, pid as i64));
+                }
+                child.wait().await
+            }
+            Err(err) => Err(err),
+        };
+        end_span(&cx, result.as_ref().copied());
+        result
+    }
+
+    /// Run the command and collect its output, see [`tokio::process::Command::output`].
+    /// The standard output and error are captured.
+    pub async fn output(&mut self) -> io::Result<Output> {
+        let cx = self.start_span();
+        self.inner.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let result = match self.inner.spawn() {
+            Ok(child) => {
+                if let Some(pid) = child.id() {
+                    cx.span()
+                        .set_attribute(KeyValue::new(PROCESS_PID, pid as i64));
+                }
+                child.wait_with_output().await
+            }
+            Err(err) => Err(err),
+        };
+        end_span(&cx, result.as_ref().map(|output| output.status));
+        result
+    }
+
+    /// Start the span of the child process, and inject it into its environment.
+    fn start_span(&mut self) -> Context {
+        let args: Vec<StringValue> = std::iter::once(self.program.clone())
+            .chain(self.args.iter().cloned())
+            .map(StringValue::from)
+            .collect();
+        let builder = SpanBuilder::from_name(self.program.clone()).with_attributes([
+            KeyValue::new(PROCESS_COMMAND, self.program.clone()),
+            KeyValue::new(PROCESS_COMMAND_ARGS, Value::Array(Array::String(args))),
+        ]);
+        let span = tracer_span(builder, Some(&Context::current()));
+        let cx = Context::current_with_span(span);
+        self.inner.envs(context_env_vars(&cx));
+        cx
+    }
+}
+
+/// Record the outcome of the child process of the span of `cx`, and end it.
+fn end_span(cx: &Context, result: Result<ExitStatus, &io::Error>) {
+    let span = cx.span();
+    match result {
+        Ok(status) => {
+            if let Some(code) = status.code() {
+                span.set_attribute(KeyValue::new(PROCESS_EXIT_CODE, code as i64));
+            }
+            if !status.success() {
+                span.set_status(Status::error(status.to_string()));
+            }
+        }
+        Err(err) => span.set_status(Status::error(err.to_string())),
+    }
+    span.end();
+}
@@ -0,0 +1,214 @@
+pub use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+#[cfg(feature = "jaeger-compat")]
+pub use opentelemetry_jaeger_propagator::Propagator as JaegerPropagator;
+pub use opentelemetry_sdk::propagation::{BaggagePropagator, TraceContextPropagator};
+
+use crate::baggage::LimitedBaggagePropagator;
+use crate::{AllowlistBaggagePropagator, BaggageLimits};
+
+use opentelemetry::global::get_text_map_propagator;
+use opentelemetry::propagation::TextMapCompositePropagator;
+use opentelemetry::trace::{SpanContext, TraceContextExt as _, TraceFlags};
+use opentelemetry::{Context, KeyValue};
+use std::collections::HashMap;
+use tracing_opentelemetry::OpenTelemetrySpanExt as _;
+
+/// Re-export opentelemetry::propagation;
+pub mod otel_propagation {
+    pub use opentelemetry::propagation::*;
+}
+
+/// The text map propagators that can be installed globally by `init_otel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Propagator {
+    /// W3C `traceparent` / `tracestate` headers.
+    TraceContext,
+    /// W3C `baggage` header.
+    Baggage,
+    /// Jaeger `uber-trace-id` header, for legacy Jaeger-instrumented services.
+    #[cfg(feature = "jaeger-compat")]
+    Jaeger,
+}
+
+impl Propagator {
+    fn build(
+        self,
+        baggage_allowlist: Option<&[String]>,
+        baggage_limits: Option<BaggageLimits>,
+    ) -> Box<dyn TextMapPropagator + Send + Sync> {
+        match self {
+            Propagator::TraceContext => Box::new(TraceContextPropagator::new()),
+            Propagator::Baggage => match (baggage_allowlist, baggage_limits) {
+                (allowlist, Some(limits)) => Box::new(LimitedBaggagePropagator::new(
+                    allowlist.map(<[String]>::to_vec),
+                    limits,
+                )),
+                (Some(allowlist), None) => {
+                    Box::new(AllowlistBaggagePropagator::new(allowlist.to_vec()))
+                }
+                (None, None) => Box::new(BaggagePropagator::new()),
+            },
+            #[cfg(feature = "jaeger-compat")]
+            Propagator::Jaeger => Box::new(JaegerPropagator::new()),
+        }
+    }
+}
+
+/// Install the composite of `propagators` as the global text map propagator.
+/// Nothing is installed if `propagators` is empty.
+pub(crate) fn init_propagators(
+    propagators: &[Propagator],
+    baggage_allowlist: Option<&[String]>,
+    baggage_limits: Option<BaggageLimits>,
+) {
+    if propagators.is_empty() {
+        return;
+    }
+    let propagators = propagators
+        .iter()
+        .map(|p| p.build(baggage_allowlist, baggage_limits))
+        .collect();
+    opentelemetry::global::set_text_map_propagator(TextMapCompositePropagator::new(propagators));
+}
+
+/// Configuration of [`extract_context`].
+#[derive(Debug, Clone, Default)]
+pub struct PropagationConfig {
+    lenient: bool,
+}
+
+impl PropagationConfig {
+    /// Create a configuration with the lenient parser mode on or off.
+    ///
+    /// In lenient mode, header values are trimmed, unquoted, lower-cased and
+    /// de-duplicated before parsing, and a trace header that still cannot be parsed
+    /// yields a fresh root context instead of an error. This suits browser-facing
+    /// (e.g. gRPC-Web) endpoints, where proxies and vendor scripts mangle headers.
+    pub fn lenient(lenient: bool) -> Self {
+        Self { lenient }
+    }
+}
+
+/// Extract the remote context from `extractor` into a new root context with the global
+/// propagator, matching header names case-insensitively.
+///
+/// A force-sampling header marks the context as forced, see
+/// [`ForceSampleConfig`](crate::ForceSampleConfig).
+///
+/// Fails if a trace header is present but malformed, unless the configuration is lenient.
+pub fn extract_context(
+    extractor: &dyn Extractor,
+    config: &PropagationConfig,
+) -> anyhow::Result<Context> {
+    let headers = NormalizedExtractor::new(extractor, config.lenient);
+    let cx = get_text_map_propagator(|propagator| {
+        propagator.extract_with_context(&Context::new(), &headers)
+    });
+    if !config.lenient && !cx.has_active_span() && headers.has_trace_header() {
+        anyhow::bail!("malformed trace context header");
+    }
+    Ok(crate::force_sample::force(cx, &headers))
+}
+
+/// The header names read and written by the global propagator,
+/// to be allowed in CORS `Access-Control-Allow-Headers` for browser clients.
+pub fn cors_allowed_headers() -> Vec<String> {
+    get_text_map_propagator(|propagator| propagator.fields().map(str::to_owned).collect())
+}
+
+/// Format `span_context` as a W3C `traceparent` value,
+/// e.g. `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+pub fn span_context_to_traceparent(span_context: &SpanContext) -> String {
+    format!(
+        "{:02x}-{}-{}-{:02x}",
+        0,
+        span_context.trace_id(),
+        span_context.span_id(),
+        span_context.trace_flags() & TraceFlags::SAMPLED
+    )
+}
+
+/// Parse a W3C `traceparent` value into a remote span context.
+///
+/// Fails if `traceparent` is malformed.
+pub fn traceparent_to_span_context(traceparent: &str) -> anyhow::Result<SpanContext> {
+    let headers = HashMap::from([("traceparent".to_owned(), traceparent.to_owned())]);
+    let cx = TraceContextPropagator::new().extract(&headers);
+    let span_context = cx.span().span_context().clone();
+    if !span_context.is_valid() {
+        anyhow::bail!("malformed traceparent: {traceparent:?}");
+    }
+    Ok(span_context)
+}
+
+/// Add a link from the current `tracing` span to the span identified by the W3C `traceparent`,
+/// e.g. to correlate a batch job with the request trace carried in a message payload.
+///
+/// Fails if `traceparent` is malformed.
+pub fn link_current_span_to(traceparent: &str, attributes: Vec<KeyValue>) -> anyhow::Result<()> {
+    let span_context = traceparent_to_span_context(traceparent)?;
+    tracing::Span::current().add_link_with_attributes(span_context, attributes);
+    Ok(())
+}
+
+const TRACE_HEADERS: [&str; 2] = ["traceparent", "uber-trace-id"];
+
+/// An extractor with lower-cased keys, and optionally cleaned-up values.
+struct NormalizedExtractor {
+    headers: HashMap<String, String>,
+}
+
+impl NormalizedExtractor {
+    fn new(extractor: &dyn Extractor, lenient: bool) -> Self {
+        let headers = extractor
+            .keys()
+            .into_iter()
+            .filter_map(|key| {
+                let lowercase_key = key.to_ascii_lowercase();
+                // Some extractors (e.g. `HashMap`) only look up lower-cased keys.
+                let value = extractor
+                    .get(key)
+                    .or_else(|| extractor.get(&lowercase_key))?;
+                let key = lowercase_key;
+                let value = if lenient {
+                    Self::clean(&key, value)
+                } else {
+                    value.to_owned()
+                };
+                Some((key, value))
+            })
+            .collect();
+        Self { headers }
+    }
+
+    fn clean(key: &str, value: &str) -> String {
+        let mut value = value.trim();
+        if TRACE_HEADERS.contains(&key) {
+            // Headers repeated by proxies may be joined with commas: keep the first one.
+            value = value.split(',').next().unwrap_or_default().trim();
+            value = value.trim_matches(|c| c == '"' || c == '\'');
+            value.to_ascii_lowercase()
+        } else {
+            value.to_owned()
+        }
+    }
+
+    fn has_trace_header(&self) -> bool {
+        TRACE_HEADERS
+            .iter()
+            .any(|header| self.headers.contains_key(*header))
+    }
+}
+
+impl Extractor for NormalizedExtractor {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.headers
+            .get(&key.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.headers.keys().map(String::as_str).collect()
+    }
+}
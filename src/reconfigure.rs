@@ -0,0 +1,543 @@
+use crate::{logs, metrics, snapshot, trace};
+
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use futures_util::lock::Mutex as AsyncMutex;
+use opentelemetry::logs::LogResult;
+use opentelemetry::logs::Severity;
+use opentelemetry::metrics::Result as MetricsResult;
+use opentelemetry::trace::{Link, SamplingResult, SpanKind, TraceId};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::export::logs::{LogBatch, LogExporter};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::metrics::data::{ResourceMetrics, Temporality};
+use opentelemetry_sdk::metrics::exporter::PushMetricsExporter;
+use opentelemetry_sdk::metrics::reader::{AggregationSelector, MetricReader, TemporalitySelector};
+#[cfg(feature = "wasm")]
+use opentelemetry_sdk::metrics::ManualReader;
+#[cfg(not(feature = "wasm"))]
+use opentelemetry_sdk::metrics::PeriodicReader;
+use opentelemetry_sdk::metrics::{Aggregation, InstrumentKind, Pipeline};
+#[cfg(not(feature = "wasm"))]
+use opentelemetry_sdk::runtime::Tokio;
+use opentelemetry_sdk::trace::ShouldSample;
+use opentelemetry_sdk::Resource;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+#[cfg(not(feature = "wasm"))]
+use std::time::Duration;
+use tokio::sync::RwLock as AsyncRwLock;
+
+/// Handle to re-configure the providers installed by [`init_otel`](crate::init_otel)
+/// at runtime, without restarting the process.
+///
+/// It is obtained with [`reconfigure_handle`](crate::reconfigure_handle) once initialized.
+///
+/// The providers stay registered globally; only the sampler, the exporters
+/// or the metric reader behind them are swapped.
+#[derive(Debug, Clone)]
+pub struct ReconfigureHandle {
+    sampler: ReloadableSampler,
+    span_exporter: ReloadableSpanExporter,
+    log_exporter: Option<ReloadableLogExporter>,
+    metric_reader: ReloadableMetricReader,
+    resource: Resource,
+}
+
+impl ReconfigureHandle {
+    pub(crate) fn new(
+        sampler: ReloadableSampler,
+        span_exporter: ReloadableSpanExporter,
+        log_exporter: Option<ReloadableLogExporter>,
+        metric_reader: ReloadableMetricReader,
+        resource: Resource,
+    ) -> Self {
+        Self {
+            sampler,
+            span_exporter,
+            log_exporter,
+            metric_reader,
+            resource,
+        }
+    }
+
+    /// Replace the sampler used for spans started from now on.
+    ///
+    /// The sampling rules, if configured, are kept: the sampler replaces only their fallback
+    /// sampler, for the spans matching no rule.
+    pub fn set_sampler(&self, sampler: impl ShouldSample + 'static) {
+        snapshot::update_snapshot(|snapshot| snapshot.sampler = format!("{sampler:?}"));
+        self.sampler.replace(Box::new(sampler));
+    }
+
+    /// Rebuild the OTLP exporters of all signals so that they export to `endpoint`.
+    /// The previous exporters are shut down after the swap.
+    ///
+    /// Must be called from within the Tokio runtime.
+    pub async fn set_otlp_endpoint(&self, endpoint: impl Into<String>) -> anyhow::Result<()> {
+        let endpoint = endpoint.into();
+        let resource = &self.resource;
+
+        let span_exporter = trace::build_span_exporter(false, Some(&endpoint))?;
+        let log_exporter = match self.log_exporter {
+            Some(_) => Some(logs::build_log_exporter(false, Some(&endpoint))?),
+            None => None,
+        };
+        let metrics_exporter = metrics::build_metrics_exporter(false, Some(&endpoint))?;
+
+        self.span_exporter.replace(span_exporter, resource);
+        if let (Some(slot), Some(log_exporter)) = (&self.log_exporter, log_exporter) {
+            slot.replace(log_exporter, resource).await;
+        }
+        self.metric_reader
+            .exporter
+            .replace(metrics_exporter)
+            .await?;
+        snapshot::update_snapshot(|snapshot| {
+            snapshot.exporter = match crate::transport::otlp_http_client() {
+                Some(_) => "otlp-http".to_owned(),
+                None => "otlp-grpc".to_owned(),
+            };
+            snapshot.set_otlp_endpoint(Some(&endpoint));
+        });
+        Ok(())
+    }
+
+    /// Replace the periodic metric reader with one collecting every `interval`.
+    /// The previous reader is shut down after a final collection.
+    ///
+    /// Must be called from within the Tokio runtime.
+    /// Not available with the `wasm` feature, whose metrics are exported on flush only.
+    #[cfg(not(feature = "wasm"))]
+    pub async fn set_metric_interval(&self, interval: Duration) -> anyhow::Result<()> {
+        self.metric_reader.set_interval(interval).await?;
+        snapshot::update_snapshot(|snapshot| snapshot.metric_interval = interval);
+        Ok(())
+    }
+}
+
+/// A sampler delegating to a replaceable inner sampler.
+#[derive(Debug, Clone)]
+pub(crate) struct ReloadableSampler(Arc<RwLock<Box<dyn ShouldSample>>>);
+
+impl ReloadableSampler {
+    pub(crate) fn new(sampler: Box<dyn ShouldSample>) -> Self {
+        Self(Arc::new(RwLock::new(sampler)))
+    }
+
+    pub(crate) fn replace(&self, sampler: Box<dyn ShouldSample>) {
+        *self.0.write().unwrap() = sampler;
+    }
+}
+
+impl ShouldSample for ReloadableSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        self.0.read().unwrap().should_sample(
+            parent_context,
+            trace_id,
+            name,
+            span_kind,
+            attributes,
+            links,
+        )
+    }
+}
+
+/// A span exporter delegating to a replaceable inner exporter.
+#[derive(Debug, Clone)]
+pub(crate) struct ReloadableSpanExporter(Arc<Mutex<Box<dyn SpanExporter>>>);
+
+impl ReloadableSpanExporter {
+    pub(crate) fn new(exporter: Box<dyn SpanExporter>) -> Self {
+        Self(Arc::new(Mutex::new(exporter)))
+    }
+
+    fn replace(&self, mut exporter: Box<dyn SpanExporter>, resource: &Resource) {
+        exporter.set_resource(resource);
+        let mut old = std::mem::replace(&mut *self.0.lock().unwrap(), exporter);
+        old.shutdown();
+    }
+}
+
+impl SpanExporter for ReloadableSpanExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        self.0.lock().unwrap().export(batch)
+    }
+
+    fn shutdown(&mut self) {
+        self.0.lock().unwrap().shutdown()
+    }
+
+    fn force_flush(&mut self) -> BoxFuture<'static, ExportResult> {
+        self.0.lock().unwrap().force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.0.lock().unwrap().set_resource(resource)
+    }
+}
+
+/// A log exporter delegating to a replaceable inner exporter.
+#[derive(Debug, Clone)]
+pub(crate) struct ReloadableLogExporter(Arc<AsyncMutex<Box<dyn LogExporter>>>);
+
+impl ReloadableLogExporter {
+    pub(crate) fn new(exporter: Box<dyn LogExporter>) -> Self {
+        Self(Arc::new(AsyncMutex::new(exporter)))
+    }
+
+    async fn replace(&self, mut exporter: Box<dyn LogExporter>, resource: &Resource) {
+        exporter.set_resource(resource);
+        let mut old = std::mem::replace(&mut *self.0.lock().await, exporter);
+        old.shutdown();
+    }
+}
+
+#[async_trait]
+impl LogExporter for ReloadableLogExporter {
+    async fn export(&mut self, batch: LogBatch<'_>) -> LogResult<()> {
+        self.0.lock().await.export(batch).await
+    }
+
+    fn shutdown(&mut self) {
+        futures_executor::block_on(self.0.lock()).shutdown()
+    }
+
+    fn event_enabled(&self, level: Severity, target: &str, name: &str) -> bool {
+        self.0
+            .try_lock()
+            .map_or(true, |exporter| exporter.event_enabled(level, target, name))
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        futures_executor::block_on(self.0.lock()).set_resource(resource)
+    }
+}
+
+/// A push metrics exporter delegating to a replaceable inner exporter.
+///
+/// Shutting it down is a no-op, so that a replaced reader does not shut down
+/// the shared exporter; [`ReloadableMetricReader`] owns its lifecycle instead.
+#[derive(Clone)]
+pub(crate) struct ReloadableMetricsExporter(Arc<RwLock<Arc<MetricsExporterSlot>>>);
+
+/// An inner exporter of [`ReloadableMetricsExporter`], with the exports in flight on it.
+struct MetricsExporterSlot {
+    exporter: Box<dyn PushMetricsExporter>,
+    in_flight: AsyncRwLock<()>,
+}
+
+impl ReloadableMetricsExporter {
+    pub(crate) fn new(exporter: Box<dyn PushMetricsExporter>) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(MetricsExporterSlot {
+            exporter,
+            in_flight: AsyncRwLock::new(()),
+        }))))
+    }
+
+    fn current(&self) -> Arc<MetricsExporterSlot> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Swap in `exporter`, then shut down the previous one once its exports in flight are done.
+    async fn replace(&self, exporter: Box<dyn PushMetricsExporter>) -> MetricsResult<()> {
+        let slot = Arc::new(MetricsExporterSlot {
+            exporter,
+            in_flight: AsyncRwLock::new(()),
+        });
+        let old = std::mem::replace(&mut *self.0.write().unwrap(), slot);
+        let _drained = old.in_flight.write().await;
+        old.exporter.shutdown()
+    }
+}
+
+impl Debug for ReloadableMetricsExporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ReloadableMetricsExporter")
+            .field(&"<dyn PushMetricsExporter>")
+            .finish()
+    }
+}
+
+impl AggregationSelector for ReloadableMetricsExporter {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.current().exporter.aggregation(kind)
+    }
+}
+
+impl TemporalitySelector for ReloadableMetricsExporter {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.current().exporter.temporality(kind)
+    }
+}
+
+#[async_trait]
+impl PushMetricsExporter for ReloadableMetricsExporter {
+    async fn export(&self, metrics: &mut ResourceMetrics) -> MetricsResult<()> {
+        crate::pre_init::append_pre_init_metrics(metrics);
+        let slot = self.current();
+        let _in_flight = slot.in_flight.read().await;
+        slot.exporter.export(metrics).await
+    }
+
+    async fn force_flush(&self) -> MetricsResult<()> {
+        let slot = self.current();
+        let _in_flight = slot.in_flight.read().await;
+        slot.exporter.force_flush().await
+    }
+
+    fn shutdown(&self) -> MetricsResult<()> {
+        Ok(())
+    }
+}
+
+/// The metric reader exporting on its own, periodically on the Tokio runtime.
+#[cfg(not(feature = "wasm"))]
+type ExportingReader = PeriodicReader;
+
+/// The metric reader exporting on its own, when flushed only, since there is no Tokio runtime
+/// to export periodically on with the `wasm` feature.
+#[cfg(feature = "wasm")]
+type ExportingReader = FlushingReader;
+
+/// A metric reader delegating to a replaceable [`ExportingReader`].
+#[derive(Debug, Clone)]
+pub(crate) struct ReloadableMetricReader {
+    reader: Arc<RwLock<ExportingReader>>,
+    pipeline: Arc<Mutex<Option<Weak<Pipeline>>>>,
+    exporter: ReloadableMetricsExporter,
+}
+
+impl ReloadableMetricReader {
+    pub(crate) fn new(exporter: ReloadableMetricsExporter) -> Self {
+        #[cfg(not(feature = "wasm"))]
+        let reader = PeriodicReader::builder(exporter.clone(), Tokio).build();
+        #[cfg(feature = "wasm")]
+        let reader = FlushingReader::new(exporter.clone());
+        Self {
+            reader: Arc::new(RwLock::new(reader)),
+            pipeline: Default::default(),
+            exporter,
+        }
+    }
+
+    /// Swap in a reader collecting every `interval`, then shut down the previous one,
+    /// off the runtime since its shutdown blocks until its final export is done.
+    #[cfg(not(feature = "wasm"))]
+    async fn set_interval(&self, interval: Duration) -> anyhow::Result<()> {
+        let reader = PeriodicReader::builder(self.exporter.clone(), Tokio)
+            .with_interval(interval)
+            .build();
+        if let Some(pipeline) = self.pipeline.lock().unwrap().clone() {
+            reader.register_pipeline(pipeline);
+        }
+        let old = std::mem::replace(&mut *self.reader.write().unwrap(), reader);
+        tokio::task::spawn_blocking(move || old.shutdown()).await??;
+        Ok(())
+    }
+}
+
+impl AggregationSelector for ReloadableMetricReader {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.exporter.aggregation(kind)
+    }
+}
+
+impl TemporalitySelector for ReloadableMetricReader {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.exporter.temporality(kind)
+    }
+}
+
+impl MetricReader for ReloadableMetricReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        *self.pipeline.lock().unwrap() = Some(pipeline.clone());
+        self.reader.read().unwrap().register_pipeline(pipeline)
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> MetricsResult<()> {
+        self.reader.read().unwrap().collect(rm)
+    }
+
+    fn force_flush(&self) -> MetricsResult<()> {
+        let reader = self.reader.read().unwrap().clone();
+        reader.force_flush()
+    }
+
+    fn shutdown(&self) -> MetricsResult<()> {
+        let reader = self.reader.read().unwrap().clone();
+        let result = reader.shutdown();
+        let slot = self.exporter.current();
+        result.and(slot.exporter.shutdown())
+    }
+}
+
+/// A metric reader collecting and exporting the metrics synchronously when flushed.
+#[cfg(feature = "wasm")]
+#[derive(Debug, Clone)]
+pub(crate) struct FlushingReader {
+    reader: Arc<ManualReader>,
+    exporter: ReloadableMetricsExporter,
+}
+
+#[cfg(feature = "wasm")]
+impl FlushingReader {
+    fn new(exporter: ReloadableMetricsExporter) -> Self {
+        let reader = ManualReader::builder()
+            .with_aggregation_selector(exporter.clone())
+            .with_temporality_selector(exporter.clone())
+            .build();
+        Self {
+            reader: Arc::new(reader),
+            exporter,
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl AggregationSelector for FlushingReader {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.exporter.aggregation(kind)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl TemporalitySelector for FlushingReader {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.exporter.temporality(kind)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl MetricReader for FlushingReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.reader.register_pipeline(pipeline)
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> MetricsResult<()> {
+        self.reader.collect(rm)
+    }
+
+    fn force_flush(&self) -> MetricsResult<()> {
+        let mut rm = ResourceMetrics {
+            resource: Resource::empty(),
+            scope_metrics: Vec::new(),
+        };
+        self.reader.collect(&mut rm)?;
+        futures_executor::block_on(self.exporter.export(&mut rm))
+    }
+
+    fn shutdown(&self) -> MetricsResult<()> {
+        let result = self.force_flush();
+        result.and(self.reader.shutdown())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt as _;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// An exporter counting its exports, and recording whether it was shut down.
+    #[derive(Debug, Clone, Default)]
+    struct Recording {
+        exports: Arc<AtomicUsize>,
+        shut_down: Arc<AtomicBool>,
+    }
+
+    impl Recording {
+        fn exports(&self) -> usize {
+            self.exports.load(Ordering::Relaxed)
+        }
+
+        fn is_shut_down(&self) -> bool {
+            self.shut_down.load(Ordering::Relaxed)
+        }
+    }
+
+    impl SpanExporter for Recording {
+        fn export(&mut self, _batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+            self.exports.fetch_add(1, Ordering::Relaxed);
+            Box::pin(std::future::ready(Ok(())))
+        }
+
+        fn shutdown(&mut self) {
+            self.shut_down.store(true, Ordering::Relaxed);
+        }
+    }
+
+    impl AggregationSelector for Recording {
+        fn aggregation(&self, _kind: InstrumentKind) -> Aggregation {
+            Aggregation::Default
+        }
+    }
+
+    impl TemporalitySelector for Recording {
+        fn temporality(&self, _kind: InstrumentKind) -> Temporality {
+            Temporality::Cumulative
+        }
+    }
+
+    #[async_trait]
+    impl PushMetricsExporter for Recording {
+        async fn export(&self, _metrics: &mut ResourceMetrics) -> MetricsResult<()> {
+            self.exports.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        async fn force_flush(&self) -> MetricsResult<()> {
+            Ok(())
+        }
+
+        fn shutdown(&self) -> MetricsResult<()> {
+            self.shut_down.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn span_exporter_replacement_shuts_down_the_previous_exporter() {
+        let (old, new) = (Recording::default(), Recording::default());
+        let mut exporter = ReloadableSpanExporter::new(Box::new(old.clone()));
+        futures_executor::block_on(exporter.export(Vec::new())).unwrap();
+
+        exporter.replace(Box::new(new.clone()), &Resource::empty());
+        assert!(old.is_shut_down());
+        futures_executor::block_on(exporter.export(Vec::new())).unwrap();
+        assert_eq!((old.exports(), new.exports()), (1, 1));
+        assert!(!new.is_shut_down());
+    }
+
+    #[test]
+    fn metrics_exporter_replacement_waits_for_the_exports_in_flight() {
+        let (old, new) = (Recording::default(), Recording::default());
+        let exporter = ReloadableMetricsExporter::new(Box::new(old.clone()));
+        let old_slot = exporter.current();
+        let in_flight = old_slot.in_flight.try_read().unwrap();
+
+        let mut replace = Box::pin(exporter.replace(Box::new(new.clone())));
+        assert!((&mut replace).now_or_never().is_none());
+        assert!(!old.is_shut_down());
+        // The exports started after the swap already go to the new exporter.
+        let mut metrics = ResourceMetrics {
+            resource: Resource::empty(),
+            scope_metrics: Vec::new(),
+        };
+        futures_executor::block_on(exporter.export(&mut metrics)).unwrap();
+        assert_eq!((old.exports(), new.exports()), (0, 1));
+
+        drop(in_flight);
+        futures_executor::block_on(replace).unwrap();
+        assert!(old.is_shut_down());
+        assert!(!new.is_shut_down());
+    }
+}
@@ -0,0 +1,219 @@
+//! Instrumentation of streaming RPCs, e.g. gRPC streams.
+
+use crate::tracer_span;
+
+use futures_util::Stream;
+use opentelemetry::metrics::Counter;
+use opentelemetry::trace::{SpanBuilder, SpanKind, Status, TraceContextExt as _};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_semantic_conventions::attribute::{RPC_METHOD, RPC_SERVICE, RPC_SYSTEM};
+use pin_project_lite::pin_project;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context as TaskContext, Poll};
+
+const RPC_MESSAGE: &str = "rpc.message";
+const RPC_MESSAGE_TYPE: &str = "rpc.message.type";
+const RPC_MESSAGE_ID: &str = "rpc.message.id";
+const RPC_MESSAGE_UNCOMPRESSED_SIZE: &str = "rpc.message.uncompressed_size";
+
+/// The counters of the streamed messages, and of their bytes.
+struct StreamCounters {
+    messages: Counter<u64>,
+    bytes: Counter<u64>,
+}
+
+static STREAM_COUNTERS: OnceLock<StreamCounters> = OnceLock::new();
+
+fn stream_counters() -> &'static StreamCounters {
+    STREAM_COUNTERS.get_or_init(|| {
+        let meter = crate::metrics::crate_meter();
+        StreamCounters {
+            messages: meter
+                .u64_counter("rpc.stream.messages")
+                .with_unit("{message}")
+                .with_description("Number of messages sent or received on RPC streams")
+                .init(),
+            bytes: meter
+                .u64_counter("rpc.stream.bytes")
+                .with_unit("By")
+                .with_description("Uncompressed size of the messages sent or received on streams")
+                .init(),
+        }
+    })
+}
+
+/// The direction of a streamed message, see [`RpcStreamSpan::record_message`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageDirection {
+    /// The message was sent.
+    Sent,
+    /// The message was received.
+    Received,
+}
+
+impl MessageDirection {
+    /// The `rpc.message.type` of the direction.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageDirection::Sent => "SENT",
+            MessageDirection::Received => "RECEIVED",
+        }
+    }
+}
+
+/// The span of a streaming RPC, covering the whole stream, with an `rpc.message` event
+/// for each sent or received message, carrying its `rpc.message.type`, its `rpc.message.id`
+/// (counted from 1 per direction) and its `rpc.message.uncompressed_size` if known.
+///
+/// The messages are also counted into the `rpc.stream.messages` counter, and their sizes into
+/// the `rpc.stream.bytes` counter, by `rpc.system`, `rpc.service`, `rpc.method` and
+/// `rpc.message.type`, to follow the throughput of the streams.
+///
+/// It works with any stream, e.g. the `Streaming` request or response body of tonic:
+///
+/// ```ignore
+/// let span = RpcStreamSpan::start("grpc", "chat.Chat", "Subscribe", SpanKind::Server);
+/// let requests = span.instrument(request.into_inner(), MessageDirection::Received, |_| None);
+/// // ...
+/// span.end(None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RpcStreamSpan {
+    inner: Arc<StreamSpanInner>,
+}
+
+#[derive(Debug)]
+struct StreamSpanInner {
+    cx: Context,
+    attributes: [KeyValue; 3],
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl RpcStreamSpan {
+    /// Start the span of a stream of the RPC `service/method` of `system` (e.g. `grpc`),
+    /// as a child of the current context.
+    pub fn start(
+        system: impl Into<String>,
+        service: impl Into<String>,
+        method: impl Into<String>,
+        kind: SpanKind,
+    ) -> Self {
+        let (service, method) = (service.into(), method.into());
+        let attributes = [
+            KeyValue::new(RPC_SYSTEM, system.into()),
+            KeyValue::new(RPC_SERVICE, service.clone()),
+            KeyValue::new(RPC_METHOD, method.clone()),
+        ];
+        let builder = SpanBuilder::from_name(format!("{service}/{method}"))
+            .with_kind(kind)
+            .with_attributes(attributes.to_vec());
+        let span = tracer_span(builder, Some(&Context::current()));
+        Self {
+            inner: Arc::new(StreamSpanInner {
+                cx: Context::current_with_span(span),
+                attributes,
+                sent: AtomicU64::new(0),
+                received: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// The context of the stream span.
+    pub fn context(&self) -> &Context {
+        &self.inner.cx
+    }
+
+    /// Record a message sent or received on the stream, with its uncompressed size if known.
+    pub fn record_message(&self, direction: MessageDirection, size: Option<u64>) {
+        let counter = match direction {
+            MessageDirection::Sent => &self.inner.sent,
+            MessageDirection::Received => &self.inner.received,
+        };
+        let id = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut event_attributes = vec![
+            KeyValue::new(RPC_MESSAGE_TYPE, direction.as_str()),
+            KeyValue::new(RPC_MESSAGE_ID, id as i64),
+        ];
+        if let Some(size) = size {
+            event_attributes.push(KeyValue::new(RPC_MESSAGE_UNCOMPRESSED_SIZE, size as i64));
+        }
+        self.inner
+            .cx
+            .span()
+            .add_event(RPC_MESSAGE, event_attributes);
+
+        let mut metric_attributes = self.inner.attributes.to_vec();
+        metric_attributes.push(KeyValue::new(RPC_MESSAGE_TYPE, direction.as_str()));
+        let counters = stream_counters();
+        counters.messages.add(1, &metric_attributes);
+        if let Some(size) = size {
+            counters.bytes.add(size, &metric_attributes);
+        }
+    }
+
+    /// Wrap `stream`, recording each of its items as a message of `direction`,
+    /// whose size is given by `size`.
+    pub fn instrument<S, F>(
+        &self,
+        stream: S,
+        direction: MessageDirection,
+        size: F,
+    ) -> RpcStream<S, F>
+    where
+        S: Stream,
+        F: FnMut(&S::Item) -> Option<u64>,
+    {
+        RpcStream {
+            inner: stream,
+            span: self.clone(),
+            direction,
+            size,
+        }
+    }
+
+    /// End the stream span, with the error status `error` if the stream failed.
+    pub fn end(self, error: Option<&str>) {
+        let span = self.inner.cx.span();
+        if let Some(error) = error {
+            span.set_status(Status::error(error.to_owned()));
+        }
+        span.end();
+    }
+}
+
+pin_project! {
+    /// A stream recording its items as messages of an [`RpcStreamSpan`],
+    /// see [`RpcStreamSpan::instrument`].
+    #[derive(Debug)]
+    pub struct RpcStream<S, F> {
+        #[pin]
+        inner: S,
+        span: RpcStreamSpan,
+        direction: MessageDirection,
+        size: F,
+    }
+}
+
+impl<S, F> Stream for RpcStream<S, F>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> Option<u64>,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, task_cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = std::task::ready!(this.inner.poll_next(task_cx));
+        if let Some(item) = &item {
+            this.span.record_message(*this.direction, (this.size)(item));
+        }
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
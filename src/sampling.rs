@@ -0,0 +1,347 @@
+use crate::reconfigure::ReloadableSampler;
+
+use opentelemetry::trace::{
+    Link, SamplingDecision, SamplingResult, SpanKind, TraceContextExt as _, TraceId,
+};
+use opentelemetry::{Context, Key, KeyValue, Value};
+use opentelemetry_sdk::trace::{Sampler, ShouldSample};
+use opentelemetry_semantic_conventions::attribute::HTTP_ROUTE;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+/// A sampler applying the first matching per-span-name or per-attribute rule,
+/// and falling back to a default sampler for the other spans.
+///
+/// ```
+/// use myotel::{Sampler, SamplingRules};
+///
+/// let rules = SamplingRules::new()
+///     .with_name_rule("POST /checkout", Sampler::AlwaysOn)
+///     .with_attribute_rule("http.route", "/healthz", Sampler::TraceIdRatioBased(0.01))
+///     .with_name_rule("GET /metrics", Sampler::AlwaysOff);
+/// ```
+///
+/// Installed with `InitConfig::with_sampling_rules`, it falls back to the sampler of the
+/// tracer provider config unless [`SamplingRules::with_default_sampler`] is called.
+/// The rules are kept when that sampler is replaced with
+/// [`ReconfigureHandle::set_sampler`](crate::ReconfigureHandle::set_sampler).
+#[derive(Debug, Clone, Default)]
+pub struct SamplingRules {
+    rules: Vec<SamplingRule>,
+    default_sampler: Option<Box<dyn ShouldSample>>,
+}
+
+#[derive(Debug, Clone)]
+struct SamplingRule {
+    matcher: RuleMatcher,
+    sampler: Sampler,
+}
+
+#[derive(Debug, Clone)]
+enum RuleMatcher {
+    Name(String),
+    Attribute(Key, Value),
+}
+
+impl RuleMatcher {
+    fn matches(&self, name: &str, attributes: &[KeyValue]) -> bool {
+        match self {
+            RuleMatcher::Name(rule_name) => rule_name == name,
+            RuleMatcher::Attribute(key, value) => attributes
+                .iter()
+                .any(|kv| &kv.key == key && &kv.value == value),
+        }
+    }
+}
+
+impl SamplingRules {
+    /// Create empty SamplingRules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sample the spans named `name` with `sampler`.
+    pub fn with_name_rule(mut self, name: impl Into<String>, sampler: Sampler) -> Self {
+        self.rules.push(SamplingRule {
+            matcher: RuleMatcher::Name(name.into()),
+            sampler,
+        });
+        self
+    }
+
+    /// Sample the spans started with the attribute `key` equal to `value` with `sampler`.
+    pub fn with_attribute_rule(
+        mut self,
+        key: impl Into<Key>,
+        value: impl Into<Value>,
+        sampler: Sampler,
+    ) -> Self {
+        self.rules.push(SamplingRule {
+            matcher: RuleMatcher::Attribute(key.into(), value.into()),
+            sampler,
+        });
+        self
+    }
+
+    /// Sample the spans matching no rule with `sampler`.
+    pub fn with_default_sampler(mut self, sampler: impl ShouldSample + 'static) -> Self {
+        self.default_sampler = Some(Box::new(sampler));
+        self
+    }
+
+    /// Set the default sampler to `sampler`, unless one was already set.
+    pub(crate) fn with_fallback_sampler(mut self, sampler: Box<dyn ShouldSample>) -> Self {
+        self.default_sampler.get_or_insert(sampler);
+        self
+    }
+}
+
+impl ShouldSample for SamplingRules {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let always_on = Sampler::AlwaysOn;
+        let sampler: &dyn ShouldSample = match self
+            .rules
+            .iter()
+            .find(|rule| rule.matcher.matches(name, attributes))
+        {
+            Some(rule) => &rule.sampler,
+            None => self.default_sampler.as_deref().unwrap_or(&always_on),
+        };
+        sampler.should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+/// The parameters of the sampling decision of a span, see
+/// [`InitConfig::with_sampling_hook`](crate::InitConfig::with_sampling_hook).
+#[derive(Debug)]
+pub struct SamplingParams<'a> {
+    /// The parent context, carrying the parent span if any.
+    pub parent_context: Option<&'a Context>,
+    /// The trace id of the span.
+    pub trace_id: TraceId,
+    /// The name of the span.
+    pub name: &'a str,
+    /// The kind of the span.
+    pub span_kind: &'a SpanKind,
+    /// The attributes the span is started with.
+    pub attributes: &'a [KeyValue],
+    /// The links the span is started with.
+    pub links: &'a [Link],
+}
+
+/// A callback deciding the sampling of some spans before the configured sampler.
+#[derive(Clone)]
+pub(crate) struct SamplingHook(
+    pub(crate) Arc<dyn Fn(&SamplingParams<'_>) -> Option<SamplingDecision> + Send + Sync>,
+);
+
+impl Debug for SamplingHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SamplingHook")
+    }
+}
+
+/// Samples the spans with the decision of the hook if any, and the others with the inner
+/// sampler.
+#[derive(Debug, Clone)]
+pub(crate) struct SamplingHookSampler {
+    hook: SamplingHook,
+    inner: Box<dyn ShouldSample>,
+}
+
+impl SamplingHookSampler {
+    pub(crate) fn new(hook: SamplingHook, inner: Box<dyn ShouldSample>) -> Self {
+        Self { hook, inner }
+    }
+}
+
+impl ShouldSample for SamplingHookSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let params = SamplingParams {
+            parent_context,
+            trace_id,
+            name,
+            span_kind,
+            attributes,
+            links,
+        };
+        match (self.hook.0)(&params) {
+            Some(decision) => SamplingResult {
+                decision,
+                attributes: Vec::new(),
+                trace_state: parent_context
+                    .map(|cx| cx.span().span_context().trace_state().clone())
+                    .unwrap_or_default(),
+            },
+            None => self.inner.should_sample(
+                parent_context,
+                trace_id,
+                name,
+                span_kind,
+                attributes,
+                links,
+            ),
+        }
+    }
+}
+
+/// Layer the sampling rules, the span suppression and the sampling hook around the reloadable
+/// sampler, outside of it so that they outlive its replacements: only the fallback sampler is
+/// ever replaced.
+pub(crate) fn layered_sampler(
+    reloadable: ReloadableSampler,
+    sampling_rules: Option<SamplingRules>,
+    suppress_spans: Arc<[String]>,
+    sampling_hook: Option<SamplingHook>,
+) -> Box<dyn ShouldSample> {
+    let mut sampler: Box<dyn ShouldSample> = Box::new(reloadable);
+    if let Some(sampling_rules) = sampling_rules {
+        sampler = Box::new(sampling_rules.with_fallback_sampler(sampler));
+    }
+    if !suppress_spans.is_empty() {
+        sampler = Box::new(SuppressSpansSampler::new(suppress_spans, sampler));
+    }
+    if let Some(hook) = sampling_hook {
+        sampler = Box::new(SamplingHookSampler::new(hook, sampler));
+    }
+    sampler
+}
+
+/// Drops the spans whose name or `http.route` attribute matches one of the patterns,
+/// and samples the others with the inner sampler.
+#[derive(Debug, Clone)]
+pub(crate) struct SuppressSpansSampler {
+    patterns: Arc<[String]>,
+    inner: Box<dyn ShouldSample>,
+}
+
+impl SuppressSpansSampler {
+    pub(crate) fn new(patterns: Arc<[String]>, inner: Box<dyn ShouldSample>) -> Self {
+        Self { patterns, inner }
+    }
+}
+
+impl ShouldSample for SuppressSpansSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let route = attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == HTTP_ROUTE)
+            .map(|kv| kv.value.as_str());
+        if is_suppressed(&self.patterns, name, route.as_deref()) {
+            return SamplingResult {
+                decision: SamplingDecision::Drop,
+                attributes: Vec::new(),
+                trace_state: parent_context
+                    .map(|cx| cx.span().span_context().trace_state().clone())
+                    .unwrap_or_default(),
+            };
+        }
+        self.inner
+            .should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+/// Whether a span named `name` (e.g. `GET /healthz` or `/healthz`), with the `http.route`
+/// attribute `route`, matches one of the suppression patterns.
+pub(crate) fn is_suppressed(patterns: &[String], name: &str, route: Option<&str>) -> bool {
+    let path = name.rsplit(' ').next().unwrap_or(name);
+    patterns
+        .iter()
+        .any(|pattern| pattern == name || pattern == path || Some(pattern.as_str()) == route)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(sampler: &dyn ShouldSample, name: &str) -> SamplingDecision {
+        sampler
+            .should_sample(
+                None,
+                TraceId::from_u128(1),
+                name,
+                &SpanKind::Internal,
+                &[],
+                &[],
+            )
+            .decision
+    }
+
+    #[test]
+    fn sampling_rules_outlive_sampler_replacement() {
+        let reloadable = ReloadableSampler::new(Box::new(Sampler::AlwaysOn));
+        let rules = SamplingRules::new().with_name_rule("GET /metrics", Sampler::AlwaysOff);
+        let sampler = layered_sampler(reloadable.clone(), Some(rules), Arc::new([]), None);
+        assert_eq!(decision(&*sampler, "GET /metrics"), SamplingDecision::Drop);
+        assert_eq!(
+            decision(&*sampler, "GET /users"),
+            SamplingDecision::RecordAndSample
+        );
+
+        // As `ReconfigureHandle::set_sampler` does.
+        reloadable.replace(Box::new(Sampler::AlwaysOn));
+        assert_eq!(decision(&*sampler, "GET /metrics"), SamplingDecision::Drop);
+
+        reloadable.replace(Box::new(Sampler::AlwaysOff));
+        assert_eq!(decision(&*sampler, "GET /users"), SamplingDecision::Drop);
+    }
+
+    #[test]
+    fn suppressed_spans_outlive_sampler_replacement() {
+        let reloadable = ReloadableSampler::new(Box::new(Sampler::AlwaysOn));
+        let patterns: Arc<[String]> = vec!["/healthz".to_owned()].into();
+        let sampler = layered_sampler(reloadable.clone(), None, patterns, None);
+        reloadable.replace(Box::new(Sampler::AlwaysOn));
+        assert_eq!(decision(&*sampler, "GET /healthz"), SamplingDecision::Drop);
+        assert_eq!(
+            decision(&*sampler, "GET /users"),
+            SamplingDecision::RecordAndSample
+        );
+    }
+
+    #[test]
+    fn is_suppressed_matches_name_path_and_route() {
+        let patterns = ["/healthz".to_owned(), "GET /readyz".to_owned()];
+        let cases = [
+            // (name, route, suppressed)
+            ("GET /readyz", None, true),
+            ("/healthz", None, true),
+            ("GET /healthz", None, true),
+            ("GET /users/{id}", Some("/healthz"), true),
+            ("POST /readyz", None, false),
+            ("GET /healthz/deep", None, false),
+            ("GET /users", Some("/users"), false),
+        ];
+        for (name, route, suppressed) in cases {
+            assert_eq!(
+                is_suppressed(&patterns, name, route),
+                suppressed,
+                "{name} {route:?}"
+            );
+        }
+    }
+}
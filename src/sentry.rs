@@ -0,0 +1,225 @@
+use opentelemetry::logs::{AnyValue, LogResult, Severity};
+use opentelemetry::trace::{SpanId, Status, TraceId, TraceResult};
+use opentelemetry::{Context, InstrumentationLibrary};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::logs::{LogProcessor, LogRecord};
+use opentelemetry_sdk::trace::{Span as SdkSpan, SpanProcessor};
+use sentry_core::protocol::{self, Event, Level};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+/// Configuration of the Sentry bridge, which forwards the spans ending with an error status
+/// and the ERROR log records to Sentry as events carrying their trace and span ids,
+/// so that the exceptions tracked by Sentry stay correlated with the traces,
+/// see [`InitConfig::with_sentry_config`](crate::InitConfig::with_sentry_config).
+///
+/// The Sentry client must be initialized by the application, e.g. with `sentry::init`.
+#[derive(Debug, Clone, Default, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct SentryConfig {
+    /// The span and log record attributes forwarded as tags of the Sentry events.
+    /// None is forwarded if empty.
+    tag_attributes: Vec<String>,
+}
+
+/// The Sentry bridge configuration of the current initialization, if any.
+pub(crate) static SENTRY_CONFIG: OnceLock<Option<SentryConfig>> = OnceLock::new();
+
+/// The Sentry bridge configuration of the current initialization, if any.
+pub(crate) fn sentry_config() -> Option<&'static SentryConfig> {
+    SENTRY_CONFIG.get().and_then(Option::as_ref)
+}
+
+/// A span processor forwarding the spans ending with an error status to Sentry.
+#[derive(Debug)]
+pub(crate) struct SentrySpanProcessor(pub(crate) &'static SentryConfig);
+
+impl SpanProcessor for SentrySpanProcessor {
+    fn on_start(&self, _span: &mut SdkSpan, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        let Status::Error { description } = &span.status else {
+            return;
+        };
+        let tags = self.0.tags(
+            span.attributes
+                .iter()
+                .map(|kv| (kv.key.as_str(), kv.value.to_string())),
+        );
+        capture(
+            format!("{}: {description}", span.name),
+            span.span_context.trace_id(),
+            span.span_context.span_id(),
+            tags,
+        );
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        Ok(())
+    }
+}
+
+/// A log processor forwarding the ERROR and FATAL log records to Sentry.
+#[derive(Debug)]
+pub(crate) struct SentryLogProcessor(pub(crate) &'static SentryConfig);
+
+impl LogProcessor for SentryLogProcessor {
+    fn emit(&self, data: &mut LogRecord, _instrumentation: &InstrumentationLibrary) {
+        if !data
+            .severity_number
+            .is_some_and(|severity| severity >= Severity::Error)
+        {
+            return;
+        }
+        let message = match &data.body {
+            Some(AnyValue::String(body)) => body.to_string(),
+            Some(body) => format!("{body:?}"),
+            None => data.event_name.as_deref().unwrap_or_default().to_owned(),
+        };
+        let tags = self.0.tags(
+            data.attributes_iter()
+                .map(|(key, value)| (key.as_str(), tag_value(value))),
+        );
+        let (trace_id, span_id) = data
+            .trace_context
+            .as_ref()
+            .map_or((TraceId::INVALID, SpanId::INVALID), |trace_context| {
+                (trace_context.trace_id, trace_context.span_id)
+            });
+        capture(message, trace_id, span_id, tags);
+    }
+
+    fn force_flush(&self) -> LogResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> LogResult<()> {
+        Ok(())
+    }
+}
+
+impl SentryConfig {
+    /// The attributes to forward as tags, among `attributes`.
+    fn tags<'a>(
+        &self,
+        attributes: impl Iterator<Item = (&'a str, String)>,
+    ) -> BTreeMap<String, String> {
+        attributes
+            .filter(|(key, _)| self.tag_attributes.iter().any(|tag| tag == key))
+            .map(|(key, value)| (key.to_owned(), value))
+            .collect()
+    }
+}
+
+/// The scalar log attribute values as is, and the others in their `Debug` representation.
+fn tag_value(value: &AnyValue) -> String {
+    match value {
+        AnyValue::String(value) => value.to_string(),
+        AnyValue::Int(value) => value.to_string(),
+        AnyValue::Double(value) => value.to_string(),
+        AnyValue::Boolean(value) => value.to_string(),
+        value => format!("{value:?}"),
+    }
+}
+
+fn capture(message: String, trace_id: TraceId, span_id: SpanId, tags: BTreeMap<String, String>) {
+    let mut event = Event {
+        message: Some(message),
+        level: Level::Error,
+        tags,
+        ..Default::default()
+    };
+    if trace_id != TraceId::INVALID {
+        let trace_context = protocol::TraceContext {
+            trace_id: trace_id.to_bytes().into(),
+            span_id: span_id.to_bytes().into(),
+            ..Default::default()
+        };
+        event.contexts.insert(
+            "trace".to_owned(),
+            protocol::Context::Trace(Box::new(trace_context)),
+        );
+    }
+    sentry_core::capture_event(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::logs::{LogRecord as _, Logger as _, LoggerProvider as _};
+    use opentelemetry_sdk::logs::{LoggerProvider, TraceContext};
+
+    fn config() -> &'static SentryConfig {
+        Box::leak(Box::new(
+            SentryConfig::default().with_tag_attributes(vec!["tenant.id".to_owned()]),
+        ))
+    }
+
+    fn record(severity: Severity, body: &'static str) -> LogRecord {
+        let mut record = LoggerProvider::builder()
+            .build()
+            .logger("test")
+            .create_log_record();
+        record.set_severity_number(severity);
+        record.set_body(AnyValue::from(body));
+        record.add_attribute("tenant.id", "acme");
+        record.add_attribute("user.id", 42_i64);
+        record
+    }
+
+    #[test]
+    fn log_processor_forwards_errors_with_their_trace_and_tags() {
+        let trace_id = TraceId::from_u128(0x4bf92f3577b34da6a3ce929d0e0e4736);
+        let span_id = SpanId::from_u64(0x00f067aa0ba902b7);
+        let processor = SentryLogProcessor(config());
+        let events = sentry_core::test::with_captured_events(|| {
+            let mut error = record(Severity::Error, "payment failed");
+            error.trace_context = Some(TraceContext {
+                trace_id,
+                span_id,
+                trace_flags: None,
+            });
+            processor.emit(&mut error, &InstrumentationLibrary::default());
+            processor.emit(
+                &mut record(Severity::Warn, "retrying"),
+                &InstrumentationLibrary::default(),
+            );
+        });
+
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.message.as_deref(), Some("payment failed"));
+        assert_eq!(event.level, Level::Error);
+        assert_eq!(
+            event.tags,
+            BTreeMap::from([("tenant.id".to_owned(), "acme".to_owned())])
+        );
+        let Some(protocol::Context::Trace(trace_context)) = event.contexts.get("trace") else {
+            panic!("no trace context in {event:?}");
+        };
+        assert_eq!(
+            trace_context.trace_id,
+            protocol::TraceId::from(trace_id.to_bytes())
+        );
+        assert_eq!(
+            trace_context.span_id,
+            protocol::SpanId::from(span_id.to_bytes())
+        );
+    }
+
+    #[test]
+    fn tags_keep_the_configured_attributes_only() {
+        let attributes = [
+            ("tenant.id", "acme".to_owned()),
+            ("user.id", "42".to_owned()),
+        ];
+        assert_eq!(
+            config().tags(attributes.into_iter()),
+            BTreeMap::from([("tenant.id".to_owned(), "acme".to_owned())])
+        );
+    }
+}
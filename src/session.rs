@@ -0,0 +1,137 @@
+use crate::{tracer_span, Instant};
+
+use opentelemetry::trace::{SpanBuilder, SpanKind, TraceContextExt as _};
+use opentelemetry::{Context, KeyValue};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const SESSION_HEARTBEAT: &str = "session.heartbeat";
+const SESSION_MESSAGE_COUNT: &str = "session.message_count";
+const SESSION_DURATION: &str = "session.duration";
+
+/// The span of a long-lived connection or session, e.g. a websocket, with a child span per
+/// message rather than one giant span recording every message as an event.
+///
+/// The session span stays open for the whole connection, and records the number of messages
+/// and the duration of the session when it ends. While it is open, `session.heartbeat` events
+/// are added periodically with the same attributes, so that the connection is seen alive.
+/// Message spans nested within message spans are re-parented to the session span beyond
+/// the maximum depth, which bounds the depth of the trace.
+///
+/// ```ignore
+/// let session = SessionSpan::start("ws /chat", vec![KeyValue::new("user.id", user_id)]);
+/// while let Some(message) = socket.next().await {
+///     let cx = session.message_span("ws.message");
+///     handle(message).with_context(cx.clone()).await;
+///     cx.span().end();
+/// }
+/// session.end();
+/// ```
+#[derive(Debug)]
+pub struct SessionSpan {
+    cx: Context,
+    started: Instant,
+    message_count: AtomicU64,
+    last_heartbeat: Mutex<Instant>,
+    heartbeat_interval: Duration,
+    max_depth: usize,
+}
+
+/// The depth of a message span of the session whose span is in the same context.
+#[derive(Debug, Clone, Copy)]
+struct MessageDepth(usize);
+
+impl SessionSpan {
+    /// Start the server span of a session named `name`, as a child of the current context.
+    pub fn start(name: impl Into<Cow<'static, str>>, attributes: Vec<KeyValue>) -> Self {
+        let builder = SpanBuilder::from_name(name)
+            .with_kind(SpanKind::Server)
+            .with_attributes(attributes);
+        let span = tracer_span(builder, Some(&Context::current()));
+        let now = Instant::now();
+        Self {
+            cx: Context::current_with_span(span),
+            started: now,
+            message_count: AtomicU64::new(0),
+            last_heartbeat: Mutex::new(now),
+            heartbeat_interval: Duration::from_secs(60),
+            max_depth: 4,
+        }
+    }
+
+    /// Set the minimum interval between two heartbeat events. Defaults to 60 seconds.
+    pub fn with_heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    /// Set the maximum depth of the nested message spans, the message spans started directly
+    /// within the session being at depth 1. Defaults to 4.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth.max(1);
+        self
+    }
+
+    /// The context of the session span.
+    pub fn context(&self) -> &Context {
+        &self.cx
+    }
+
+    /// Start the span of a message named `name`, as a child of the current message span of
+    /// this session if any and within the maximum depth, or else of the session span.
+    /// Returns the context of the message span, to run the handling of the message in,
+    /// and to end the span with once handled.
+    ///
+    /// A heartbeat event is added to the session span if due.
+    pub fn message_span(&self, name: impl Into<Cow<'static, str>>) -> Context {
+        self.message_count.fetch_add(1, Ordering::Relaxed);
+        self.heartbeat();
+        let (parent_cx, depth) = Context::map_current(|cx| {
+            let in_session =
+                cx.span().span_context().trace_id() == self.cx.span().span_context().trace_id();
+            match cx.get::<MessageDepth>() {
+                Some(depth) if in_session && depth.0 < self.max_depth => (cx.clone(), depth.0 + 1),
+                _ => (self.cx.clone(), 1),
+            }
+        });
+        let span = tracer_span(SpanBuilder::from_name(name), Some(&parent_cx));
+        parent_cx.with_span(span).with_value(MessageDepth(depth))
+    }
+
+    /// Add a `session.heartbeat` event to the session span, unless the last one was added
+    /// less than the heartbeat interval ago.
+    pub fn heartbeat(&self) {
+        let now = Instant::now();
+        {
+            let mut last_heartbeat = self.last_heartbeat.lock().unwrap();
+            if now.duration_since(*last_heartbeat) < self.heartbeat_interval {
+                return;
+            }
+            *last_heartbeat = now;
+        }
+        self.cx
+            .span()
+            .add_event(SESSION_HEARTBEAT, self.session_attributes());
+    }
+
+    /// End the session span, recording the number of messages and the duration of the session.
+    pub fn end(self) {
+        let span = self.cx.span();
+        for attribute in self.session_attributes() {
+            span.set_attribute(attribute);
+        }
+        span.end();
+    }
+
+    fn session_attributes(&self) -> Vec<KeyValue> {
+        vec![
+            KeyValue::new(
+                SESSION_MESSAGE_COUNT,
+                self.message_count.load(Ordering::Relaxed) as i64,
+            ),
+            KeyValue::new(SESSION_DURATION, self.started.elapsed().as_secs_f64()),
+        ]
+    }
+}
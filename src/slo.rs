@@ -0,0 +1,109 @@
+use crate::logs::GLOBAL_LOGGER_PROVIDER;
+
+use opentelemetry::logs::{LogRecord as _, Logger as _, Severity};
+use opentelemetry::metrics::Counter;
+use opentelemetry::trace::TraceResult;
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::logs::TraceContext;
+use opentelemetry_sdk::trace::{Span as SdkSpan, SpanProcessor};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const SLO_BREACH: &str = "slo.breach";
+const SPAN_NAME: &str = "span.name";
+const SLO_THRESHOLD: &str = "slo.threshold";
+const SPAN_DURATION: &str = "span.duration";
+
+static BREACH_COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+
+/// Latency objectives of spans by name: each span lasting longer than the threshold of its
+/// name is counted into the `slo.breach` counter, by `span.name`, and reported by a WARN log
+/// record correlated with its trace, see
+/// [`InitConfig::with_span_slos`](crate::InitConfig::with_span_slos).
+///
+/// Only the recorded spans are checked, i.e. not those dropped by the sampler.
+///
+/// ```ignore
+/// let slos = SpanSlos::new()
+///     .with_threshold("GET /users/{id}", Duration::from_millis(200))
+///     .with_threshold("checkout", Duration::from_secs(1));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SpanSlos {
+    thresholds: HashMap<String, Duration>,
+}
+
+impl SpanSlos {
+    /// Create new SpanSlos, checking no span.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report the spans named `span_name` lasting longer than `threshold`.
+    pub fn with_threshold(mut self, span_name: impl Into<String>, threshold: Duration) -> Self {
+        self.thresholds.insert(span_name.into(), threshold);
+        self
+    }
+}
+
+/// A span processor reporting the spans breaching their [`SpanSlos`].
+#[derive(Debug)]
+pub(crate) struct SloSpanProcessor(pub(crate) SpanSlos);
+
+impl SpanProcessor for SloSpanProcessor {
+    fn on_start(&self, _span: &mut SdkSpan, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        let Some(threshold) = self.0.thresholds.get(span.name.as_ref()) else {
+            return;
+        };
+        let duration = span
+            .end_time
+            .duration_since(span.start_time)
+            .unwrap_or_default();
+        if duration <= *threshold {
+            return;
+        }
+        BREACH_COUNTER
+            .get_or_init(|| {
+                crate::metrics::crate_meter()
+                    .u64_counter(SLO_BREACH)
+                    .with_description("Number of spans lasting longer than their objective")
+                    .init()
+            })
+            .add(1, &[KeyValue::new(SPAN_NAME, span.name.clone())]);
+
+        let Some(logger_provider) = GLOBAL_LOGGER_PROVIDER.get() else {
+            return;
+        };
+        let logger = crate::logs::scoped_logger(logger_provider, env!("CARGO_PKG_NAME"));
+        let mut record = logger.create_log_record();
+        record.set_severity_number(Severity::Warn);
+        record.set_severity_text("WARN");
+        record.set_body(
+            format!(
+                "{} lasted {}ms, over its objective of {}ms",
+                span.name,
+                duration.as_millis(),
+                threshold.as_millis()
+            )
+            .into(),
+        );
+        record.add_attribute("event.name", SLO_BREACH);
+        record.add_attribute(SPAN_NAME, span.name.clone());
+        record.add_attribute(SPAN_DURATION, duration.as_secs_f64());
+        record.add_attribute(SLO_THRESHOLD, threshold.as_secs_f64());
+        record.trace_context = Some(TraceContext::from(&span.span_context));
+        logger.emit(record);
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        Ok(())
+    }
+}
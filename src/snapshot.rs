@@ -0,0 +1,213 @@
+use crate::{ExporterKind, InitConfig};
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// The effective telemetry configuration, see [`current_config_snapshot`].
+///
+/// Serializable with the `serde` feature, e.g. to be shown by an admin endpoint.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConfigSnapshot {
+    /// Whether telemetry is enabled.
+    pub enabled: bool,
+    /// Service name.
+    pub service_name: String,
+    /// Service version.
+    pub service_version: String,
+    /// The exporter of all signals: `stdout`, `stdout-tree`, `otlp-grpc` or `otlp-http`.
+    pub exporter: String,
+    /// The OTLP endpoint of spans, with its credentials redacted.
+    pub traces_endpoint: Option<String>,
+    /// The OTLP endpoint of log records, with its credentials redacted.
+    pub logs_endpoint: Option<String>,
+    /// The OTLP endpoint of metrics, with its credentials redacted.
+    pub metrics_endpoint: Option<String>,
+    /// The OTLP endpoint of audit records, with its credentials redacted.
+    pub audit_endpoint: Option<String>,
+    /// The OTLP endpoints of the routed tenants, with their credentials redacted.
+    pub tenant_endpoints: BTreeMap<String, String>,
+    /// The names of the headers sent with OTLP exports, without their values.
+    pub otlp_headers: Vec<String>,
+    /// The sampler of spans.
+    pub sampler: String,
+    /// The batch configuration of spans, `None` if they are exported one by one.
+    pub batch_trace_config: Option<String>,
+    /// The batch configuration of log records, `None` if they are exported one by one.
+    pub batch_log_config: Option<String>,
+    /// The interval between two metric collections.
+    pub metric_interval: Duration,
+    /// The attributes of the resource shared by all providers.
+    pub resource_attributes: BTreeMap<String, String>,
+}
+
+static SNAPSHOT: RwLock<Option<ConfigSnapshot>> = RwLock::new(None);
+
+/// Returns the telemetry configuration in effect, including the changes made through the
+/// [`ReconfigureHandle`](crate::ReconfigureHandle), so that it can be inspected at runtime.
+pub fn current_config_snapshot() -> ConfigSnapshot {
+    SNAPSHOT.read().unwrap().clone().unwrap_or_default()
+}
+
+/// Record the snapshot of `init_config`, once the resource is set when enabled.
+pub(crate) fn init_snapshot(init_config: &InitConfig) {
+    let exporter = if init_config.exporter_kind() == ExporterKind::StdoutTree {
+        "stdout-tree"
+    } else if init_config.stdout_exporter {
+        "stdout"
+    } else if init_config.otlp_http_client.is_some() {
+        "otlp-http"
+    } else {
+        "otlp-grpc"
+    };
+    let mut snapshot = ConfigSnapshot {
+        enabled: init_config.enabled,
+        service_name: init_config.service_name.clone(),
+        service_version: init_config.service_version.clone(),
+        exporter: exporter.to_owned(),
+        sampler: match &init_config.sampling_rules {
+            Some(sampling_rules) => format!("{sampling_rules:?}"),
+            None => format!("{:?}", init_config.tracer_provider_config.sampler),
+        },
+        batch_trace_config: init_config
+            .batch_trace_config
+            .as_ref()
+            .map(|config| format!("{config:?}")),
+        batch_log_config: init_config
+            .batch_log_config
+            .as_ref()
+            .map(|config| format!("{config:?}")),
+        metric_interval: std::env::var("OTEL_METRIC_EXPORT_INTERVAL")
+            .ok()
+            .and_then(|interval| interval.parse().ok())
+            .map_or(DEFAULT_METRIC_INTERVAL, Duration::from_millis),
+        ..Default::default()
+    };
+    if init_config.enabled {
+        snapshot.resource_attributes = crate::resource()
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        if !init_config.stdout_exporter {
+            snapshot.set_otlp_endpoint(None);
+            snapshot.audit_endpoint = init_config
+                .audit_config
+                .otlp_endpoint
+                .as_deref()
+                .map(redact_endpoint)
+                .or_else(|| snapshot.logs_endpoint.clone());
+            let http = init_config.otlp_http_client.is_some();
+            if let Some(endpoint) = &init_config.traces_endpoint {
+                snapshot.traces_endpoint = Some(otlp_endpoint(Some(endpoint), "TRACES", http));
+            }
+            #[cfg(feature = "jaeger-compat")]
+            if let Some(endpoint) = &init_config.jaeger_agent_endpoint {
+                snapshot.traces_endpoint = Some(format!("jaeger-agent://{endpoint}"));
+            }
+            if let Some(endpoint) = &init_config.logs_endpoint {
+                snapshot.logs_endpoint = Some(otlp_endpoint(Some(endpoint), "LOGS", http));
+            }
+            if let Some(endpoint) = &init_config.metrics_endpoint {
+                snapshot.metrics_endpoint = Some(otlp_endpoint(Some(endpoint), "METRICS", http));
+            }
+            if let Some(tenant_router) = &init_config.tenant_router {
+                snapshot.tenant_endpoints = tenant_router
+                    .endpoints()
+                    .map(|(tenant, endpoint)| (tenant.to_owned(), redact_endpoint(endpoint)))
+                    .collect();
+            }
+            snapshot.otlp_headers = std::env::var("OTEL_EXPORTER_OTLP_HEADERS")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|header| header.split_once('='))
+                .map(|(name, _)| name.trim().to_owned())
+                .collect();
+        } else if init_config.dual_logging {
+            let http = init_config.otlp_http_client.is_some();
+            snapshot.logs_endpoint = Some(otlp_endpoint(
+                init_config.logs_endpoint.as_deref(),
+                "LOGS",
+                http,
+            ));
+        }
+    }
+    *SNAPSHOT.write().unwrap() = Some(snapshot);
+}
+
+/// Whether the spans and log records are currently exported to the standard output.
+pub(crate) fn uses_stdout_exporter() -> bool {
+    SNAPSHOT
+        .read()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|snapshot| snapshot.enabled && snapshot.exporter.starts_with("stdout"))
+}
+
+/// Apply `update` to the current snapshot, after a reconfiguration.
+pub(crate) fn update_snapshot(update: impl FnOnce(&mut ConfigSnapshot)) {
+    if let Some(snapshot) = SNAPSHOT.write().unwrap().as_mut() {
+        update(snapshot)
+    }
+}
+
+const DEFAULT_METRIC_INTERVAL: Duration = Duration::from_secs(60);
+
+impl ConfigSnapshot {
+    /// Set the OTLP endpoints of all signals to `endpoint`,
+    /// or else to the ones of the `OTEL_EXPORTER_OTLP_*` environment variables.
+    pub(crate) fn set_otlp_endpoint(&mut self, endpoint: Option<&str>) {
+        let http = self.exporter == "otlp-http";
+        self.traces_endpoint = Some(otlp_endpoint(endpoint, "TRACES", http));
+        self.logs_endpoint = Some(otlp_endpoint(endpoint, "LOGS", http));
+        self.metrics_endpoint = Some(otlp_endpoint(endpoint, "METRICS", http));
+    }
+}
+
+/// The redacted OTLP endpoint of `signal`, resolved like the OTLP exporters do.
+fn otlp_endpoint(endpoint: Option<&str>, signal: &str, http: bool) -> String {
+    if let Some(endpoint) = endpoint {
+        return redact_endpoint(endpoint);
+    }
+    if let Ok(endpoint) = std::env::var(format!("OTEL_EXPORTER_OTLP_{signal}_ENDPOINT")) {
+        return redact_endpoint(&endpoint);
+    }
+    let base = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").unwrap_or_else(|_| {
+        if http {
+            "http://localhost:4318".to_owned()
+        } else {
+            "http://localhost:4317".to_owned()
+        }
+    });
+    let endpoint = if http {
+        format!(
+            "{}/v1/{}",
+            base.trim_end_matches('/'),
+            signal.to_ascii_lowercase()
+        )
+    } else {
+        base
+    };
+    redact_endpoint(&endpoint)
+}
+
+/// Replace the user info and the query of `endpoint` with `***`, as they may hold credentials.
+fn redact_endpoint(endpoint: &str) -> String {
+    let (scheme, rest) = match endpoint.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme), rest),
+        None => (None, endpoint),
+    };
+    let (rest, query) = match rest.split_once('?') {
+        Some((rest, _)) => (rest, "?***"),
+        None => (rest, ""),
+    };
+    let authority_end = rest.find('/').unwrap_or(rest.len());
+    let rest = match rest[..authority_end].rsplit_once('@') {
+        Some((_, host)) => format!("***@{host}{}", &rest[authority_end..]),
+        None => rest.to_owned(),
+    };
+    match scheme {
+        Some(scheme) => format!("{scheme}://{rest}{query}"),
+        None => format!("{rest}{query}"),
+    }
+}
@@ -0,0 +1,178 @@
+use opentelemetry::trace::{Span as _, TraceResult};
+use opentelemetry::Context;
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::trace::{Span as SdkSpan, SpanProcessor};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+/// Rewrites high-cardinality span names into low-cardinality ones, such as
+/// `GET /users/12345` into `GET /users/{id}`, so that backends can aggregate them.
+///
+/// The rules are tried in order, and the first one matching a span name rewrites it.
+///
+/// ```
+/// use myotel::SpanNameRewriter;
+///
+/// let rewriter = SpanNameRewriter::new()
+///     .with_route("/users/{id}/orders/{order_id}")
+///     .with_id_segments();
+/// assert_eq!(
+///     rewriter.rewrite("GET /users/42/orders/7").as_deref(),
+///     Some("GET /users/{id}/orders/{order_id}")
+/// );
+/// assert_eq!(rewriter.rewrite("DELETE /carts/42").as_deref(), Some("DELETE /carts/{id}"));
+/// assert_eq!(rewriter.rewrite("GET /health"), None);
+/// ```
+#[derive(Clone, Default)]
+pub struct SpanNameRewriter {
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone)]
+enum Rule {
+    /// A route template, whose `{placeholder}` segments match any segment.
+    Route(String),
+    /// Replace the numeric, UUID and long hexadecimal segments with `{id}`.
+    IdSegments,
+    Callback(Arc<dyn Fn(&str) -> Option<String> + Send + Sync>),
+}
+
+impl SpanNameRewriter {
+    /// Create a new SpanNameRewriter, without rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rewrite the span names whose path matches the route template `route`, e.g.
+    /// `/users/{id}`, into that template, keeping their HTTP method prefix if any.
+    pub fn with_route(mut self, route: impl Into<String>) -> Self {
+        self.rules.push(Rule::Route(route.into()));
+        self
+    }
+
+    /// Replace the numeric, UUID and long hexadecimal path segments of span names with `{id}`.
+    pub fn with_id_segments(mut self) -> Self {
+        self.rules.push(Rule::IdSegments);
+        self
+    }
+
+    /// Rewrite the span names for which `callback` returns a new name.
+    pub fn with_callback(
+        mut self,
+        callback: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.rules.push(Rule::Callback(Arc::new(callback)));
+        self
+    }
+
+    /// The rewritten span name, `None` if no rule matches `name`.
+    pub fn rewrite(&self, name: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| match rule {
+            Rule::Route(route) => rewrite_route(name, route),
+            Rule::IdSegments => rewrite_id_segments(name),
+            Rule::Callback(callback) => callback(name),
+        })
+    }
+}
+
+impl Debug for SpanNameRewriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rules: Vec<&str> = self
+            .rules
+            .iter()
+            .map(|rule| match rule {
+                Rule::Route(route) => route.as_str(),
+                Rule::IdSegments => "{id segments}",
+                Rule::Callback(_) => "{callback}",
+            })
+            .collect();
+        f.debug_struct("SpanNameRewriter")
+            .field("rules", &rules)
+            .finish()
+    }
+}
+
+/// Split `GET /users/42` into `("GET ", "/users/42")`, without the query string.
+fn split_method(name: &str) -> (&str, &str) {
+    let (method, path) = match name.split_once(' ') {
+        Some((method, path)) if method.chars().all(|c| c.is_ascii_uppercase()) => {
+            (&name[..=method.len()], path)
+        }
+        _ => ("", name),
+    };
+    (method, path.split('?').next().unwrap_or_default())
+}
+
+fn rewrite_route(name: &str, route: &str) -> Option<String> {
+    let (method, path) = split_method(name);
+    let mut segments = path.split('/');
+    let mut route_segments = route.split('/');
+    loop {
+        match (segments.next(), route_segments.next()) {
+            (None, None) => return Some(format!("{method}{route}")),
+            (Some(segment), Some(route_segment)) => {
+                let is_placeholder = route_segment.starts_with('{') && route_segment.ends_with('}');
+                if !(segment == route_segment || (is_placeholder && !segment.is_empty())) {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn rewrite_id_segments(name: &str) -> Option<String> {
+    let (method, path) = split_method(name);
+    if !path.starts_with('/') {
+        return None;
+    }
+    let mut rewritten = false;
+    let segments: Vec<&str> = path
+        .split('/')
+        .map(|segment| {
+            if is_id_segment(segment) {
+                rewritten = true;
+                "{id}"
+            } else {
+                segment
+            }
+        })
+        .collect();
+    rewritten.then(|| format!("{method}{}", segments.join("/")))
+}
+
+fn is_id_segment(segment: &str) -> bool {
+    let is_number = !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit());
+    let is_uuid = segment.len() == 36
+        && segment.char_indices().all(|(index, c)| match index {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        });
+    let is_hex = segment.len() >= 16 && segment.chars().all(|c| c.is_ascii_hexdigit());
+    is_number || is_uuid || is_hex
+}
+
+/// A span processor renaming the started spans with a [`SpanNameRewriter`].
+#[derive(Debug)]
+pub(crate) struct SpanNameProcessor(pub(crate) SpanNameRewriter);
+
+impl SpanProcessor for SpanNameProcessor {
+    fn on_start(&self, span: &mut SdkSpan, _cx: &Context) {
+        let Some(data) = span.exported_data() else {
+            return;
+        };
+        if let Some(name) = self.0.rewrite(&data.name) {
+            span.update_name(name);
+        }
+    }
+
+    fn on_end(&self, _span: SpanData) {}
+
+    fn force_flush(&self) -> TraceResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use crate::stats;
+
+use futures_util::future::BoxFuture;
+use opentelemetry::trace::TraceResult;
+use opentelemetry::Context;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::trace::{Span as SdkSpan, SpanProcessor};
+use opentelemetry_sdk::Resource;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Configuration of the span queue of the batch processor, which bounds the number of spans
+/// waiting to be exported, and what happens to the spans ending while it is full,
+/// see [`InitConfig::with_span_queue_config`](crate::InitConfig::with_span_queue_config).
+///
+/// Under sustained overload, i.e. when the spans end faster than they are exported, the queue
+/// stays full and each span beyond it is either dropped or delays the thread ending it,
+/// according to the [`SpanQueuePolicy`]. The dropped spans are counted into
+/// [`SignalExportStats::dropped`](crate::SignalExportStats::dropped).
+///
+/// The queue of the batch trace configuration must be larger, or the batch processor drops
+/// the spans beyond it itself: it holds 2048 spans by default, and must hold twice
+/// `max_queue_size` with [`SpanQueuePolicy::DropOldest`]. Only applies with a batch trace
+/// configuration.
+#[derive(Debug, Clone, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct SpanQueueConfig {
+    /// The maximum number of spans waiting to be exported. Defaults to 1024.
+    max_queue_size: usize,
+    /// What to do with the spans ending while the queue is full.
+    /// Defaults to [`SpanQueuePolicy::DropNewest`].
+    policy: SpanQueuePolicy,
+}
+
+impl Default for SpanQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_queue_size: 1024,
+            policy: SpanQueuePolicy::DropNewest,
+        }
+    }
+}
+
+/// What to do with the spans ending while the span queue is full, see [`SpanQueueConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpanQueuePolicy {
+    /// Drop the new spans, keeping the queued ones.
+    #[default]
+    DropNewest,
+    /// Queue the new spans, and drop as many of the oldest queued spans when they are
+    /// exported, so that the most recent spans are kept.
+    DropOldest,
+    /// Block the thread ending the span until the queue has room, for up to the timeout,
+    /// and then drop the span. Blocking a thread the exports need, e.g. the thread of
+    /// a current-thread runtime, delays them by the whole timeout.
+    Block(Duration),
+}
+
+/// Counts the spans between the [`QueueSpanProcessor`] and the [`QueueSpanExporter`],
+/// i.e. those waiting in the queue of the batch processor.
+#[derive(Debug)]
+pub(crate) struct SpanQueue {
+    config: SpanQueueConfig,
+    state: Mutex<QueueState>,
+    /// Notified when spans leave the queue.
+    space: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    queued: usize,
+    /// The number of the oldest queued spans to drop, with [`SpanQueuePolicy::DropOldest`].
+    evicted: usize,
+}
+
+impl SpanQueue {
+    pub(crate) fn new(config: SpanQueueConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            state: Mutex::new(QueueState::default()),
+            space: Condvar::new(),
+        })
+    }
+
+    /// Whether a span ending now enters the queue.
+    fn admit(&self) -> bool {
+        let max_queue_size = self.config.max_queue_size;
+        let mut state = self.state.lock().unwrap();
+        if state.queued >= max_queue_size {
+            match self.config.policy {
+                SpanQueuePolicy::DropNewest => {
+                    stats::record_dropped_spans(1);
+                    return false;
+                }
+                SpanQueuePolicy::DropOldest => state.evicted += 1,
+                SpanQueuePolicy::Block(timeout) => {
+                    state = self
+                        .space
+                        .wait_timeout_while(state, timeout, |state| state.queued >= max_queue_size)
+                        .unwrap()
+                        .0;
+                    if state.queued >= max_queue_size {
+                        stats::record_dropped_spans(1);
+                        return false;
+                    }
+                }
+            }
+        }
+        state.queued += 1;
+        true
+    }
+
+    /// Take `batch` out of the queue, dropping its oldest spans if some are to be evicted.
+    fn dequeue(&self, batch: &mut Vec<SpanData>) {
+        let mut state = self.state.lock().unwrap();
+        state.queued = state.queued.saturating_sub(batch.len());
+        let evicted = state.evicted.min(batch.len());
+        state.evicted -= evicted;
+        drop(state);
+        self.space.notify_all();
+        if evicted > 0 {
+            batch.drain(..evicted);
+            stats::record_dropped_spans(evicted);
+        }
+    }
+}
+
+/// Wraps the batch span processor so that the spans enter its queue according to
+/// the [`SpanQueue`].
+#[derive(Debug)]
+pub(crate) struct QueueSpanProcessor<P> {
+    pub(crate) inner: P,
+    pub(crate) queue: Arc<SpanQueue>,
+}
+
+impl<P: SpanProcessor> SpanProcessor for QueueSpanProcessor<P> {
+    fn on_start(&self, span: &mut SdkSpan, cx: &Context) {
+        self.inner.on_start(span, cx)
+    }
+
+    fn on_end(&self, span: SpanData) {
+        // The batch processor ignores the unsampled spans.
+        if !span.span_context.is_sampled() || self.queue.admit() {
+            self.inner.on_end(span)
+        }
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        self.inner.shutdown()
+    }
+}
+
+/// Wraps the exporter of the batch span processor, so that the exported spans leave
+/// the [`SpanQueue`].
+#[derive(Debug)]
+pub(crate) struct QueueSpanExporter<E> {
+    pub(crate) inner: E,
+    pub(crate) queue: Arc<SpanQueue>,
+}
+
+impl<E: SpanExporter> SpanExporter for QueueSpanExporter<E> {
+    fn export(&mut self, mut batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        self.queue.dequeue(&mut batch);
+        self.inner.export(batch)
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown()
+    }
+
+    fn force_flush(&mut self) -> BoxFuture<'static, ExportResult> {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource)
+    }
+}
@@ -0,0 +1,113 @@
+use crate::logs::GLOBAL_LOGGER_PROVIDER;
+use crate::metrics::GLOBAL_MMTER_PROVIDER;
+use crate::trace::{self, GLOBAL_TRACER_PROVIDER};
+use crate::{stats, SignalExportStats};
+
+use opentelemetry::logs::{LogRecord as _, Logger as _, Severity};
+use opentelemetry::trace::{Span as _, SpanBuilder, Tracer as _};
+use opentelemetry_sdk::logs::TraceContext;
+use std::time::SystemTime;
+
+const MYOTEL_STARTUP: &str = "myotel.startup";
+
+/// Emit a `myotel.startup` span, log record and counter increment, flush the providers,
+/// and warn if any of them was not accepted by its exporter, see
+/// [`InitConfig::with_startup_probe`](crate::InitConfig::with_startup_probe).
+pub(crate) async fn run() {
+    if !crate::is_enabled() {
+        return;
+    }
+    // Flushing blocks on the export tasks, which may need the current thread to make progress.
+    #[cfg(not(feature = "wasm"))]
+    let problems = match tokio::runtime::Handle::try_current() {
+        Ok(_) => tokio::task::spawn_blocking(probe)
+            .await
+            .unwrap_or_else(|err| vec![format!("the startup probe panicked: {err}")]),
+        Err(_) => probe(),
+    };
+    #[cfg(feature = "wasm")]
+    let problems = probe();
+    if !problems.is_empty() {
+        tracing::warn!(
+            "the telemetry pipeline is broken, the startup probe was not exported: {}",
+            problems.join("; ")
+        );
+    }
+}
+
+/// Emit the startup telemetry and return the problems exporting it.
+fn probe() -> Vec<String> {
+    let start = SystemTime::now();
+    let mut problems = Vec::new();
+
+    let mut span_context = None;
+    if let Some(tracer) = trace::try_tracer() {
+        let mut span = tracer.build(SpanBuilder::from_name(MYOTEL_STARTUP));
+        span_context = Some(span.span_context().clone());
+        span.end();
+    }
+    if let Some(logger_provider) = GLOBAL_LOGGER_PROVIDER.get() {
+        let logger = crate::logs::scoped_logger(logger_provider, env!("CARGO_PKG_NAME"));
+        let mut record = logger.create_log_record();
+        record.set_severity_number(Severity::Info);
+        record.set_severity_text("INFO");
+        record.set_body("OpenTelemetry initialized".into());
+        record.add_attribute("event.name", MYOTEL_STARTUP);
+        if let Some(span_context) = &span_context {
+            record.trace_context = Some(TraceContext::from(span_context));
+        }
+        logger.emit(record);
+    }
+    crate::metrics::crate_meter()
+        .u64_counter(MYOTEL_STARTUP)
+        .with_description("Number of OpenTelemetry initializations")
+        .init()
+        .add(1, &[]);
+
+    if let Some(tracer_provider) = GLOBAL_TRACER_PROVIDER.get() {
+        for result in tracer_provider.force_flush() {
+            if let Err(err) = result {
+                problems.push(format!("traces: {err}"));
+            }
+        }
+    }
+    if let Some(logger_provider) = GLOBAL_LOGGER_PROVIDER.get() {
+        for result in logger_provider.force_flush() {
+            if let Err(err) = result {
+                problems.push(format!("logs: {err}"));
+            }
+        }
+    }
+    if let Some(meter_provider) = GLOBAL_MMTER_PROVIDER.get() {
+        if let Err(err) = meter_provider.force_flush() {
+            problems.push(format!("metrics: {err}"));
+        }
+    }
+
+    // The errors of the exports themselves are only reported through the export statistics.
+    let stats = stats::export_stats();
+    for (signal, stats) in [
+        ("traces", &stats.traces),
+        ("logs", &stats.logs),
+        ("metrics", &stats.metrics),
+    ] {
+        if let Some(err) = failed_since(stats, start) {
+            problems.push(format!("{signal}: {err}"));
+        }
+    }
+    problems
+}
+
+/// The error of the last export of `stats`, if it failed since `start`.
+fn failed_since(stats: &SignalExportStats, start: SystemTime) -> Option<&str> {
+    let failed = stats.last_error_time.is_some_and(|time| time >= start);
+    let succeeded = stats
+        .last_success_time
+        .zip(stats.last_error_time)
+        .is_some_and(|(success, error)| success > error);
+    if failed && !succeeded {
+        stats.last_error.as_deref()
+    } else {
+        None
+    }
+}
@@ -0,0 +1,338 @@
+use crate::Signal;
+
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use opentelemetry::global;
+use opentelemetry::logs::Severity;
+use opentelemetry::logs::{LogError, LogResult};
+use opentelemetry::metrics::Result as MetricsResult;
+use opentelemetry::trace::TraceError;
+use opentelemetry_sdk::export::logs::{LogBatch, LogExporter};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::metrics::data::{ResourceMetrics, Temporality};
+use opentelemetry_sdk::metrics::exporter::PushMetricsExporter;
+use opentelemetry_sdk::metrics::reader::{AggregationSelector, TemporalitySelector};
+use opentelemetry_sdk::metrics::{Aggregation, InstrumentKind};
+use opentelemetry_sdk::runtime::TrySendError;
+use opentelemetry_sdk::Resource;
+use std::fmt::{Debug, Display};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+/// Statistics of the telemetry exports since the process started, see [`export_stats`].
+#[derive(Debug, Clone, Default)]
+pub struct ExportStats {
+    /// Statistics of the span exports.
+    pub traces: SignalExportStats,
+    /// Statistics of the log record exports.
+    pub logs: SignalExportStats,
+    /// Statistics of the metric exports, counting metrics rather than data points.
+    pub metrics: SignalExportStats,
+}
+
+/// Statistics of the exports of a signal, see [`ExportStats`].
+#[derive(Debug, Clone, Default)]
+pub struct SignalExportStats {
+    /// The number of items exported successfully.
+    pub exported: u64,
+    /// The number of items whose export failed.
+    pub failed: u64,
+    /// The number of items dropped before reaching the exporter, because the queue of the
+    /// batch processor was full, see
+    /// [`InitConfig::with_dropped_items_stats`](crate::InitConfig::with_dropped_items_stats).
+    pub dropped: u64,
+    /// The error of the last failed export.
+    pub last_error: Option<String>,
+    /// When the last export failed.
+    pub last_error_time: Option<SystemTime>,
+    /// When the last export succeeded.
+    pub last_success_time: Option<SystemTime>,
+    /// The health of the exports, see [`on_exporter_state_change`].
+    pub state: ExporterState,
+}
+
+/// The health of the exports of a signal, see [`on_exporter_state_change`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExporterState {
+    /// The exports succeed.
+    #[default]
+    Healthy,
+    /// The exports keep failing.
+    Degraded,
+}
+
+/// A change of the [`ExporterState`] of a signal.
+#[derive(Debug, Clone)]
+pub struct ExporterStateChange {
+    /// The signal whose exports changed state.
+    pub signal: Signal,
+    /// The new state.
+    pub state: ExporterState,
+    /// The error of the last failed export.
+    pub last_error: Option<String>,
+}
+
+/// The number of consecutive failed exports after which a signal is degraded.
+const DEGRADED_AFTER_FAILURES: u32 = 3;
+/// The number of consecutive successful exports after which a degraded signal recovers.
+const RECOVERED_AFTER_SUCCESSES: u32 = 2;
+
+type StateChangeCallback = Arc<dyn Fn(&ExporterStateChange) + Send + Sync>;
+
+static STATE_CHANGE_CALLBACKS: RwLock<Vec<StateChangeCallback>> = RwLock::new(Vec::new());
+
+/// Call `callback` whenever the exports of a signal degrade or recover,
+/// e.g. to alert when the telemetry pipeline itself is unhealthy.
+///
+/// The states change with hysteresis, so that a single flaky export does not flap them:
+/// a signal is degraded after 3 consecutive failed exports, and healthy again after
+/// 2 consecutive successful ones. The callback runs on the exporting thread, so it should
+/// return quickly.
+pub fn on_exporter_state_change(callback: impl Fn(&ExporterStateChange) + Send + Sync + 'static) {
+    STATE_CHANGE_CALLBACKS
+        .write()
+        .unwrap()
+        .push(Arc::new(callback));
+}
+
+/// Returns the statistics of the telemetry exports, to track the health of the
+/// telemetry pipeline itself, e.g. on a dashboard.
+pub fn export_stats() -> ExportStats {
+    ExportStats {
+        traces: TRACES.snapshot(),
+        logs: LOGS.snapshot(),
+        metrics: METRICS.snapshot(),
+    }
+}
+
+static TRACES: SignalCounters = SignalCounters::new(Signal::Traces);
+static LOGS: SignalCounters = SignalCounters::new(Signal::Logs);
+static METRICS: SignalCounters = SignalCounters::new(Signal::Metrics);
+
+struct SignalCounters {
+    signal: Signal,
+    exported: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    last: Mutex<LastExport>,
+}
+
+struct LastExport {
+    error: Option<String>,
+    error_time: Option<SystemTime>,
+    success_time: Option<SystemTime>,
+    state: ExporterState,
+    /// The number of consecutive exports with the same outcome as the last one.
+    streak: u32,
+    /// Whether the exports of the streak succeeded.
+    streak_succeeded: bool,
+}
+
+impl SignalCounters {
+    const fn new(signal: Signal) -> Self {
+        Self {
+            signal,
+            exported: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            last: Mutex::new(LastExport {
+                error: None,
+                error_time: None,
+                success_time: None,
+                state: ExporterState::Healthy,
+                streak: 0,
+                streak_succeeded: true,
+            }),
+        }
+    }
+
+    fn record<E: Display>(&self, count: usize, result: &Result<(), E>) {
+        let mut last = self.last.lock().unwrap();
+        match result {
+            Ok(()) => {
+                self.exported.fetch_add(count as u64, Ordering::Relaxed);
+                last.success_time = Some(SystemTime::now());
+            }
+            Err(err) => {
+                self.failed.fetch_add(count as u64, Ordering::Relaxed);
+                last.error = Some(err.to_string());
+                last.error_time = Some(SystemTime::now());
+            }
+        }
+        if last.streak_succeeded == result.is_ok() {
+            last.streak += 1;
+        } else {
+            last.streak_succeeded = result.is_ok();
+            last.streak = 1;
+        }
+        let state = match (last.state, result.is_ok()) {
+            (ExporterState::Healthy, false) if last.streak >= DEGRADED_AFTER_FAILURES => {
+                ExporterState::Degraded
+            }
+            (ExporterState::Degraded, true) if last.streak >= RECOVERED_AFTER_SUCCESSES => {
+                ExporterState::Healthy
+            }
+            (state, _) => state,
+        };
+        if state == last.state {
+            return;
+        }
+        last.state = state;
+        let change = ExporterStateChange {
+            signal: self.signal,
+            state,
+            last_error: last.error.clone(),
+        };
+        drop(last);
+        let callbacks = STATE_CHANGE_CALLBACKS.read().unwrap().clone();
+        for callback in callbacks {
+            callback(&change);
+        }
+    }
+
+    fn snapshot(&self) -> SignalExportStats {
+        let last = self.last.lock().unwrap();
+        SignalExportStats {
+            exported: self.exported.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            last_error: last.error.clone(),
+            last_error_time: last.error_time,
+            last_success_time: last.success_time,
+            state: last.state,
+        }
+    }
+}
+
+/// Install the global OpenTelemetry error handler, which counts the items dropped by
+/// the batch processors before printing the errors like the default handler.
+pub(crate) fn init_error_handler() -> anyhow::Result<()> {
+    global::set_error_handler(|error| {
+        // The batch processors report a full queue as a send error of their channel,
+        // once per dropped item.
+        match &error {
+            global::Error::Trace(TraceError::Other(err)) if is_channel_full(&**err) => {
+                TRACES.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            global::Error::Log(LogError::Other(err)) if is_channel_full(&**err) => {
+                LOGS.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+        eprintln!("OpenTelemetry error occurred. {}", error);
+    })?;
+    Ok(())
+}
+
+fn is_channel_full(error: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(
+        error.downcast_ref::<TrySendError>(),
+        Some(TrySendError::ChannelFull)
+    )
+}
+
+/// Count spans dropped before reaching the batch processor, see
+/// [`SpanQueueConfig`](crate::SpanQueueConfig).
+pub(crate) fn record_dropped_spans(count: usize) {
+    TRACES.dropped.fetch_add(count as u64, Ordering::Relaxed);
+}
+
+/// A span exporter recording the outcome of its exports into [`export_stats`].
+#[derive(Debug)]
+pub(crate) struct StatsSpanExporter(pub(crate) Box<dyn SpanExporter>);
+
+impl SpanExporter for StatsSpanExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let count = batch.len();
+        let export = self.0.export(batch);
+        Box::pin(async move {
+            let result = export.await;
+            TRACES.record(count, &result);
+            result
+        })
+    }
+
+    fn shutdown(&mut self) {
+        self.0.shutdown()
+    }
+
+    fn force_flush(&mut self) -> BoxFuture<'static, ExportResult> {
+        self.0.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.0.set_resource(resource)
+    }
+}
+
+/// A log exporter recording the outcome of its exports into [`export_stats`].
+#[derive(Debug)]
+pub(crate) struct StatsLogExporter(pub(crate) Box<dyn LogExporter>);
+
+#[async_trait]
+impl LogExporter for StatsLogExporter {
+    async fn export(&mut self, batch: LogBatch<'_>) -> LogResult<()> {
+        let count = batch.iter().count();
+        let result = self.0.export(batch).await;
+        LOGS.record(count, &result);
+        result
+    }
+
+    fn shutdown(&mut self) {
+        self.0.shutdown()
+    }
+
+    fn event_enabled(&self, level: Severity, target: &str, name: &str) -> bool {
+        self.0.event_enabled(level, target, name)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.0.set_resource(resource)
+    }
+}
+
+/// A push metrics exporter recording the outcome of its exports into [`export_stats`].
+pub(crate) struct StatsMetricsExporter(pub(crate) Box<dyn PushMetricsExporter>);
+
+impl Debug for StatsMetricsExporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("StatsMetricsExporter")
+            .field(&"<dyn PushMetricsExporter>")
+            .finish()
+    }
+}
+
+impl AggregationSelector for StatsMetricsExporter {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.0.aggregation(kind)
+    }
+}
+
+impl TemporalitySelector for StatsMetricsExporter {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.0.temporality(kind)
+    }
+}
+
+#[async_trait]
+impl PushMetricsExporter for StatsMetricsExporter {
+    async fn export(&self, metrics: &mut ResourceMetrics) -> MetricsResult<()> {
+        let count = metrics
+            .scope_metrics
+            .iter()
+            .map(|scope| scope.metrics.len())
+            .sum();
+        let result = self.0.export(metrics).await;
+        METRICS.record(count, &result);
+        result
+    }
+
+    async fn force_flush(&self) -> MetricsResult<()> {
+        self.0.force_flush().await
+    }
+
+    fn shutdown(&self) -> MetricsResult<()> {
+        self.0.shutdown()
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{logs, trace};
+
+use async_trait::async_trait;
+use futures_util::future::{join_all, BoxFuture};
+use opentelemetry::baggage::BaggageExt as _;
+use opentelemetry::logs::{AnyValue, LogRecord as _, LogResult};
+use opentelemetry::{Context, InstrumentationLibrary, Value};
+use opentelemetry_sdk::export::logs::{LogBatch, LogExporter};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::logs::{LogProcessor, LogRecord};
+use opentelemetry_sdk::Resource;
+use std::collections::HashMap;
+
+/// The span attribute, log record attribute or baggage entry naming the tenant.
+pub const TENANT_ID: &str = "tenant.id";
+
+/// Routes the spans and log records of some tenants to their own OTLP endpoints,
+/// keeping the telemetry of those tenants isolated from each other.
+///
+/// The tenant is read from the [`TENANT_ID`] span or log record attribute,
+/// or else from the [`TENANT_ID`] baggage entry when the span starts or the record is emitted.
+/// The telemetry of the other tenants, and without tenant, goes to the default exporters.
+#[derive(Debug, Clone, Default)]
+pub struct TenantRouter {
+    endpoints: HashMap<String, String>,
+}
+
+impl TenantRouter {
+    /// Create a new TenantRouter, routing no tenant.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route the telemetry of `tenant_id` to `otlp_endpoint`.
+    pub fn with_tenant(
+        mut self,
+        tenant_id: impl Into<String>,
+        otlp_endpoint: impl Into<String>,
+    ) -> Self {
+        self.endpoints
+            .insert(tenant_id.into(), otlp_endpoint.into());
+        self
+    }
+
+    /// The OTLP endpoints of the routed tenants.
+    pub(crate) fn endpoints(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.endpoints
+            .iter()
+            .map(|(tenant_id, endpoint)| (tenant_id.as_str(), endpoint.as_str()))
+    }
+
+    /// Wrap `default` into a span exporter routing the spans of the configured tenants.
+    pub(crate) fn span_exporter<E: SpanExporter>(
+        &self,
+        use_stdout_exporter: bool,
+        default: E,
+    ) -> anyhow::Result<TenantSpanExporter<E>> {
+        let mut tenants = HashMap::with_capacity(self.endpoints.len());
+        for (tenant_id, endpoint) in &self.endpoints {
+            let exporter = trace::build_span_exporter(use_stdout_exporter, Some(endpoint))?;
+            tenants.insert(tenant_id.clone(), exporter);
+        }
+        Ok(TenantSpanExporter { default, tenants })
+    }
+
+    /// Wrap `default` into a log exporter routing the log records of the configured tenants.
+    pub(crate) fn log_exporter<E: LogExporter>(
+        &self,
+        use_stdout_exporter: bool,
+        default: E,
+    ) -> anyhow::Result<TenantLogExporter<E>> {
+        let mut tenants = HashMap::with_capacity(self.endpoints.len());
+        for (tenant_id, endpoint) in &self.endpoints {
+            let exporter = logs::build_log_exporter(use_stdout_exporter, Some(endpoint))?;
+            tenants.insert(tenant_id.clone(), exporter);
+        }
+        Ok(TenantLogExporter { default, tenants })
+    }
+}
+
+impl From<HashMap<String, String>> for TenantRouter {
+    /// Create a TenantRouter from a map of tenant ids to OTLP endpoints.
+    fn from(endpoints: HashMap<String, String>) -> Self {
+        Self { endpoints }
+    }
+}
+
+/// A span exporter sending the spans of each routed tenant to the exporter of that tenant.
+#[derive(Debug)]
+pub(crate) struct TenantSpanExporter<E> {
+    default: E,
+    tenants: HashMap<String, Box<dyn SpanExporter>>,
+}
+
+impl<E: SpanExporter> SpanExporter for TenantSpanExporter<E> {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let mut routed: HashMap<String, Vec<SpanData>> = HashMap::new();
+        let mut rest = Vec::new();
+        for span in batch {
+            match span_tenant(&span).filter(|tenant| self.tenants.contains_key(*tenant)) {
+                Some(tenant) => routed.entry(tenant.to_owned()).or_default().push(span),
+                None => rest.push(span),
+            }
+        }
+        let mut exports = Vec::with_capacity(routed.len() + 1);
+        if !rest.is_empty() {
+            exports.push(self.default.export(rest));
+        }
+        for (tenant, spans) in routed {
+            if let Some(exporter) = self.tenants.get_mut(&tenant) {
+                exports.push(exporter.export(spans));
+            }
+        }
+        Box::pin(async move { join_all(exports).await.into_iter().collect() })
+    }
+
+    fn shutdown(&mut self) {
+        self.default.shutdown();
+        for exporter in self.tenants.values_mut() {
+            exporter.shutdown();
+        }
+    }
+
+    fn force_flush(&mut self) -> BoxFuture<'static, ExportResult> {
+        let mut flushes = vec![self.default.force_flush()];
+        flushes.extend(
+            self.tenants
+                .values_mut()
+                .map(|exporter| exporter.force_flush()),
+        );
+        Box::pin(async move { join_all(flushes).await.into_iter().collect() })
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.default.set_resource(resource);
+        for exporter in self.tenants.values_mut() {
+            exporter.set_resource(resource);
+        }
+    }
+}
+
+fn span_tenant(span: &SpanData) -> Option<&str> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == TENANT_ID)
+        .and_then(|kv| match &kv.value {
+            Value::String(value) => Some(value.as_str()),
+            _ => None,
+        })
+}
+
+/// A log exporter sending the records of each routed tenant to the exporter of that tenant.
+#[derive(Debug)]
+pub(crate) struct TenantLogExporter<E> {
+    default: E,
+    tenants: HashMap<String, Box<dyn LogExporter>>,
+}
+
+#[async_trait]
+impl<E: LogExporter> LogExporter for TenantLogExporter<E> {
+    async fn export(&mut self, batch: LogBatch<'_>) -> LogResult<()> {
+        let mut routed: HashMap<&str, Vec<(&LogRecord, &InstrumentationLibrary)>> = HashMap::new();
+        let mut rest = Vec::new();
+        for (record, library) in batch.iter() {
+            match record_tenant(record).filter(|tenant| self.tenants.contains_key(*tenant)) {
+                Some(tenant) => routed.entry(tenant).or_default().push((record, library)),
+                None => rest.push((record, library)),
+            }
+        }
+        let mut result = Ok(());
+        if !rest.is_empty() {
+            result = self.default.export(LogBatch::new(&rest)).await;
+        }
+        for (tenant, records) in routed {
+            if let Some(exporter) = self.tenants.get_mut(tenant) {
+                let exported = exporter.export(LogBatch::new(&records)).await;
+                result = result.and(exported);
+            }
+        }
+        result
+    }
+
+    fn shutdown(&mut self) {
+        self.default.shutdown();
+        for exporter in self.tenants.values_mut() {
+            exporter.shutdown();
+        }
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.default.set_resource(resource);
+        for exporter in self.tenants.values_mut() {
+            exporter.set_resource(resource);
+        }
+    }
+}
+
+fn record_tenant(record: &LogRecord) -> Option<&str> {
+    record
+        .attributes_iter()
+        .find(|(key, _)| key.as_str() == TENANT_ID)
+        .and_then(|(_, value)| match value {
+            AnyValue::String(value) => Some(value.as_str()),
+            _ => None,
+        })
+}
+
+/// A log processor copying the [`TENANT_ID`] baggage entry of the current context
+/// onto the emitted log records without a [`TENANT_ID`] attribute.
+#[derive(Debug)]
+pub(crate) struct TenantBaggageLogProcessor;
+
+impl LogProcessor for TenantBaggageLogProcessor {
+    fn emit(&self, data: &mut LogRecord, _instrumentation: &InstrumentationLibrary) {
+        if record_tenant(data).is_some() {
+            return;
+        }
+        let cx = Context::current();
+        if let Some(tenant) = cx.baggage().get(TENANT_ID) {
+            data.add_attribute(TENANT_ID, tenant.to_string());
+        }
+    }
+
+    fn force_flush(&self) -> LogResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> LogResult<()> {
+        Ok(())
+    }
+}
+
+/// The baggage entries to copy onto spans, extended with [`TENANT_ID`] for routing.
+pub(crate) fn with_tenant_baggage(baggage_span_attributes: Option<Vec<String>>) -> Vec<String> {
+    let mut keys = baggage_span_attributes.unwrap_or_default();
+    if !keys.iter().any(|key| key == TENANT_ID) {
+        keys.push(TENANT_ID.to_owned());
+    }
+    keys
+}
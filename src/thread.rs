@@ -0,0 +1,228 @@
+//! Trace context continuation across OS threads.
+//!
+//! Both the OpenTelemetry context and the `tracing` span are thread-local,
+//! so closures run on other threads lose them unless they are captured beforehand,
+//! as done by [`spawn_traced`], [`spawn_blocking_traced`] and [`ContextPreservingThreadPool`],
+//! and for rayon by `par_iter_traced` with the `rayon` feature.
+
+use opentelemetry::trace::{TraceContextExt as _, Tracer as _};
+use opentelemetry::Context;
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelRefIterator, Map, ParallelIterator};
+use std::fmt::Debug;
+use std::io;
+use std::thread::{Builder, JoinHandle};
+#[cfg(not(feature = "wasm"))]
+use tokio::task::JoinHandle as TaskJoinHandle;
+use tracing_opentelemetry::OpenTelemetrySpanExt as _;
+
+/// Spawn a thread named `name` running `f` within the current OpenTelemetry context
+/// and `tracing` span, so that its spans and logs belong to the current trace.
+pub fn spawn_traced<F, T>(name: impl Into<String>, f: F) -> io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Builder::new()
+        .name(name.into())
+        .spawn(in_current_context(f))
+}
+
+/// Run `f` on the blocking thread pool of the current Tokio runtime, like
+/// `tokio::task::spawn_blocking`, within the current OpenTelemetry context and `tracing` span.
+///
+/// `tokio::task::block_in_place` runs its closure on the calling thread, which keeps the
+/// context, so that it needs no such wrapper.
+///
+/// # Panics
+///
+/// Panics if called outside a Tokio runtime.
+#[cfg(not(feature = "wasm"))]
+pub fn spawn_blocking_traced<F, T>(f: F) -> TaskJoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(in_current_context(f))
+}
+
+/// Run a block on the blocking thread pool of the current Tokio runtime, within the current
+/// OpenTelemetry context and `tracing` span, see [`spawn_blocking_traced`].
+///
+/// ```ignore
+/// let digest = myotel::propagate_blocking!({
+///     info!("hashing within the request trace");
+///     hash(&payload)
+/// })
+/// .await?;
+/// ```
+#[cfg(not(feature = "wasm"))]
+#[macro_export]
+macro_rules! propagate_blocking {
+    ($body:block) => {
+        $crate::thread::spawn_blocking_traced(move || $body)
+    };
+}
+
+/// Wrap `f` so that it runs within the current OpenTelemetry context and `tracing` span,
+/// on whichever thread it is eventually called.
+pub fn in_current_context<F, T>(f: F) -> impl FnOnce() -> T + Send + 'static
+where
+    F: FnOnce() -> T + Send + 'static,
+{
+    let otel_cx = Context::current();
+    let span = tracing::Span::current();
+    move || {
+        let _guard = otel_cx.attach();
+        span.in_scope(f)
+    }
+}
+
+/// Wrap `f` so that each call runs within the current OpenTelemetry context and `tracing` span,
+/// and within a child span named `span_name` if any, e.g. for the closures of parallel iterators:
+///
+/// ```ignore
+/// let sums: Vec<u64> = data
+///     .par_chunks(1024)
+///     .map(traced_fn(Some("sum_chunk"), |chunk: &[u64]| chunk.iter().sum()))
+///     .collect();
+/// ```
+pub fn traced_fn<F, A, R>(span_name: Option<&'static str>, f: F) -> impl Fn(A) -> R + Send + Sync
+where
+    F: Fn(A) -> R + Send + Sync,
+{
+    let otel_cx = Context::current();
+    let span = tracing::Span::current();
+    move |arg| {
+        let _guard = match span_name {
+            Some(name) => {
+                let parent_cx = match otel_cx.has_active_span() {
+                    true => otel_cx.clone(),
+                    false => span.context(),
+                };
+                let child = crate::tracer().start_with_context(name, &parent_cx);
+                parent_cx.with_span(child).attach()
+            }
+            None => otel_cx.clone().attach(),
+        };
+        span.in_scope(|| f(arg))
+    }
+}
+
+/// A job run by a [`ContextPreservingThreadPool`].
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Wraps the spawn function of a thread pool, e.g. `rayon::spawn`,
+/// so that the jobs run within the OpenTelemetry context and `tracing` span
+/// current when they are submitted.
+///
+/// ```ignore
+/// let pool = ContextPreservingThreadPool::new(|job| rayon::spawn(job));
+/// pool.spawn(|| info!("still within the request trace"));
+/// ```
+#[derive(Clone)]
+pub struct ContextPreservingThreadPool<S> {
+    spawn: S,
+}
+
+impl<S: Fn(Job)> ContextPreservingThreadPool<S> {
+    /// Create a new ContextPreservingThreadPool submitting its jobs through `spawn`.
+    pub fn new(spawn: S) -> Self {
+        Self { spawn }
+    }
+
+    /// Submit `f` to the pool, to run within the current context.
+    pub fn spawn<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        (self.spawn)(Box::new(in_current_context(f)))
+    }
+}
+
+impl<S> Debug for ContextPreservingThreadPool<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContextPreservingThreadPool")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Parallel iteration within the current context, see [`TracedParIter`].
+#[cfg(feature = "rayon")]
+pub trait ParIterTracedExt<'data> {
+    /// The parallel iterator over the items.
+    type Iter: ParallelIterator;
+
+    /// Like `par_iter`, with the closures run within the current OpenTelemetry context and
+    /// `tracing` span on the worker threads.
+    ///
+    /// ```ignore
+    /// let hashes: Vec<_> = files.par_iter_traced().map(|file| hash(file)).collect();
+    /// ```
+    fn par_iter_traced(&'data self) -> TracedParIter<Self::Iter>;
+}
+
+#[cfg(feature = "rayon")]
+impl<'data, C: IntoParallelRefIterator<'data> + ?Sized> ParIterTracedExt<'data> for C {
+    type Iter = C::Iter;
+
+    fn par_iter_traced(&'data self) -> TracedParIter<Self::Iter> {
+        TracedParIter::new(self.par_iter())
+    }
+}
+
+/// A rayon parallel iterator whose closures run within the OpenTelemetry context and
+/// `tracing` span current when they are given, and within a child span per item if named,
+/// e.g. per chunk:
+///
+/// ```ignore
+/// let sums: Vec<u64> = TracedParIter::new(data.par_chunks(1024))
+///     .with_span("sum_chunk")
+///     .map(|chunk| chunk.iter().sum())
+///     .collect();
+/// ```
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone)]
+pub struct TracedParIter<I> {
+    inner: I,
+    span_name: Option<&'static str>,
+}
+
+#[cfg(feature = "rayon")]
+impl<I: ParallelIterator> TracedParIter<I> {
+    /// Wrap the parallel iterator `inner`.
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            span_name: None,
+        }
+    }
+
+    /// Open a child span named `span_name` around the processing of each item.
+    pub fn with_span(mut self, span_name: &'static str) -> Self {
+        self.span_name = Some(span_name);
+        self
+    }
+
+    /// Map each item with `f`, see [`ParallelIterator::map`].
+    pub fn map<F, R>(self, f: F) -> Map<I, impl Fn(I::Item) -> R + Send + Sync>
+    where
+        F: Fn(I::Item) -> R + Send + Sync,
+        R: Send,
+    {
+        self.inner.map(traced_fn(self.span_name, f))
+    }
+
+    /// Call `f` on each item, see [`ParallelIterator::for_each`].
+    pub fn for_each<F>(self, f: F)
+    where
+        F: Fn(I::Item) + Send + Sync,
+    {
+        self.inner.for_each(traced_fn(self.span_name, f))
+    }
+
+    /// The wrapped parallel iterator.
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
@@ -1,24 +1,54 @@
 pub use opentelemetry::trace::{
-    get_active_span, mark_span_as_active, FutureExt, Span as _, SpanBuilder, SpanContext, SpanId,
-    TraceContextExt, TraceFlags, TraceId, TraceState, Tracer as OtelTracer, TracerProvider as _,
-    WithContext,
+    get_active_span, mark_span_as_active, FutureExt, SamplingDecision, Span as _, SpanBuilder,
+    SpanContext, SpanId, SpanKind, SpanRef, TraceContextExt, TraceFlags, TraceId, TraceState,
+    Tracer as OtelTracer, TracerProvider as _, WithContext,
 };
 pub use opentelemetry::Context;
 pub use opentelemetry_sdk::trace::IdGenerator;
 pub use opentelemetry_sdk::trace::RandomIdGenerator;
+pub use opentelemetry_sdk::trace::{Sampler, ShouldSample};
 pub use opentelemetry_sdk::{
     trace::BatchConfig as BatchTraceConfig, trace::Config as TracerProviderConfig,
     trace::Span as TraceSpan, trace::Tracer,
 };
 
-use opentelemetry::global;
+use crate::reconfigure::{ReloadableSampler, ReloadableSpanExporter};
+use crate::sampling::{self, SamplingHook, SamplingRules};
+use crate::slo::SloSpanProcessor;
+use crate::span_name::SpanNameProcessor;
+use crate::span_queue::{QueueSpanExporter, QueueSpanProcessor, SpanQueue};
+use crate::stats::StatsSpanExporter;
+use crate::{
+    BaggageSpanProcessor, LatencyThresholds, ScopeConfig, SpanNameRewriter, SpanQueueConfig,
+    SpanSlos, TenantRouter, TreeExporter,
+};
+use futures_util::future::BoxFuture;
+use futures_util::{Sink, Stream};
+use opentelemetry::trace::Link;
+use opentelemetry::{global, Key, KeyValue, Value};
+use opentelemetry_otlp::{Protocol, WithExportConfig as _};
+use opentelemetry_sdk::export::trace::SpanExporter as SdkSpanExporter;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData};
+#[cfg(not(feature = "wasm"))]
 use opentelemetry_sdk::runtime::Tokio;
-use opentelemetry_sdk::{trace::BatchSpanProcessor, trace::TracerProvider};
+#[cfg(not(feature = "wasm"))]
+use opentelemetry_sdk::trace::BatchSpanProcessor;
+use opentelemetry_sdk::trace::Builder as TracerProviderBuilder;
+use opentelemetry_sdk::trace::TracerProvider;
 use opentelemetry_stdout::SpanExporter;
-use std::fmt::Debug;
+use pin_project_lite::pin_project;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{Debug, Write as _};
 use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, OnceLock};
+use std::task::{Context as TaskContext, Poll};
+use std::time::SystemTime;
 use sulid::SulidGenerator;
+use tracing_opentelemetry::{OpenTelemetrySpanExt as _, OtelData};
+use tracing_subscriber::registry::{LookupSpan as _, Registry};
 
 /// Re-export opentelemetry::trace;
 pub mod otel_trace {
@@ -30,9 +60,29 @@ pub mod otel_trace {
 /// The global `Tracer` singleton.
 static GLOBAL_TRACER: OnceLock<Tracer> = OnceLock::new();
 
+/// The provider of the global `Tracer`.
+pub(crate) static GLOBAL_TRACER_PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
 /// Returns the global &'static Tracer
+///
+/// Before OpenTelemetry is initialized, returns a tracer of a provider without processors,
+/// whose spans are dropped, see [`install_pre_init_buffer`](crate::install_pre_init_buffer)
+/// to queue the `tracing` spans emitted before that.
 pub fn tracer() -> &'static Tracer {
-    GLOBAL_TRACER.get().unwrap()
+    static NOOP_TRACER_PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+    static NOOP_TRACER: OnceLock<Tracer> = OnceLock::new();
+    try_tracer().unwrap_or_else(|| {
+        NOOP_TRACER.get_or_init(|| {
+            NOOP_TRACER_PROVIDER
+                .get_or_init(|| TracerProvider::builder().build())
+                .tracer(env!("CARGO_PKG_NAME"))
+        })
+    })
+}
+
+/// The global tracer, if OpenTelemetry is initialized.
+pub(crate) fn try_tracer() -> Option<&'static Tracer> {
+    GLOBAL_TRACER.get()
 }
 
 /// Returns the global Arc<Tracer>
@@ -41,51 +91,215 @@ pub fn arc_tracer() -> ArcTracer {
     tracer().into()
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn init_trace(
     service_name: String,
     service_version: String,
     use_stdout_exporter: bool,
+    otlp_endpoint: Option<&str>,
     batch_trace_config: Option<BatchTraceConfig>,
-    tracer_provider_config: TracerProviderConfig,
-) -> anyhow::Result<Tracer> {
-    let mut tracer_provider = TracerProvider::builder();
-    if use_stdout_exporter {
-        let span_exporter = SpanExporter::default();
-        if let Some(batch_trace_config) = batch_trace_config {
-            let batch = BatchSpanProcessor::builder(span_exporter, Tokio)
-                .with_batch_config(batch_trace_config)
-                .build();
-            tracer_provider = tracer_provider.with_span_processor(batch);
-        } else {
-            tracer_provider = tracer_provider.with_simple_exporter(span_exporter);
-        }
+    span_queue_config: Option<SpanQueueConfig>,
+    mut tracer_provider_config: TracerProviderConfig,
+    sampling_hook: Option<SamplingHook>,
+    sampling_rules: Option<SamplingRules>,
+    suppress_spans: Arc<[String]>,
+    mut export_options: SpanExportOptions,
+    baggage_span_attributes: Option<Vec<String>>,
+    span_name_rewriter: Option<SpanNameRewriter>,
+    span_slos: Option<SpanSlos>,
+    scope: &ScopeConfig,
+) -> anyhow::Result<(
+    Tracer,
+    TracerProvider,
+    ReloadableSampler,
+    ReloadableSpanExporter,
+)> {
+    let span_exporter = ReloadableSpanExporter::new(if export_options.stdout_tree {
+        // The tree exporter highlights the slow spans itself.
+        let tree_exporter = TreeExporter::new()
+            .with_ansi(export_options.ansi)
+            .with_latency_thresholds(export_options.latency_thresholds.take().unwrap_or_default());
+        Box::new(StatsSpanExporter(Box::new(tree_exporter)))
     } else {
-        let span_exporter = opentelemetry_otlp::new_exporter()
-            .tonic()
-            .build_span_exporter()?;
-        if let Some(batch_trace_config) = batch_trace_config {
-            let batch = BatchSpanProcessor::builder(span_exporter, Tokio)
-                .with_batch_config(batch_trace_config)
-                .build();
-            tracer_provider = tracer_provider.with_span_processor(batch);
-        } else {
-            tracer_provider = tracer_provider.with_simple_exporter(span_exporter);
+        match jaeger_agent_exporter(use_stdout_exporter, &export_options)? {
+            Some(jaeger_agent_exporter) => jaeger_agent_exporter,
+            None => build_span_exporter(use_stdout_exporter, otlp_endpoint)?,
         }
+    });
+    let mut tracer_provider = TracerProvider::builder();
+    if let Some(allowlist) = baggage_span_attributes {
+        tracer_provider = tracer_provider.with_span_processor(BaggageSpanProcessor::new(allowlist));
+    }
+    if let Some(span_name_rewriter) = span_name_rewriter {
+        tracer_provider =
+            tracer_provider.with_span_processor(SpanNameProcessor(span_name_rewriter));
     }
+    if let Some(span_slos) = span_slos {
+        tracer_provider = tracer_provider.with_span_processor(SloSpanProcessor(span_slos));
+    }
+    #[cfg(feature = "sentry")]
+    if let Some(sentry_config) = crate::sentry::sentry_config() {
+        tracer_provider =
+            tracer_provider.with_span_processor(crate::sentry::SentrySpanProcessor(sentry_config));
+    }
+    let tracer_provider = match export_options.tenant_router.clone() {
+        Some(tenant_router) => with_span_exporter(
+            tracer_provider,
+            TransformSpanExporter {
+                inner: tenant_router.span_exporter(use_stdout_exporter, span_exporter.clone())?,
+                options: export_options,
+            },
+            batch_trace_config,
+            span_queue_config,
+        ),
+        None => with_span_exporter(
+            tracer_provider,
+            TransformSpanExporter {
+                inner: span_exporter.clone(),
+                options: export_options,
+            },
+            batch_trace_config,
+            span_queue_config,
+        ),
+    };
+
+    let sampler = ReloadableSampler::new(std::mem::replace(
+        &mut tracer_provider_config.sampler,
+        Box::new(Sampler::AlwaysOn),
+    ));
+    tracer_provider_config.sampler = sampling::layered_sampler(
+        sampler.clone(),
+        sampling_rules,
+        suppress_spans,
+        sampling_hook,
+    );
 
     let tracer_provider: TracerProvider =
         tracer_provider.with_config(tracer_provider_config).build();
 
+    let mut tracer = tracer_provider
+        .tracer_builder(service_name)
+        .with_version(service_version)
+        .with_attributes(scope.attributes.clone());
+    if let Some(schema_url) = &scope.schema_url {
+        tracer = tracer.with_schema_url(schema_url.clone());
+    }
+    let tracer = tracer.build();
+
+    Ok((tracer, tracer_provider, sampler, span_exporter))
+}
+
+/// Register `tracer` and its provider as the global ones.
+pub(crate) fn register_trace(tracer: Tracer, tracer_provider: TracerProvider) {
+    global::set_tracer_provider(tracer_provider.clone());
+    let _ = GLOBAL_TRACER.set(tracer);
+    let _ = GLOBAL_TRACER_PROVIDER.set(tracer_provider);
+}
+
+/// Install a tracer whose spans are never sampled nor exported.
+/// The `opentelemetry` global tracer provider is left as the default no-op one.
+pub(crate) fn init_noop_trace(service_name: String, service_version: String) {
+    let tracer_provider = TracerProvider::builder()
+        .with_config(TracerProviderConfig::default().with_sampler(Sampler::AlwaysOff))
+        .build();
     let tracer = tracer_provider
         .tracer_builder(service_name)
         .with_version(service_version)
         .build();
+    let _ = GLOBAL_TRACER.set(tracer);
+}
+
+fn with_span_exporter<E: SdkSpanExporter + 'static>(
+    tracer_provider: TracerProviderBuilder,
+    span_exporter: E,
+    batch_trace_config: Option<BatchTraceConfig>,
+    span_queue_config: Option<SpanQueueConfig>,
+) -> TracerProviderBuilder {
+    #[cfg(not(feature = "wasm"))]
+    if let Some(batch_trace_config) = batch_trace_config {
+        let Some(span_queue_config) = span_queue_config else {
+            let batch = BatchSpanProcessor::builder(span_exporter, Tokio)
+                .with_batch_config(batch_trace_config)
+                .build();
+            return tracer_provider.with_span_processor(batch);
+        };
+        let queue = SpanQueue::new(span_queue_config);
+        let span_exporter = QueueSpanExporter {
+            inner: span_exporter,
+            queue: queue.clone(),
+        };
+        let batch = BatchSpanProcessor::builder(span_exporter, Tokio)
+            .with_batch_config(batch_trace_config)
+            .build();
+        return tracer_provider.with_span_processor(QueueSpanProcessor {
+            inner: batch,
+            queue,
+        });
+    }
+    // The batch processor needs the Tokio runtime, so the spans are exported as they end.
+    #[cfg(feature = "wasm")]
+    let _ = (batch_trace_config, span_queue_config);
+    tracer_provider.with_simple_exporter(span_exporter)
+}
 
-    global::set_tracer_provider(tracer_provider);
+/// Build the exporter to the Jaeger agent of `export_options`, unless the standard output is used.
+#[cfg(feature = "jaeger-compat")]
+fn jaeger_agent_exporter(
+    use_stdout_exporter: bool,
+    export_options: &SpanExportOptions,
+) -> anyhow::Result<Option<Box<dyn SdkSpanExporter>>> {
+    match &export_options.jaeger_agent_endpoint {
+        Some(endpoint) if !use_stdout_exporter => {
+            let exporter = crate::JaegerAgentExporter::new(endpoint.as_str())?;
+            Ok(Some(Box::new(StatsSpanExporter(Box::new(exporter)))))
+        }
+        _ => Ok(None),
+    }
+}
 
-    GLOBAL_TRACER.set(tracer.clone()).unwrap();
+#[cfg(not(feature = "jaeger-compat"))]
+fn jaeger_agent_exporter(
+    _use_stdout_exporter: bool,
+    _export_options: &SpanExportOptions,
+) -> anyhow::Result<Option<Box<dyn SdkSpanExporter>>> {
+    Ok(None)
+}
 
-    Ok(tracer)
+/// Build the span exporter, either to the standard output or to OTLP.
+/// The OTLP endpoint falls back to the `OTEL_EXPORTER_OTLP_*` environment variables.
+pub(crate) fn build_span_exporter(
+    use_stdout_exporter: bool,
+    otlp_endpoint: Option<&str>,
+) -> anyhow::Result<Box<dyn SdkSpanExporter>> {
+    if use_stdout_exporter {
+        return Ok(Box::new(StatsSpanExporter(Box::new(
+            SpanExporter::default(),
+        ))));
+    }
+    if let Some(http_client) = crate::transport::otlp_http_client() {
+        let mut builder = opentelemetry_otlp::new_exporter()
+            .http()
+            .with_http_client(http_client)
+            .with_protocol(Protocol::HttpJson);
+        if let Some(otlp_endpoint) = otlp_endpoint {
+            builder = builder.with_endpoint(otlp_endpoint);
+        }
+        return Ok(Box::new(StatsSpanExporter(Box::new(
+            builder.build_span_exporter()?,
+        ))));
+    }
+    #[cfg(feature = "wasm")]
+    return Err(crate::transport::no_otlp_http_client());
+    #[cfg(not(feature = "wasm"))]
+    {
+        let mut builder = crate::transport::otlp_tonic_builder();
+        if let Some(otlp_endpoint) = otlp_endpoint {
+            builder = builder.with_endpoint(otlp_endpoint);
+        }
+        Ok(Box::new(StatsSpanExporter(Box::new(
+            builder.build_span_exporter()?,
+        ))))
+    }
 }
 
 /// Create trace span customarily.
@@ -98,6 +312,243 @@ pub fn tracer_span(builder: SpanBuilder, parent_cx: Option<&Context>) -> TraceSp
     }
 }
 
+/// Start a span of kind `Server` named `name`, e.g. for an incoming HTTP request.
+/// The parent is `parent_cx`, typically extracted from the request headers,
+/// or the current context if `None`.
+pub fn start_server_span(
+    name: impl Into<Cow<'static, str>>,
+    parent_cx: Option<&Context>,
+) -> TraceSpan {
+    start_span_with_kind(name, SpanKind::Server, parent_cx)
+}
+
+/// Start a span of kind `Client` named `name`, e.g. for an outgoing HTTP request.
+/// The parent is `parent_cx`, or the current context if `None`.
+pub fn start_client_span(
+    name: impl Into<Cow<'static, str>>,
+    parent_cx: Option<&Context>,
+) -> TraceSpan {
+    start_span_with_kind(name, SpanKind::Client, parent_cx)
+}
+
+/// Start a span of kind `Consumer` named `name`, e.g. for processing a received message.
+/// The parent is `parent_cx`, or the current context if `None`.
+pub fn start_consumer_span(
+    name: impl Into<Cow<'static, str>>,
+    parent_cx: Option<&Context>,
+) -> TraceSpan {
+    start_span_with_kind(name, SpanKind::Consumer, parent_cx)
+}
+
+fn start_span_with_kind(
+    name: impl Into<Cow<'static, str>>,
+    kind: SpanKind,
+    parent_cx: Option<&Context>,
+) -> TraceSpan {
+    tracer_span(SpanBuilder::from_name(name).with_kind(kind), parent_cx)
+}
+
+/// The trace flags of the current trace: those of the active span,
+/// or else of the current `tracing` span.
+pub fn current_trace_flags() -> TraceFlags {
+    current_span_context().trace_flags()
+}
+
+/// The span context of the active span, or else of the current `tracing` span.
+fn current_span_context() -> SpanContext {
+    let cx = Context::current();
+    if cx.has_active_span() {
+        return cx.span().span_context().clone();
+    }
+    tracing::Span::current()
+        .context()
+        .span()
+        .span_context()
+        .clone()
+}
+
+/// The id of the current trace: that of the active span, or else of the current `tracing`
+/// span, `None` outside any trace.
+/// Useful to show a support code on error pages, or to attach it to error reports.
+pub fn current_trace_id() -> Option<TraceId> {
+    let span_context = current_span_context();
+    span_context.is_valid().then(|| span_context.trace_id())
+}
+
+/// The id of the current trace as 32 lowercase hexadecimal digits, see [`current_trace_id`].
+pub fn current_trace_id_hex() -> Option<String> {
+    current_trace_id().map(|trace_id| trace_id.to_string())
+}
+
+/// Whether the current trace is sampled, see [`current_trace_flags`].
+/// Useful to skip computing expensive debug attributes that would not be exported.
+pub fn is_current_trace_sampled() -> bool {
+    current_trace_flags().is_sampled()
+}
+
+/// Call `f` with the active span only if its trace is sampled,
+/// so that the attributes it computes are skipped for unsampled traces.
+pub fn record_if_sampled<F>(f: F)
+where
+    F: FnOnce(&SpanRef<'_>),
+{
+    get_active_span(|span| {
+        if span.span_context().is_sampled() {
+            f(&span)
+        }
+    })
+}
+
+/// Print the ancestry of the current `tracing` span to the standard output, from the root
+/// down, with the attributes and elapsed time of each span, for local debugging without
+/// a trace UI. Does nothing unless the spans are exported to the standard output, so that
+/// it can be left in code deployed with OTLP; see [`current_trace_dump`] to get the dump
+/// whatever the exporter.
+///
+/// ```text
+/// trace 4bf92f3577b34da6a3ce929d0e0e4736
+/// └─ handle_request (12.3ms) http.route=/users/:id
+///    └─ load_user (1.2ms) user.id=42
+/// ```
+pub fn debug_dump_current_trace() {
+    if !crate::snapshot::uses_stdout_exporter() {
+        return;
+    }
+    match current_trace_dump() {
+        Some(dump) => print!("{dump}"),
+        None => println!("no active span"),
+    }
+}
+
+/// The ancestry of the current `tracing` span as printed by [`debug_dump_current_trace`],
+/// read from the spans in memory whatever the exporter, e.g. to attach it to an error report.
+/// Returns `None` without an active span.
+pub fn current_trace_dump() -> Option<String> {
+    // Both need the current dispatcher, which cannot be re-entered from `get_default`.
+    let current = tracing::Span::current();
+    let trace_id = current.context().span().span_context().trace_id();
+    let id = current.id()?;
+    tracing::dispatcher::get_default(|dispatch| {
+        let span = dispatch
+            .downcast_ref::<Registry>()
+            .and_then(|registry| registry.span(&id))?;
+        let now = SystemTime::now();
+        let mut dump = format!("trace {trace_id}\n");
+        for (depth, span) in span.scope().from_root().enumerate() {
+            let extensions = span.extensions();
+            let builder = extensions.get::<OtelData>().map(|data| &data.builder);
+            let elapsed = builder
+                .and_then(|builder| builder.start_time)
+                .and_then(|start_time| now.duration_since(start_time).ok())
+                .unwrap_or_default();
+            let _ = write!(
+                dump,
+                "{}└─ {} ({elapsed:.1?})",
+                "   ".repeat(depth),
+                span.name()
+            );
+            let attributes = builder.and_then(|builder| builder.attributes.as_ref());
+            for kv in attributes.into_iter().flatten() {
+                let _ = write!(dump, " {}={}", kv.key, kv.value);
+            }
+            dump.push('\n');
+        }
+        Some(dump)
+    })
+}
+
+/// Record the source chain of `error` on the active span:
+/// each cause becomes an `exception` event with `exception.type` and `exception.message`,
+/// and the chain length is set as the `error.chain_depth` span attribute.
+///
+/// `exception.type` is the type name of the causes of the common standard library error types,
+/// found by downcasting, and `error` for the others, e.g. `anyhow!` messages.
+pub fn record_error_chain(error: &anyhow::Error) {
+    use opentelemetry::KeyValue;
+    use opentelemetry_semantic_conventions::trace::{EXCEPTION_MESSAGE, EXCEPTION_TYPE};
+
+    get_active_span(|span| {
+        let mut depth = 0;
+        for (index, cause) in error.chain().enumerate() {
+            span.add_event(
+                "exception",
+                vec![
+                    KeyValue::new(EXCEPTION_TYPE, error_type_name(cause)),
+                    KeyValue::new(EXCEPTION_MESSAGE, cause.to_string()),
+                    KeyValue::new("error.chain_depth", index as i64),
+                ],
+            );
+            depth = index + 1;
+        }
+        span.set_attribute(KeyValue::new("error.chain_depth", depth as i64));
+    });
+}
+
+/// The type name of `cause` if it is one of the common error types of the standard library,
+/// or `serde_json::Error` with the `serde` feature, found by downcasting; `"error"` otherwise.
+fn error_type_name(cause: &(dyn std::error::Error + 'static)) -> &'static str {
+    macro_rules! downcast {
+        ($($ty:ty),* $(,)?) => {
+            $(
+                if cause.is::<$ty>() {
+                    return std::any::type_name::<$ty>();
+                }
+            )*
+        };
+    }
+    downcast!(
+        std::io::Error,
+        std::fmt::Error,
+        std::num::ParseIntError,
+        std::num::ParseFloatError,
+        std::num::TryFromIntError,
+        std::str::ParseBoolError,
+        std::str::Utf8Error,
+        std::string::FromUtf8Error,
+        std::char::ParseCharError,
+        std::net::AddrParseError,
+        std::env::VarError,
+        std::time::SystemTimeError,
+        std::array::TryFromSliceError,
+        std::sync::mpsc::RecvError,
+    );
+    #[cfg(feature = "serde")]
+    downcast!(serde_json::Error);
+    "error"
+}
+
+/// Extension trait adding links to span builders, e.g. for fan-in operations such as
+/// a batch aggregation linking the spans of all the messages it consumes.
+///
+/// ```ignore
+/// let builder = SpanBuilder::from_name("aggregate")
+///     .with_links_from(messages.iter().map(|message| message.span_context.clone()));
+/// ```
+pub trait SpanBuilderExt {
+    /// Link the span to the active span, or else to the current `tracing` span, if any.
+    fn with_link_to_current(self) -> Self;
+
+    /// Link the span to each valid span context of `contexts`.
+    fn with_links_from(self, contexts: impl IntoIterator<Item = SpanContext>) -> Self;
+}
+
+impl SpanBuilderExt for SpanBuilder {
+    fn with_link_to_current(self) -> Self {
+        self.with_links_from([current_span_context()])
+    }
+
+    fn with_links_from(mut self, contexts: impl IntoIterator<Item = SpanContext>) -> Self {
+        let links = self.links.get_or_insert_with(Vec::new);
+        links.extend(
+            contexts
+                .into_iter()
+                .filter(SpanContext::is_valid)
+                .map(Link::with_context),
+        );
+        self
+    }
+}
+
 /// Extension trait allowing futures, streams, and sinks to be traced with a span.
 pub trait FutureTraceExt: FutureExt {
     /// Pass the span of opentelemetry to the current context of tracing.
@@ -109,6 +560,157 @@ pub trait FutureTraceExt: FutureExt {
 
 impl<T: FutureExt> FutureTraceExt for T {}
 
+/// Extension trait allowing each item of a stream to be traced.
+pub trait StreamTraceExt: Stream + Sized {
+    /// Yield each item together with a context holding a new child span named `name`,
+    /// whose parent is the current context at the time of this call.
+    /// The span ends once the yielded context (and its clones) are dropped,
+    /// so processing the item within that context traces it in its own span.
+    fn with_item_spans(self, name: impl Into<Cow<'static, str>>) -> ItemSpans<Self> {
+        ItemSpans {
+            inner: self,
+            name: name.into(),
+            parent_cx: Context::current(),
+        }
+    }
+
+    /// Add an event named `name` with the `item.index` attribute to the current span
+    /// (at the time of this call) for each yielded item.
+    fn with_item_events(self, name: impl Into<Cow<'static, str>>) -> ItemEvents<Self> {
+        ItemEvents {
+            inner: self,
+            name: name.into(),
+            otel_cx: Context::current(),
+            index: 0,
+        }
+    }
+}
+
+impl<T: Stream> StreamTraceExt for T {}
+
+/// Extension trait allowing each item sent into a sink to be traced.
+pub trait SinkTraceExt<I>: Sink<I> + Sized {
+    /// Add an event named `name` with the `item.index` attribute to the current span
+    /// (at the time of this call) for each item sent.
+    fn with_send_events(self, name: impl Into<Cow<'static, str>>) -> ItemEvents<Self> {
+        ItemEvents {
+            inner: self,
+            name: name.into(),
+            otel_cx: Context::current(),
+            index: 0,
+        }
+    }
+}
+
+impl<I, T: Sink<I>> SinkTraceExt<I> for T {}
+
+pin_project! {
+    /// A stream yielding each item with a context holding its own child span.
+    #[derive(Debug)]
+    pub struct ItemSpans<T> {
+        #[pin]
+        inner: T,
+        name: Cow<'static, str>,
+        parent_cx: Context,
+    }
+}
+
+impl<T: Stream> Stream for ItemSpans<T> {
+    type Item = (T::Item, Context);
+
+    fn poll_next(self: Pin<&mut Self>, task_cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let _guard = this.parent_cx.clone().attach();
+        this.inner.poll_next(task_cx).map(|item| {
+            item.map(|item| {
+                let span = tracer().start_with_context(this.name.clone(), this.parent_cx);
+                (item, this.parent_cx.with_span(span))
+            })
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+pin_project! {
+    /// A stream or sink adding an event to a span for each item passing through.
+    #[derive(Debug)]
+    pub struct ItemEvents<T> {
+        #[pin]
+        inner: T,
+        name: Cow<'static, str>,
+        otel_cx: Context,
+        index: i64,
+    }
+}
+
+impl<T: Stream> Stream for ItemEvents<T> {
+    type Item = T::Item;
+
+    fn poll_next(self: Pin<&mut Self>, task_cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let _guard = this.otel_cx.clone().attach();
+        let poll = this.inner.poll_next(task_cx);
+        if let Poll::Ready(Some(_)) = &poll {
+            add_item_event(this.otel_cx, this.name.clone(), this.index);
+        }
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<I, T: Sink<I>> Sink<I> for ItemEvents<T> {
+    type Error = T::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        task_cx: &mut TaskContext<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        let _guard = this.otel_cx.clone().attach();
+        this.inner.poll_ready(task_cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        let this = self.project();
+        let _guard = this.otel_cx.clone().attach();
+        this.inner.start_send(item)?;
+        add_item_event(this.otel_cx, this.name.clone(), this.index);
+        Ok(())
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        task_cx: &mut TaskContext<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        let _guard = this.otel_cx.clone().attach();
+        this.inner.poll_flush(task_cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        task_cx: &mut TaskContext<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        let _guard = this.otel_cx.clone().attach();
+        this.inner.poll_close(task_cx)
+    }
+}
+
+fn add_item_event(otel_cx: &Context, name: Cow<'static, str>, index: &mut i64) {
+    otel_cx.span().add_event(
+        name,
+        vec![opentelemetry::KeyValue::new("item.index", *index)],
+    );
+    *index += 1;
+}
+
 /// Generate trace_id using the Snowflake-inspired ULIDs (SULIDs),
 /// and generate span_id using a random number generator.
 pub struct MyIdGenerator {
@@ -135,6 +737,203 @@ impl Debug for MyIdGenerator {
     }
 }
 
+/// Generate trace_id and span_id from a seeded SplitMix64 sequence,
+/// so that the same seed yields the same ids across runs.
+#[derive(Debug)]
+pub struct DeterministicIdGenerator {
+    state: AtomicU64,
+}
+
+impl DeterministicIdGenerator {
+    /// Create a new DeterministicIdGenerator starting from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    fn next_u64(&self) -> u64 {
+        loop {
+            let mut z = self
+                .state
+                .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+                .wrapping_add(0x9E37_79B9_7F4A_7C15);
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^= z >> 31;
+            // Zero is an invalid id.
+            if z != 0 {
+                return z;
+            }
+        }
+    }
+}
+
+impl IdGenerator for DeterministicIdGenerator {
+    fn new_trace_id(&self) -> TraceId {
+        TraceId::from(((self.next_u64() as u128) << 64) | self.next_u64() as u128)
+    }
+
+    fn new_span_id(&self) -> SpanId {
+        SpanId::from(self.next_u64())
+    }
+}
+
+/// Options of the transformations applied to spans right before export, and of their routing.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SpanExportOptions {
+    /// Shift the span and event timestamps so that each span starts at the Unix epoch.
+    pub(crate) fixed_clock: bool,
+    /// Truncate string attribute values longer than this many bytes.
+    pub(crate) attribute_value_limit: Option<usize>,
+    /// Rename the span attributes, from the key to the value of the map.
+    pub(crate) field_renames: HashMap<String, String>,
+    /// Export the spans of some tenants to their own endpoints.
+    pub(crate) tenant_router: Option<TenantRouter>,
+    /// Print the spans to the standard output as trees, with a [`TreeExporter`].
+    pub(crate) stdout_tree: bool,
+    /// Color the spans printed to the standard output as trees.
+    pub(crate) ansi: bool,
+    /// Highlight the slow spans printed to the standard output with a `latency.threshold`
+    /// attribute, or in color when printed as trees.
+    pub(crate) latency_thresholds: Option<LatencyThresholds>,
+    /// Export the spans to this Jaeger agent rather than over OTLP.
+    #[cfg(feature = "jaeger-compat")]
+    pub(crate) jaeger_agent_endpoint: Option<String>,
+}
+
+/// Shift the timestamps of `span` and of its events so that it starts at the Unix epoch,
+/// keeping its duration and the offsets of its events.
+fn shift_to_epoch(span: &mut SpanData) {
+    let start = span.start_time;
+    let shift =
+        |time: SystemTime| SystemTime::UNIX_EPOCH + time.duration_since(start).unwrap_or_default();
+    span.end_time = shift(span.end_time);
+    for event in span.events.events.iter_mut() {
+        event.timestamp = shift(event.timestamp);
+    }
+    span.start_time = SystemTime::UNIX_EPOCH;
+}
+
+/// Applies the [`SpanExportOptions`] to spans before passing them on to the inner exporter.
+#[derive(Debug)]
+struct TransformSpanExporter<E> {
+    inner: E,
+    options: SpanExportOptions,
+}
+
+impl<E: SdkSpanExporter> SdkSpanExporter for TransformSpanExporter<E> {
+    fn export(&mut self, mut batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        for span in batch.iter_mut() {
+            if let Some(latency_thresholds) = &self.options.latency_thresholds {
+                let duration = span
+                    .end_time
+                    .duration_since(span.start_time)
+                    .unwrap_or_default();
+                if let Some(level) = latency_thresholds.exceeded(duration) {
+                    span.attributes
+                        .push(KeyValue::new(LATENCY_THRESHOLD_ATTRIBUTE, level.as_str()));
+                }
+            }
+            if self.options.fixed_clock {
+                shift_to_epoch(span);
+            }
+            if !self.options.field_renames.is_empty() {
+                rename_attributes(span, &self.options.field_renames);
+            }
+            if let Some(limit) = self.options.attribute_value_limit {
+                let truncated = truncate_attributes(&mut span.attributes, limit);
+                for event in span.events.events.iter_mut() {
+                    if truncate_attributes(&mut event.attributes, limit) {
+                        event
+                            .attributes
+                            .push(KeyValue::new(TRUNCATED_ATTRIBUTE, true));
+                    }
+                }
+                if truncated {
+                    span.attributes
+                        .push(KeyValue::new(TRUNCATED_ATTRIBUTE, true));
+                }
+            }
+        }
+        self.inner.export(batch)
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown()
+    }
+
+    fn force_flush(&mut self) -> BoxFuture<'static, ExportResult> {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource)
+    }
+}
+
+/// Rename the span attributes according to `renames`. Attributes renamed to `otel.name`
+/// or `otel.kind` set the span name or kind instead, like the fields of `tracing` spans.
+fn rename_attributes(span: &mut SpanData, renames: &HashMap<String, String>) {
+    span.attributes.retain_mut(|kv| {
+        let Some(target) = renames.get(kv.key.as_str()) else {
+            return true;
+        };
+        match target.as_str() {
+            "otel.name" => {
+                span.name = kv.value.as_str().into_owned().into();
+                false
+            }
+            "otel.kind" => match parse_span_kind(&kv.value.as_str()) {
+                Some(kind) => {
+                    span.span_kind = kind;
+                    false
+                }
+                None => true,
+            },
+            _ => {
+                kv.key = Key::new(target.clone());
+                true
+            }
+        }
+    });
+}
+
+fn parse_span_kind(kind: &str) -> Option<SpanKind> {
+    match kind.to_ascii_lowercase().as_str() {
+        "client" => Some(SpanKind::Client),
+        "server" => Some(SpanKind::Server),
+        "producer" => Some(SpanKind::Producer),
+        "consumer" => Some(SpanKind::Consumer),
+        "internal" => Some(SpanKind::Internal),
+        _ => None,
+    }
+}
+
+const TRUNCATED_ATTRIBUTE: &str = "truncated";
+
+const LATENCY_THRESHOLD_ATTRIBUTE: &str = "latency.threshold";
+
+/// Truncate the string values longer than `limit` bytes to `{prefix}...{truncated bytes}`.
+/// Returns whether any value was truncated.
+fn truncate_attributes(attributes: &mut [KeyValue], limit: usize) -> bool {
+    let mut truncated = false;
+    for kv in attributes.iter_mut() {
+        if let Value::String(value) = &kv.value {
+            let value = value.as_str();
+            if value.len() > limit {
+                let mut end = limit;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                kv.value = Value::from(format!("{}...{}", &value[..end], value.len() - end));
+                truncated = true;
+            }
+        }
+    }
+    truncated
+}
+
 /// ArcTracer implement: Tracer + Sync + Send + 'static
 pub struct ArcTracer(Arc<&'static Tracer>);
 
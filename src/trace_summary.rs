@@ -0,0 +1,238 @@
+use crate::log_buffer::ErrorStatusVisitor;
+use crate::log_sampling;
+use crate::logs::GLOBAL_LOGGER_PROVIDER;
+use crate::Instant;
+
+use opentelemetry::logs::{LogRecord as _, Logger as _, Severity};
+use opentelemetry::trace::{SpanId, TraceFlags, TraceId};
+use opentelemetry_sdk::logs::TraceContext;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+const TRACE_SUMMARY: &str = "trace.summary";
+const TRACE_ROOT_SPAN_NAME: &str = "trace.root_span.name";
+const TRACE_DURATION: &str = "trace.duration";
+const TRACE_SPAN_COUNT: &str = "trace.span_count";
+const TRACE_ERROR_COUNT: &str = "trace.error_count";
+const TRACE_SLOWEST_SPAN_NAME: &str = "trace.slowest_span.name";
+const TRACE_SLOWEST_SPAN_DURATION: &str = "trace.slowest_span.duration";
+
+/// Configuration of the trace summaries: when the root span of a trace closes, one INFO log
+/// record summarizes it with its duration, span and error counts, and its slowest child span,
+/// so that every trace stays searchable in the logs when only some of them are sampled, see
+/// [`InitConfig::with_trace_summary_config`](crate::InitConfig::with_trace_summary_config).
+///
+/// The summaries cover the `tracing` spans enabled by the span filter, whether their trace is
+/// sampled or not. A trace errors with an ERROR record, or a span whose `otel.status_code`
+/// field is `ERROR`.
+#[derive(Debug, Clone, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct TraceSummaryConfig {
+    /// The minimum duration of the traces to summarize. Defaults to zero: all of them.
+    min_duration: Duration,
+    /// The maximum number of traces tracked at once, beyond which new traces are not
+    /// summarized. Defaults to 1024.
+    max_traces: usize,
+}
+
+impl Default for TraceSummaryConfig {
+    fn default() -> Self {
+        Self {
+            min_duration: Duration::ZERO,
+            max_traces: 1024,
+        }
+    }
+}
+
+/// Emits a summary log record for each trace, according to a [`TraceSummaryConfig`].
+///
+/// It must come before the OpenTelemetry layer, which drops the OpenTelemetry data of the spans
+/// when they close, so that the root span can still be correlated with its trace.
+pub(crate) struct TraceSummaryLayer {
+    config: TraceSummaryConfig,
+    /// The traces in progress, by root span.
+    traces: Mutex<HashMap<Id, TraceSummary>>,
+}
+
+struct TraceSummary {
+    span_count: u64,
+    error_count: u64,
+    slowest: Option<(&'static str, Duration)>,
+}
+
+/// When a span was created.
+struct SpanStart(Instant);
+
+impl TraceSummaryLayer {
+    pub(crate) fn new(config: TraceSummaryConfig) -> Self {
+        Self {
+            config,
+            traces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count an error into the trace of the span `id`.
+    fn record_error<S>(&self, id: &Id, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let root = ctx
+            .span(id)
+            .and_then(|span| span.scope().from_root().next())
+            .map(|root| root.id());
+        if let Some(root) = root {
+            self.count_error(&root);
+        }
+    }
+
+    fn count_error(&self, root: &Id) {
+        if let Some(trace) = self.traces.lock().unwrap().get_mut(root) {
+            trace.error_count += 1;
+        }
+    }
+}
+
+impl<S> Layer<S> for TraceSummaryLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        span.extensions_mut().insert(SpanStart(Instant::now()));
+        let root = span.scope().from_root().next().map(|root| root.id());
+        {
+            let mut traces = self.traces.lock().unwrap();
+            match root {
+                Some(root) if root != *id => {
+                    if let Some(trace) = traces.get_mut(&root) {
+                        trace.span_count += 1;
+                    }
+                }
+                _ if traces.len() < self.config.max_traces => {
+                    traces.insert(
+                        id.clone(),
+                        TraceSummary {
+                            span_count: 1,
+                            error_count: 0,
+                            slowest: None,
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+        let mut visitor = ErrorStatusVisitor(false);
+        attrs.record(&mut visitor);
+        if visitor.0 {
+            self.record_error(id, &ctx);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let mut visitor = ErrorStatusVisitor(false);
+        values.record(&mut visitor);
+        if visitor.0 {
+            self.record_error(id, &ctx);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        let root = ctx
+            .event_scope(event)
+            .and_then(|scope| scope.from_root().next())
+            .map(|root| root.id());
+        if let Some(root) = root {
+            self.count_error(&root);
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(duration) = span
+            .extensions()
+            .get::<SpanStart>()
+            .map(|start| start.0.elapsed())
+        else {
+            return;
+        };
+        let Some(root) = span.scope().from_root().next() else {
+            return;
+        };
+        if root.id() != id {
+            let mut traces = self.traces.lock().unwrap();
+            if let Some(trace) = traces.get_mut(&root.id()) {
+                if trace
+                    .slowest
+                    .map_or(true, |(_, slowest)| duration > slowest)
+                {
+                    trace.slowest = Some((span.name(), duration));
+                }
+            }
+            return;
+        }
+        let Some(trace) = self.traces.lock().unwrap().remove(&id) else {
+            return;
+        };
+        if duration < self.config.min_duration {
+            return;
+        }
+        let Some(logger_provider) = GLOBAL_LOGGER_PROVIDER.get() else {
+            return;
+        };
+        let (trace_id, sampled) = log_sampling::span_trace(&span);
+        let span_id = span
+            .extensions()
+            .get::<OtelData>()
+            .and_then(|data| data.builder.span_id)
+            .unwrap_or(SpanId::INVALID);
+
+        let logger = crate::logs::scoped_logger(logger_provider, env!("CARGO_PKG_NAME"));
+        let mut record = logger.create_log_record();
+        record.set_severity_number(Severity::Info);
+        record.set_severity_text("INFO");
+        record.set_body(
+            format!(
+                "{}: {}ms, {} spans, {} errors",
+                span.name(),
+                duration.as_millis(),
+                trace.span_count,
+                trace.error_count
+            )
+            .into(),
+        );
+        record.add_attribute("event.name", TRACE_SUMMARY);
+        record.add_attribute(TRACE_ROOT_SPAN_NAME, span.name());
+        record.add_attribute(TRACE_DURATION, duration.as_secs_f64());
+        record.add_attribute(TRACE_SPAN_COUNT, trace.span_count as i64);
+        record.add_attribute(TRACE_ERROR_COUNT, trace.error_count as i64);
+        if let Some((name, duration)) = trace.slowest {
+            record.add_attribute(TRACE_SLOWEST_SPAN_NAME, name);
+            record.add_attribute(TRACE_SLOWEST_SPAN_DURATION, duration.as_secs_f64());
+        }
+        if trace_id != TraceId::INVALID {
+            record.trace_context = Some(TraceContext {
+                trace_id,
+                span_id,
+                trace_flags: Some(if sampled {
+                    TraceFlags::SAMPLED
+                } else {
+                    TraceFlags::default()
+                }),
+            });
+        }
+        logger.emit(record);
+    }
+}
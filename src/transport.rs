@@ -0,0 +1,322 @@
+pub use opentelemetry_http::HttpClient;
+
+use crate::Instant;
+
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt as _;
+use http::header::{HeaderName, HeaderValue, AUTHORIZATION};
+use opentelemetry_http::{Bytes, HttpError, Request, Response};
+#[cfg(not(feature = "wasm"))]
+use opentelemetry_otlp::TonicExporterBuilder;
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+#[cfg(not(feature = "wasm"))]
+use tokio::runtime::RuntimeFlavor;
+#[cfg(not(feature = "wasm"))]
+use tonic::metadata::{Ascii, MetadataKey, MetadataValue};
+#[cfg(not(feature = "wasm"))]
+use tonic::service::Interceptor;
+#[cfg(not(feature = "wasm"))]
+use tonic::Status;
+
+/// How long before its expiry a bearer token is refreshed.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+/// Re-export opentelemetry_http;
+pub mod otel_http {
+    pub use opentelemetry_http::*;
+}
+
+/// The user-provided HTTP transport of the OTLP exporters, if any.
+static OTLP_HTTP_CLIENT: RwLock<Option<SharedHttpClient>> = RwLock::new(None);
+
+pub(crate) fn set_otlp_http_client(client: Option<SharedHttpClient>) {
+    *OTLP_HTTP_CLIENT.write().unwrap() = client;
+}
+
+/// The HTTP transport the OTLP exporters must use instead of gRPC, if any.
+pub(crate) fn otlp_http_client() -> Option<SharedHttpClient> {
+    OTLP_HTTP_CLIENT.read().unwrap().clone()
+}
+
+/// The authentication of the OTLP exports over gRPC, if any.
+#[cfg(not(feature = "wasm"))]
+static OTLP_AUTH_PROVIDER: RwLock<Option<Arc<dyn AuthProvider>>> = RwLock::new(None);
+
+#[cfg(not(feature = "wasm"))]
+pub(crate) fn set_otlp_auth_provider(auth_provider: Option<Arc<dyn AuthProvider>>) {
+    *OTLP_AUTH_PROVIDER.write().unwrap() = auth_provider;
+}
+
+/// The builder of the OTLP exporters over gRPC, adding the headers of the auth provider if any
+/// to each export request as metadata.
+#[cfg(not(feature = "wasm"))]
+pub(crate) fn otlp_tonic_builder() -> TonicExporterBuilder {
+    let builder = opentelemetry_otlp::new_exporter().tonic();
+    match OTLP_AUTH_PROVIDER.read().unwrap().clone() {
+        Some(auth_provider) => builder.with_interceptor(AuthInterceptor(auth_provider)),
+        None => builder,
+    }
+}
+
+/// The error of building an OTLP exporter without the HTTP transport, which is the only one
+/// with the `wasm` feature.
+#[cfg(feature = "wasm")]
+pub(crate) fn no_otlp_http_client() -> anyhow::Error {
+    anyhow::anyhow!(
+        "the `wasm` feature exports over OTLP through the HTTP transport of \
+         `InitConfig::with_otlp_http_client` only"
+    )
+}
+
+/// A cloneable [`HttpClient`], as the OTLP exporter builders take one by value,
+/// adding the headers of the [`AuthProvider`] if any to each request.
+#[derive(Debug, Clone)]
+pub(crate) struct SharedHttpClient {
+    client: Arc<dyn HttpClient>,
+    auth_provider: Option<Arc<dyn AuthProvider>>,
+}
+
+impl SharedHttpClient {
+    pub(crate) fn new(client: impl HttpClient + 'static) -> Self {
+        Self {
+            client: Arc::new(client),
+            auth_provider: None,
+        }
+    }
+
+    pub(crate) fn with_auth_provider(
+        mut self,
+        auth_provider: Option<Arc<dyn AuthProvider>>,
+    ) -> Self {
+        self.auth_provider = auth_provider;
+        self
+    }
+}
+
+#[async_trait]
+impl HttpClient for SharedHttpClient {
+    async fn send(&self, mut request: Request<Vec<u8>>) -> Result<Response<Bytes>, HttpError> {
+        if let Some(auth_provider) = &self.auth_provider {
+            for (name, value) in auth_provider.headers().await? {
+                request.headers_mut().insert(name, value);
+            }
+        }
+        self.client.send(request).await
+    }
+}
+
+/// A tonic interceptor adding the headers of an [`AuthProvider`] to each export request as
+/// metadata. Interceptors are synchronous, so the headers are awaited in place: off the worker
+/// thread on a multi-threaded runtime, or else by blocking the current thread.
+#[cfg(not(feature = "wasm"))]
+#[derive(Debug, Clone)]
+struct AuthInterceptor(Arc<dyn AuthProvider>);
+
+#[cfg(not(feature = "wasm"))]
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: tonic::Request<()>) -> Result<tonic::Request<()>, Status> {
+        let headers = match tokio::runtime::Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| handle.block_on(self.0.headers()))
+            }
+            _ => futures_executor::block_on(self.0.headers()),
+        }
+        .map_err(|err| Status::unauthenticated(format!("no OTLP auth headers: {err:#}")))?;
+        for (name, value) in headers {
+            let key = MetadataKey::<Ascii>::from_bytes(name.as_str().as_bytes())
+                .map_err(|err| Status::internal(format!("invalid OTLP auth header: {err}")))?;
+            let mut value = MetadataValue::<Ascii>::try_from(value.as_bytes())
+                .map_err(|err| Status::internal(format!("invalid OTLP auth header: {err}")))?;
+            value.set_sensitive(true);
+            request.metadata_mut().insert(key, value);
+        }
+        Ok(request)
+    }
+}
+
+/// Provides the authentication headers of the OTLP exports, consulted before each export
+/// request so that expiring credentials are renewed in time,
+/// see [`InitConfig::with_otlp_auth_provider`](crate::InitConfig::with_otlp_auth_provider).
+///
+/// An export fails, and is reported to the global error handler, if its headers cannot be
+/// provided.
+#[async_trait]
+pub trait AuthProvider: Debug + Send + Sync {
+    /// The headers to add to the next export request.
+    async fn headers(&self) -> anyhow::Result<Vec<(HeaderName, HeaderValue)>>;
+}
+
+/// An [`AuthProvider`] sending a static API key in a header.
+#[derive(Clone)]
+pub struct ApiKeyAuthProvider {
+    header: HeaderName,
+    value: HeaderValue,
+}
+
+impl ApiKeyAuthProvider {
+    /// Create a new ApiKeyAuthProvider sending `api_key` in the `header` header,
+    /// e.g. `x-api-key`.
+    pub fn new(header: &str, api_key: &str) -> anyhow::Result<Self> {
+        let mut value = HeaderValue::from_str(api_key)?;
+        value.set_sensitive(true);
+        Ok(Self {
+            header: HeaderName::from_bytes(header.as_bytes())?,
+            value,
+        })
+    }
+}
+
+impl Debug for ApiKeyAuthProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKeyAuthProvider")
+            .field("header", &self.header)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuthProvider for ApiKeyAuthProvider {
+    async fn headers(&self) -> anyhow::Result<Vec<(HeaderName, HeaderValue)>> {
+        Ok(vec![(self.header.clone(), self.value.clone())])
+    }
+}
+
+/// An [`AuthProvider`] sending a bearer token in the `Authorization` header, obtained from
+/// an async callback, e.g. an OAuth client-credentials grant, which returns the token along
+/// with how long it is valid.
+///
+/// The token is cached, and refreshed 30 seconds before it expires.
+///
+/// ```ignore
+/// let auth = BearerTokenAuthProvider::new(move || {
+///     let oauth = oauth.clone();
+///     async move {
+///         let grant = oauth.client_credentials().await?;
+///         Ok((grant.access_token, grant.expires_in))
+///     }
+/// });
+/// ```
+pub struct BearerTokenAuthProvider {
+    refresh: Box<dyn Fn() -> BoxFuture<'static, anyhow::Result<(String, Duration)>> + Send + Sync>,
+    token: Mutex<Option<(HeaderValue, Instant)>>,
+}
+
+impl BearerTokenAuthProvider {
+    /// Create a new BearerTokenAuthProvider obtaining its tokens from `refresh`.
+    pub fn new<F, Fut>(refresh: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<(String, Duration)>> + Send + 'static,
+    {
+        Self {
+            refresh: Box::new(move || refresh().boxed()),
+            token: Mutex::new(None),
+        }
+    }
+}
+
+impl Debug for BearerTokenAuthProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BearerTokenAuthProvider")
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuthProvider for BearerTokenAuthProvider {
+    async fn headers(&self) -> anyhow::Result<Vec<(HeaderName, HeaderValue)>> {
+        let cached = self.token.lock().unwrap().clone();
+        let value = match cached {
+            Some((value, refresh_at)) if Instant::now() < refresh_at => value,
+            _ => {
+                let (token, valid_for) = (self.refresh)().await?;
+                let mut value = HeaderValue::from_str(&format!("Bearer {token}"))?;
+                value.set_sensitive(true);
+                let refresh_at = Instant::now() + valid_for.saturating_sub(TOKEN_REFRESH_MARGIN);
+                *self.token.lock().unwrap() = Some((value.clone(), refresh_at));
+                value
+            }
+        };
+        Ok(vec![(AUTHORIZATION, value)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_executor::block_on;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// An HTTP client recording the headers of the requests it is sent.
+    #[derive(Debug, Default)]
+    struct RecordingClient(Arc<Mutex<Vec<http::HeaderMap>>>);
+
+    #[async_trait]
+    impl HttpClient for RecordingClient {
+        async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Bytes>, HttpError> {
+            self.0.lock().unwrap().push(request.headers().clone());
+            Ok(Response::new(Bytes::new()))
+        }
+    }
+
+    fn bearer_provider(valid_for: Duration) -> (BearerTokenAuthProvider, Arc<AtomicUsize>) {
+        let refreshes = Arc::new(AtomicUsize::new(0));
+        let counter = refreshes.clone();
+        let provider = BearerTokenAuthProvider::new(move || {
+            let refresh = counter.fetch_add(1, Ordering::Relaxed) + 1;
+            async move { Ok((format!("token-{refresh}"), valid_for)) }
+        });
+        (provider, refreshes)
+    }
+
+    #[test]
+    fn api_key_is_sensitive_and_not_printed() {
+        let provider = ApiKeyAuthProvider::new("x-api-key", "s3cr3t").unwrap();
+        let headers = block_on(provider.headers()).unwrap();
+        assert_eq!(headers.len(), 1);
+        let (name, value) = &headers[0];
+        assert_eq!(name, "x-api-key");
+        assert_eq!(value, "s3cr3t");
+        assert!(value.is_sensitive());
+        assert!(!format!("{provider:?}").contains("s3cr3t"));
+    }
+
+    #[test]
+    fn bearer_token_is_cached_until_its_refresh_margin() {
+        let (provider, refreshes) = bearer_provider(Duration::from_secs(3600));
+        for _ in 0..3 {
+            let headers = block_on(provider.headers()).unwrap();
+            assert_eq!(headers.len(), 1);
+            assert_eq!(headers[0].0, AUTHORIZATION);
+            assert_eq!(headers[0].1, "Bearer token-1");
+        }
+        assert_eq!(refreshes.load(Ordering::Relaxed), 1);
+
+        // Valid for less than the margin, so refreshed on every request.
+        let (provider, refreshes) = bearer_provider(TOKEN_REFRESH_MARGIN / 2);
+        block_on(provider.headers()).unwrap();
+        let headers = block_on(provider.headers()).unwrap();
+        assert_eq!(headers[0].1, "Bearer token-2");
+        assert_eq!(refreshes.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn shared_client_adds_the_auth_headers() {
+        let client = RecordingClient::default();
+        let sent = client.0.clone();
+        let auth_provider = ApiKeyAuthProvider::new("x-api-key", "s3cr3t").unwrap();
+        let shared =
+            SharedHttpClient::new(client).with_auth_provider(Some(Arc::new(auth_provider)));
+        let plain = SharedHttpClient::new(RecordingClient(sent.clone()));
+        block_on(shared.send(Request::new(Vec::new()))).unwrap();
+        block_on(plain.send(Request::new(Vec::new()))).unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0]["x-api-key"], "s3cr3t");
+        assert!(!sent[1].contains_key("x-api-key"));
+    }
+}
@@ -0,0 +1,248 @@
+use futures_util::future::{ready, BoxFuture};
+use opentelemetry::trace::{SpanId, Status, TraceId};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::time::Duration;
+
+/// The maximum number of traces buffered while waiting for their root span.
+/// Beyond it, the oldest trace is printed incomplete.
+const MAX_BUFFERED_TRACES: usize = 1024;
+
+/// Latency thresholds above which the spans printed to the standard output are highlighted,
+/// see [`InitConfig::with_latency_thresholds`](crate::InitConfig::with_latency_thresholds).
+///
+/// ```ignore
+/// let thresholds = LatencyThresholds::new()
+///     .warn_over(Duration::from_millis(100))
+///     .error_over(Duration::from_secs(1));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatencyThresholds {
+    warn: Option<Duration>,
+    error: Option<Duration>,
+}
+
+impl LatencyThresholds {
+    /// Create new LatencyThresholds, highlighting no span.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Highlight the spans lasting longer than `threshold` as warnings.
+    pub fn warn_over(mut self, threshold: Duration) -> Self {
+        self.warn = Some(threshold);
+        self
+    }
+
+    /// Highlight the spans lasting longer than `threshold` as errors.
+    pub fn error_over(mut self, threshold: Duration) -> Self {
+        self.error = Some(threshold);
+        self
+    }
+
+    /// The highest level whose threshold `duration` exceeds, if any.
+    pub(crate) fn exceeded(&self, duration: Duration) -> Option<LatencyLevel> {
+        if self.error.is_some_and(|threshold| duration > threshold) {
+            Some(LatencyLevel::Error)
+        } else if self.warn.is_some_and(|threshold| duration > threshold) {
+            Some(LatencyLevel::Warn)
+        } else {
+            None
+        }
+    }
+}
+
+/// The level of a span exceeding [`LatencyThresholds`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LatencyLevel {
+    Warn,
+    Error,
+}
+
+impl LatencyLevel {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            LatencyLevel::Warn => "warn",
+            LatencyLevel::Error => "error",
+        }
+    }
+}
+
+/// A span exporter printing each trace to the standard output as a tree of spans,
+/// with their durations and error markers, once its root span ends.
+///
+/// ```text
+/// trace 4bf92f3577b34da6a3ce929d0e0e4736
+/// GET /users/:id (12.3ms)
+/// ├─ load_user (1.2ms)
+/// │  └─ SELECT (0.5ms) ✗ connection reset
+/// └─ render (2.0ms)
+/// ```
+///
+/// Installed by `init_otel` with [`ExporterKind::StdoutTree`](crate::ExporterKind::StdoutTree).
+/// The traces whose root span is never exported here, such as those continuing a remote
+/// trace, are printed on shutdown.
+#[derive(Debug)]
+pub struct TreeExporter {
+    traces: HashMap<TraceId, Vec<SpanData>>,
+    order: Vec<TraceId>,
+    latency_thresholds: LatencyThresholds,
+    ansi: bool,
+}
+
+impl TreeExporter {
+    /// Create a new TreeExporter.
+    pub fn new() -> Self {
+        Self {
+            traces: HashMap::new(),
+            order: Vec::new(),
+            latency_thresholds: LatencyThresholds::default(),
+            ansi: true,
+        }
+    }
+
+    /// Whether the slow spans are colored with ANSI escape codes. Enabled by default.
+    pub fn with_ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    /// Highlight the spans slower than `latency_thresholds`, in yellow or red if colored.
+    pub fn with_latency_thresholds(mut self, latency_thresholds: LatencyThresholds) -> Self {
+        self.latency_thresholds = latency_thresholds;
+        self
+    }
+
+    fn highlight(&self) -> Highlight {
+        Highlight {
+            latency_thresholds: self.latency_thresholds,
+            ansi: self.ansi,
+        }
+    }
+
+    fn print_trace(&mut self, trace_id: TraceId) {
+        self.order.retain(|id| *id != trace_id);
+        if let Some(spans) = self.traces.remove(&trace_id) {
+            let _ = std::io::stdout()
+                .lock()
+                .write_all(format_trace(trace_id, spans, &self.highlight()).as_bytes());
+        }
+    }
+}
+
+impl Default for TreeExporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpanExporter for TreeExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let mut completed = Vec::new();
+        for span in batch {
+            let trace_id = span.span_context.trace_id();
+            if span.parent_span_id == SpanId::INVALID {
+                completed.push(trace_id);
+            }
+            if !self.traces.contains_key(&trace_id) {
+                self.order.push(trace_id);
+            }
+            self.traces.entry(trace_id).or_default().push(span);
+        }
+        for trace_id in completed {
+            self.print_trace(trace_id);
+        }
+        while self.order.len() > MAX_BUFFERED_TRACES {
+            self.print_trace(self.order[0]);
+        }
+        Box::pin(ready(Ok(())))
+    }
+
+    fn shutdown(&mut self) {
+        for trace_id in self.order.clone() {
+            self.print_trace(trace_id);
+        }
+    }
+}
+
+/// How the slow spans are highlighted.
+struct Highlight {
+    latency_thresholds: LatencyThresholds,
+    ansi: bool,
+}
+
+/// Format the spans of a trace as a tree, children sorted by start time.
+/// The spans whose parent is missing are printed as roots.
+fn format_trace(trace_id: TraceId, mut spans: Vec<SpanData>, highlight: &Highlight) -> String {
+    spans.sort_by_key(|span| span.start_time);
+    let span_ids: Vec<SpanId> = spans
+        .iter()
+        .map(|span| span.span_context.span_id())
+        .collect();
+    let mut children: HashMap<SpanId, Vec<&SpanData>> = HashMap::new();
+    let mut roots = Vec::new();
+    for span in &spans {
+        if span_ids.contains(&span.parent_span_id) {
+            children.entry(span.parent_span_id).or_default().push(span);
+        } else {
+            roots.push(span);
+        }
+    }
+    let mut tree = format!("trace {trace_id}\n");
+    for span in roots {
+        format_span(&mut tree, span, &children, highlight, "", None);
+    }
+    tree
+}
+
+/// Format `span` and its descendants, `is_last` being `None` for roots.
+fn format_span(
+    tree: &mut String,
+    span: &SpanData,
+    children: &HashMap<SpanId, Vec<&SpanData>>,
+    highlight: &Highlight,
+    prefix: &str,
+    is_last: Option<bool>,
+) {
+    let (branch, child_prefix) = match is_last {
+        None => ("", prefix.to_owned()),
+        Some(true) => ("└─ ", format!("{prefix}   ")),
+        Some(false) => ("├─ ", format!("{prefix}│  ")),
+    };
+    let duration = span
+        .end_time
+        .duration_since(span.start_time)
+        .unwrap_or_default();
+    let _ = write!(tree, "{prefix}{branch}{} ", span.name);
+    let (color, marker) = match highlight.latency_thresholds.exceeded(duration) {
+        Some(LatencyLevel::Warn) => ("\x1b[33m", " ⚠ slow"),
+        Some(LatencyLevel::Error) => ("\x1b[31m", " ⚠ very slow"),
+        None => ("", ""),
+    };
+    if highlight.ansi && !color.is_empty() {
+        let _ = write!(tree, "{color}({duration:.1?}){marker}\x1b[0m");
+    } else {
+        let _ = write!(tree, "({duration:.1?}){marker}");
+    }
+    if let Status::Error { description } = &span.status {
+        let _ = write!(tree, " ✗ {description}");
+    }
+    tree.push('\n');
+    let span_children = children
+        .get(&span.span_context.span_id())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for (index, child) in span_children.iter().enumerate() {
+        let is_last = index + 1 == span_children.len();
+        format_span(
+            tree,
+            child,
+            children,
+            highlight,
+            &child_prefix,
+            Some(is_last),
+        );
+    }
+}
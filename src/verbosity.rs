@@ -0,0 +1,115 @@
+use crate::force_sample;
+
+use opentelemetry::baggage::BaggageExt as _;
+use opentelemetry::Context;
+use std::str::FromStr as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::Level;
+use tracing_subscriber::filter::LevelFilter;
+
+/// The baggage entry raising the log verbosity of a request, e.g.
+/// `telemetry.level=debug;expires=1735689600`.
+pub const TELEMETRY_LEVEL: &str = "telemetry.level";
+
+/// Configuration of the per-request log verbosity, see
+/// [`InitConfig::with_baggage_verbosity_config`](crate::InitConfig::with_baggage_verbosity_config).
+///
+/// A request whose baggage carries the [`TELEMETRY_LEVEL`] entry, e.g.
+/// `telemetry.level=debug;expires=1735689600`, has its log records exported down to that
+/// level, even if filtered out otherwise. As baggage propagates, every service touching the
+/// request does so, for correlated troubleshooting.
+///
+/// So that a stray entry cannot flood the pipeline, the entry must expire: its `expires`
+/// property is the Unix time in seconds after which it is ignored, and which may be at most
+/// `max_ttl` from now. The records exported only because of the entry are also rate limited.
+#[derive(Debug, Clone, getset2::WithSetters)]
+#[getset(set_with = "pub")]
+pub struct BaggageVerbosityConfig {
+    /// The most verbose level an entry may raise the verbosity to. Defaults to `DEBUG`.
+    max_level: Level,
+    /// The longest time an entry may be honored for. Defaults to 15 minutes.
+    max_ttl: Duration,
+    /// The maximum number of log records exported per second only because of the entries,
+    /// beyond which they are dropped. Defaults to 100.
+    max_records_per_second: u64,
+}
+
+impl Default for BaggageVerbosityConfig {
+    fn default() -> Self {
+        Self {
+            max_level: Level::DEBUG,
+            max_ttl: Duration::from_secs(15 * 60),
+            max_records_per_second: 100,
+        }
+    }
+}
+
+static BAGGAGE_VERBOSITY_CONFIG: RwLock<Option<BaggageVerbosityConfig>> = RwLock::new(None);
+
+/// The second of the current rate limiting window, and the records exported in it.
+static WINDOW_SECOND: AtomicU64 = AtomicU64::new(0);
+static WINDOW_RECORDS: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn set_config(config: Option<BaggageVerbosityConfig>) {
+    *BAGGAGE_VERBOSITY_CONFIG.write().unwrap() = config;
+}
+
+/// The most verbose level the log records of a request may be raised to, by a force-sampling
+/// header or the baggage, `OFF` if neither is configured.
+pub(crate) fn max_request_level(
+    force_sample_level: Option<Level>,
+    baggage_config: Option<&BaggageVerbosityConfig>,
+) -> LevelFilter {
+    let baggage_level = baggage_config.map(|config| config.max_level);
+    match force_sample_level.max(baggage_level) {
+        Some(level) => LevelFilter::from_level(level),
+        None => LevelFilter::OFF,
+    }
+}
+
+/// Whether a log record of `level`, filtered out otherwise, is exported because the current
+/// request raised the verbosity.
+pub(crate) fn request_enabled(level: &Level) -> bool {
+    if force_sample::forced_log_level().is_some_and(|forced| *level <= forced) {
+        return true;
+    }
+    let config = BAGGAGE_VERBOSITY_CONFIG.read().unwrap();
+    let Some(config) = config.as_ref() else {
+        return false;
+    };
+    baggage_level(config).is_some_and(|baggage| *level <= baggage) && admit(config)
+}
+
+/// The level of the unexpired [`TELEMETRY_LEVEL`] entry of the current baggage, if any.
+fn baggage_level(config: &BaggageVerbosityConfig) -> Option<Level> {
+    Context::map_current(|cx| {
+        let (value, metadata) = cx.baggage().get_with_metadata(TELEMETRY_LEVEL)?;
+        let expires = metadata
+            .as_str()
+            .split(';')
+            .find_map(|property| property.trim().strip_prefix("expires="))?
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        if expires < now || expires > now + config.max_ttl.as_secs() {
+            return None;
+        }
+        let level = Level::from_str(value.as_str().trim()).ok()?;
+        Some(level.min(config.max_level))
+    })
+}
+
+/// Whether the rate limit admits one more record.
+fn admit(config: &BaggageVerbosityConfig) -> bool {
+    let second = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if WINDOW_SECOND.swap(second, Ordering::Relaxed) != second {
+        WINDOW_RECORDS.store(0, Ordering::Relaxed);
+    }
+    WINDOW_RECORDS.fetch_add(1, Ordering::Relaxed) < config.max_records_per_second
+}